@@ -0,0 +1,424 @@
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Component, Path};
+
+use chrono::{DateTime, FixedOffset, NaiveDate};
+use flate2::read::GzDecoder;
+use sha2::{Digest, Sha256};
+use tar::EntryType;
+use zip::ZipArchive;
+
+use crate::common;
+use crate::errors::ServiceError;
+use crate::snapshot::{self, File, Metadata, Snapshot};
+
+const S_IFMT: u32 = 0o170000;
+const S_IFLNK: u32 = 0o120000;
+
+#[derive(Debug, PartialEq)]
+pub enum ArchiveKind {
+    Tar,
+    TarGz,
+    TarZst,
+    Zip,
+}
+
+struct Entry {
+    components: Vec<String>,
+    is_dir: bool,
+    size: u64,
+    meta: Metadata,
+}
+
+pub fn detect_kind(path: &Path) -> Option<ArchiveKind> {
+    if !path.is_file() {
+        return None;
+    }
+
+    let name = path.file_name()?.to_str()?.to_lowercase();
+
+    if name.ends_with(".tar") {
+        Some(ArchiveKind::Tar)
+    } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        Some(ArchiveKind::TarGz)
+    } else if name.ends_with(".tar.zst") || name.ends_with(".tzst") {
+        Some(ArchiveKind::TarZst)
+    } else if name.ends_with(".zip") {
+        Some(ArchiveKind::Zip)
+    } else {
+        None
+    }
+}
+
+/// Builds a snapshot from the entries of an archive without extracting it.
+/// Content digests are computed when `SNAPSHOT_DIGESTS` is set to `1`.
+pub fn read_archive(
+    file_path: &Path,
+    kind: &ArchiveKind,
+    version: i32,
+    date: DateTime<FixedOffset>,
+    path: String,
+) -> Result<Snapshot, ServiceError> {
+    let file = fs::File::open(file_path).or(Err(ServiceError::FailedToReadArchive))?;
+    let digests = common::digests_enabled();
+
+    let entries = match kind {
+        ArchiveKind::Tar => read_tar(file, digests),
+        ArchiveKind::TarGz => read_tar(GzDecoder::new(file), digests),
+        ArchiveKind::TarZst => match zstd::Decoder::new(file) {
+            Ok(decoder) => read_tar(decoder, digests),
+            Err(_) => Err(ServiceError::FailedToReadArchive),
+        },
+        ArchiveKind::Zip => read_zip(file, digests),
+    }?;
+
+    let mut snapshot = Snapshot::create(version, date, path);
+    for entry in entries {
+        insert_entry(&mut snapshot, entry);
+    }
+
+    let size = snapshot::fill_sizes(&mut snapshot.files, &mut snapshot.dirs);
+    snapshot.set_size(size);
+
+    Ok(snapshot)
+}
+
+fn read_tar<R: Read>(reader: R, digests: bool) -> Result<Vec<Entry>, ServiceError> {
+    let mut archive = tar::Archive::new(reader);
+    let mut entries = Vec::new();
+
+    let tar_entries = archive
+        .entries()
+        .or(Err(ServiceError::FailedToReadArchive))?;
+
+    for tar_entry in tar_entries {
+        let mut tar_entry = tar_entry.or(Err(ServiceError::FailedToReadArchive))?;
+        let header = tar_entry.header();
+        let entry_type = header.entry_type();
+
+        // Extension records describe other entries, and devices and fifos
+        // have no content; neither is a file of the tree. Sparse files are
+        // kept as regular files.
+        if matches!(
+            entry_type,
+            EntryType::XGlobalHeader
+                | EntryType::XHeader
+                | EntryType::GNULongName
+                | EntryType::GNULongLink
+                | EntryType::Char
+                | EntryType::Block
+                | EntryType::Fifo
+        ) {
+            continue;
+        }
+
+        let components = match tar_entry.path() {
+            Ok(path) => get_components(&path),
+            Err(_) => return Err(ServiceError::FailedToReadArchive),
+        };
+
+        let mut meta = Metadata {
+            mode: header.mode().ok().map(|mode| mode & 0o7777),
+            uid: header.uid().ok().and_then(|uid| u32::try_from(uid).ok()),
+            gid: header.gid().ok().and_then(|gid| u32::try_from(gid).ok()),
+            mtime: header
+                .mtime()
+                .ok()
+                .and_then(|mtime| i64::try_from(mtime).ok()),
+            ..Metadata::default()
+        };
+
+        let is_link = entry_type == EntryType::Symlink || entry_type == EntryType::Link;
+        if is_link {
+            meta.link = tar_entry
+                .link_name()
+                .ok()
+                .flatten()
+                .map(|link| link.to_string_lossy().into_owned());
+        }
+
+        let is_dir = entry_type == EntryType::Directory;
+        let size = if is_dir || is_link {
+            0
+        } else {
+            tar_entry.size()
+        };
+
+        if digests && entry_type.is_file() {
+            meta.sha256 = Some(get_sha256(&mut tar_entry)?);
+        }
+
+        entries.push(Entry {
+            components,
+            is_dir,
+            size,
+            meta,
+        });
+    }
+
+    Ok(entries)
+}
+
+fn read_zip(file: fs::File, digests: bool) -> Result<Vec<Entry>, ServiceError> {
+    let mut archive = ZipArchive::new(file).or(Err(ServiceError::FailedToReadArchive))?;
+    let mut entries = Vec::new();
+
+    for index in 0..archive.len() {
+        let mut zip_entry = archive
+            .by_index(index)
+            .or(Err(ServiceError::FailedToReadArchive))?;
+
+        let components = get_components(Path::new(zip_entry.name()));
+        let is_dir = zip_entry.is_dir();
+        let unix_mode = zip_entry.unix_mode();
+        let is_link = unix_mode.is_some_and(|mode| mode & S_IFMT == S_IFLNK);
+
+        let mut meta = Metadata {
+            mode: unix_mode.map(|mode| mode & 0o7777),
+            mtime: zip_entry.last_modified().and_then(|time| {
+                NaiveDate::from_ymd_opt(time.year().into(), time.month().into(), time.day().into())?
+                    .and_hms_opt(
+                        time.hour().into(),
+                        time.minute().into(),
+                        time.second().into(),
+                    )
+                    .map(|time| time.and_utc().timestamp())
+            }),
+            ..Metadata::default()
+        };
+
+        let size = if is_dir || is_link {
+            0
+        } else {
+            zip_entry.size()
+        };
+
+        if is_link {
+            let mut target = String::new();
+            if zip_entry.read_to_string(&mut target).is_ok() {
+                meta.link = Some(target);
+            }
+        } else if digests && !is_dir {
+            meta.sha256 = Some(get_sha256(&mut zip_entry)?);
+        }
+
+        entries.push(Entry {
+            components,
+            is_dir,
+            size,
+            meta,
+        });
+    }
+
+    Ok(entries)
+}
+
+fn get_components(path: &Path) -> Vec<String> {
+    path.components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(name.to_string_lossy().into_owned()),
+            _ => None,
+        })
+        .collect()
+}
+
+fn get_sha256<R: Read>(reader: &mut R) -> Result<String, ServiceError> {
+    let mut hasher = Sha256::new();
+    io::copy(reader, &mut hasher).or(Err(ServiceError::FailedToReadArchive))?;
+
+    Ok(hex::encode(hasher.finalize()))
+}
+
+fn insert_entry(snapshot: &mut Snapshot, entry: Entry) {
+    let mut components = entry.components;
+    let Some(name) = components.pop() else {
+        return;
+    };
+
+    let (files, dirs) = snapshot::get_parent(&mut snapshot.files, &mut snapshot.dirs, &components);
+
+    if entry.is_dir {
+        snapshot::upsert_dir(dirs, name, entry.meta);
+    } else {
+        files.push(File {
+            name,
+            size_kb: entry.size,
+            meta: entry.meta,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::path::{Path, PathBuf};
+    use std::{env, fs};
+
+    use chrono::DateTime;
+    use zip::write::SimpleFileOptions;
+
+    use super::{detect_kind, read_archive, ArchiveKind};
+
+    fn get_test_path(name: &str) -> PathBuf {
+        let args: Vec<String> = env::args().collect();
+        let mut path = PathBuf::from(&args[0]);
+        path.pop();
+        path.push(name);
+        path
+    }
+
+    fn write_tar<W: Write>(writer: W) {
+        let mut builder = tar::Builder::new(writer);
+
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Directory);
+        header.set_size(0);
+        header.set_mode(0o755);
+        header.set_cksum();
+        builder.append_data(&mut header, "sub/", &[][..]).unwrap();
+
+        let data = [7u8; 2048];
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(1690000000);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, "sub/a.bin", &data[..])
+            .unwrap();
+
+        let mut header = tar::Header::new_gnu();
+        header.set_size(3);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, "b.txt", &b"abc"[..])
+            .unwrap();
+
+        builder.into_inner().unwrap();
+    }
+
+    #[test]
+    fn test_detect_kind() {
+        let path = get_test_path("detect.tar.gz");
+        fs::write(&path, "").unwrap();
+        let result = detect_kind(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(result, Some(ArchiveKind::TarGz));
+        assert_eq!(detect_kind(Path::new("non-existent.zip")), None);
+    }
+
+    #[test]
+    fn test_read_tar() {
+        let path = get_test_path("read.tar");
+        write_tar(fs::File::create(&path).unwrap());
+
+        let result = read_archive(
+            &path,
+            &ArchiveKind::Tar,
+            1,
+            DateTime::UNIX_EPOCH.fixed_offset(),
+            String::new(),
+        );
+        fs::remove_file(&path).unwrap();
+        let snapshot = result.unwrap();
+
+        assert_eq!(snapshot.files[0].name, "b.txt");
+        assert_eq!(snapshot.files[0].size_kb, 3);
+        assert_eq!(snapshot.dirs[0].name, "sub");
+        assert_eq!(snapshot.dirs[0].meta.mode, Some(0o755));
+        assert_eq!(snapshot.dirs[0].size_kb, 2);
+        assert_eq!(snapshot.dirs[0].files[0].meta.mtime, Some(1690000000));
+    }
+
+    #[test]
+    fn test_read_tar_pax_global_header() {
+        let path = get_test_path("pax.tar");
+        let mut builder = tar::Builder::new(fs::File::create(&path).unwrap());
+
+        let comment = b"52 comment=0123456789abcdef0123456789abcdef01234567\n";
+        let mut header = tar::Header::new_ustar();
+        header.set_entry_type(tar::EntryType::XGlobalHeader);
+        header.set_size(comment.len() as u64);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, "pax_global_header", &comment[..])
+            .unwrap();
+
+        let mut header = tar::Header::new_ustar();
+        header.set_entry_type(tar::EntryType::Fifo);
+        header.set_size(0);
+        header.set_cksum();
+        builder.append_data(&mut header, "pipe", &[][..]).unwrap();
+
+        let mut header = tar::Header::new_ustar();
+        header.set_size(3);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, "b.txt", &b"abc"[..])
+            .unwrap();
+        builder.into_inner().unwrap();
+
+        let result = read_archive(
+            &path,
+            &ArchiveKind::Tar,
+            1,
+            DateTime::UNIX_EPOCH.fixed_offset(),
+            String::new(),
+        );
+        fs::remove_file(&path).unwrap();
+        let snapshot = result.unwrap();
+
+        let names: Vec<&str> = snapshot
+            .files
+            .iter()
+            .map(|file| file.name.as_str())
+            .collect();
+        assert_eq!(names, Vec::from(["b.txt"]));
+        assert_eq!(snapshot.files[0].size_kb, 3);
+    }
+
+    #[test]
+    fn test_read_tar_zst() {
+        let path = get_test_path("read.tar.zst");
+        let encoder = zstd::Encoder::new(fs::File::create(&path).unwrap(), 3).unwrap();
+        write_tar(encoder.auto_finish());
+
+        let result = read_archive(
+            &path,
+            &ArchiveKind::TarZst,
+            1,
+            DateTime::UNIX_EPOCH.fixed_offset(),
+            String::new(),
+        );
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(result.unwrap().dirs[0].files[0].size_kb, 2048);
+    }
+
+    #[test]
+    fn test_read_zip() {
+        let path = get_test_path("read.zip");
+        let mut writer = zip::ZipWriter::new(fs::File::create(&path).unwrap());
+        let options = SimpleFileOptions::default().unix_permissions(0o600);
+        writer.add_directory("sub/", options).unwrap();
+        writer.start_file("sub/c.txt", options).unwrap();
+        writer.write_all(b"hello").unwrap();
+        writer.finish().unwrap();
+
+        let result = read_archive(
+            &path,
+            &ArchiveKind::Zip,
+            1,
+            DateTime::UNIX_EPOCH.fixed_offset(),
+            String::new(),
+        );
+        fs::remove_file(&path).unwrap();
+        let snapshot = result.unwrap();
+
+        assert_eq!(snapshot.dirs[0].name, "sub");
+        assert_eq!(snapshot.dirs[0].files[0].name, "c.txt");
+        assert_eq!(snapshot.dirs[0].files[0].size_kb, 5);
+        assert_eq!(snapshot.dirs[0].files[0].meta.mode, Some(0o600));
+    }
+}
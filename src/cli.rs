@@ -0,0 +1,81 @@
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+use crate::errors::ServiceError;
+
+/// Arguments of a non-interactive command: positional values, `--key=value`
+/// options and bare `--flag` switches.
+#[derive(Debug, Default, PartialEq)]
+pub struct Args {
+    pub positional: Vec<String>,
+    options: HashMap<String, String>,
+    flags: HashSet<String>,
+}
+
+impl Args {
+    pub fn parse(args: &[String]) -> Args {
+        let mut result = Args::default();
+
+        for arg in args {
+            match arg.strip_prefix("--") {
+                Some(option) => match option.split_once('=') {
+                    Some((key, value)) => {
+                        result
+                            .options
+                            .insert(String::from(key), String::from(value));
+                    }
+                    None => {
+                        result.flags.insert(String::from(option));
+                    }
+                },
+                None => result.positional.push(arg.clone()),
+            }
+        }
+
+        result
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.options.get(name).map(String::as_str)
+    }
+
+    pub fn has_flag(&self, name: &str) -> bool {
+        self.flags.contains(name)
+    }
+
+    pub fn get_or<T: FromStr>(&self, name: &str, default: T) -> Result<T, ServiceError> {
+        match self.get(name) {
+            Some(value) => value.parse().or(Err(ServiceError::InvalidArgument)),
+            None => Ok(default),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Args;
+
+    fn parse(args: &[&str]) -> Args {
+        let args: Vec<String> = args.iter().map(|arg| String::from(*arg)).collect();
+        Args::parse(&args)
+    }
+
+    #[test]
+    fn test_parse() {
+        let args = parse(&["path", "--debounce=5", "--json"]);
+
+        assert_eq!(args.positional, Vec::from([String::from("path")]));
+        assert_eq!(args.get("debounce"), Some("5"));
+        assert!(args.flags.contains("json"));
+        assert!(!args.flags.contains("debounce"));
+    }
+
+    #[test]
+    fn test_get_or() {
+        let args = parse(&["--debounce=5", "--limit=many"]);
+
+        assert_eq!(args.get_or("debounce", 1).unwrap(), 5);
+        assert_eq!(args.get_or("interval", 60).unwrap(), 60);
+        assert!(args.get_or("limit", 0).is_err());
+    }
+}
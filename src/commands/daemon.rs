@@ -0,0 +1,386 @@
+use std::cmp::Reverse;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+use std::{env, fs, thread};
+
+use chrono::{DateTime, Local, Utc};
+use cron::Schedule;
+use mongodb::bson::{doc, Document};
+use mongodb::options::FindOptions;
+use mongodb::{Collection, Database};
+use serde::Deserialize;
+
+use crate::cli::Args;
+use crate::commands::snapshot_creation::{self, SnapshotOptions, UnchangedMode};
+use crate::common::log;
+use crate::errors::ServiceError;
+use crate::snapshot::{self, Exclusions, Series};
+use crate::storage;
+
+#[derive(Debug, Deserialize, PartialEq)]
+pub struct DaemonConfig {
+    pub paths: Vec<PathConfig>,
+}
+
+/// One `[[paths]]` entry of the daemon configuration file.
+#[derive(Debug, Deserialize, PartialEq)]
+pub struct PathConfig {
+    pub path: String,
+    pub schedule: String,
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    #[serde(default)]
+    pub unchanged: UnchangedMode,
+    pub keep_last: Option<usize>,
+    pub max_age_days: Option<i64>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+/// Version number and date of a stored snapshot, as needed by retention.
+/// `legacy` versions were stored without a host and belong to every host.
+#[derive(Debug, PartialEq)]
+pub struct StoredVersion {
+    pub version: i32,
+    pub date: Option<DateTime<Utc>>,
+    pub unchanged_since: Option<i32>,
+    pub legacy: bool,
+}
+
+struct Job {
+    config: PathConfig,
+    schedule: Schedule,
+    options: SnapshotOptions,
+    next: Option<DateTime<Local>>,
+}
+
+/// Takes snapshots of every configured path on its schedule and applies the
+/// retention rules of that path afterwards. Runs until killed.
+#[cfg(not(tarpaulin_include))]
+pub async fn daemon(args: &Args, db: &Database) -> Result<(), ServiceError> {
+    let Some(config_path) = args.positional.first() else {
+        return Err(ServiceError::WrongNumberOfArgs);
+    };
+    let config = read_config(Path::new(config_path))?;
+    let collection: Collection<Document> =
+        db.collection(&env::var("COLL_NAME").expect("COLL_NAME must be set"));
+
+    let mut jobs = Vec::new();
+    for config in config.paths {
+        let schedule = parse_schedule(&config.schedule)?;
+        let options = SnapshotOptions {
+            unchanged: config.unchanged,
+            exclusions: Exclusions::create(Path::new(&config.path), &config.exclude)?,
+            tags: config.tags.clone(),
+            ..SnapshotOptions::default()
+        };
+        let next = schedule.after(&Local::now()).next();
+
+        log(&format!("Scheduled {} ({})", config.path, config.schedule));
+        jobs.push(Job {
+            config,
+            schedule,
+            options,
+            next,
+        });
+    }
+
+    loop {
+        let Some(next) = jobs.iter().filter_map(|job| job.next).min() else {
+            return Ok(());
+        };
+        if let Ok(delay) = (next - Local::now()).to_std() {
+            thread::sleep(delay.max(Duration::from_millis(100)));
+        }
+
+        let now = Local::now();
+        for job in jobs.iter_mut().filter(|job| job.next <= Some(now)) {
+            if let Err(error) = run_job(job, db, &collection).await {
+                log(&format!("Snapshot of {} failed: {error}", job.config.path));
+            }
+            job.next = job.schedule.after(&now).next();
+        }
+    }
+}
+
+#[cfg(not(tarpaulin_include))]
+async fn run_job(
+    job: &Job,
+    db: &Database,
+    collection: &Collection<Document>,
+) -> Result<(), ServiceError> {
+    let path = Path::new(&job.config.path);
+
+    if let Some(version) = snapshot_creation::store_snapshot(path, db, &job.options).await? {
+        log(&format!(
+            "Snapshot version {version} of {} created",
+            job.config.path
+        ));
+    }
+
+    apply_retention(&job.config, collection).await
+}
+
+#[cfg(not(tarpaulin_include))]
+async fn apply_retention(
+    config: &PathConfig,
+    collection: &Collection<Document>,
+) -> Result<(), ServiceError> {
+    if config.keep_last.is_none() && config.max_age_days.is_none() {
+        return Ok(());
+    }
+
+    let options = FindOptions::builder()
+        .projection(doc! {"version": 1, "date": 1, "unchanged_since": 1, "host": 1})
+        .build();
+    let series = Series::local(&config.path);
+    let mut cursor = match collection.find(series.filter(), options).await {
+        Ok(cursor) => cursor,
+        Err(_) => return Err(ServiceError::FailedToFoundCollection),
+    };
+
+    let mut versions = Vec::new();
+    while cursor
+        .advance()
+        .await
+        .or(Err(ServiceError::FailedToFoundCollection))?
+    {
+        let Ok(current) = cursor.deserialize_current() else {
+            continue;
+        };
+        if let Ok(version) = current.get_i32("version") {
+            versions.push(StoredVersion {
+                version,
+                date: current.get("date").and_then(snapshot::parse_date),
+                unchanged_since: current.get_i32("unchanged_since").ok(),
+                legacy: current.get_str("host").is_err(),
+            });
+        }
+    }
+
+    let expired = select_expired(&versions, config, Local::now());
+    if expired.is_empty() {
+        return Ok(());
+    }
+
+    // Only the versions of this host; legacy ones are shared by all hosts.
+    match collection
+        .delete_many(
+            doc! {"path": &series.path, "host": &series.host, "version": {"$in": &expired}},
+            None,
+        )
+        .await
+    {
+        Ok(_) => log(&format!(
+            "Retention removed version(s) {expired:?} of {}",
+            config.path
+        )),
+        Err(_) => return Err(ServiceError::FailedToFoundCollection),
+    }
+
+    let removed = storage::remove_unreferenced_nodes(collection).await?;
+    if removed > 0 {
+        log(&format!("Removed {removed} unreferenced tree node(s)"));
+    }
+
+    Ok(())
+}
+
+/// Reads the configuration and canonicalizes its paths, so that the daemon
+/// stores the same series the other commands read. Paths that don't exist
+/// are rejected.
+pub fn read_config(path: &Path) -> Result<DaemonConfig, ServiceError> {
+    let text = fs::read_to_string(path).or(Err(ServiceError::FailedToReadConfig))?;
+    let mut config: DaemonConfig =
+        toml::from_str(&text).or(Err(ServiceError::FailedToReadConfig))?;
+
+    for entry in config.paths.iter_mut() {
+        let Some(canonical) = fs::canonicalize(&entry.path)
+            .ok()
+            .and_then(|canonical| canonical.to_str().map(String::from))
+        else {
+            log(&format!("Configured path {} doesn't exist", entry.path));
+            return Err(ServiceError::FailedToReadConfig);
+        };
+        entry.path = canonical;
+    }
+
+    Ok(config)
+}
+
+/// Accepts both the classic five-field cron syntax and the six-field syntax
+/// with leading seconds.
+pub fn parse_schedule(expression: &str) -> Result<Schedule, ServiceError> {
+    let expression = match expression.split_whitespace().count() {
+        5 => format!("0 {expression}"),
+        _ => String::from(expression),
+    };
+
+    Schedule::from_str(&expression).or(Err(ServiceError::FailedToReadConfig))
+}
+
+/// Picks the versions to delete. Legacy versions are never picked nor
+/// counted. The latest version is always kept, as are versions without a
+/// readable date and versions that a kept marker version refers to.
+pub fn select_expired(
+    versions: &[StoredVersion],
+    config: &PathConfig,
+    now: DateTime<Local>,
+) -> Vec<i32> {
+    let mut versions: Vec<&StoredVersion> = versions.iter().collect();
+    versions.sort_by_key(|stored| Reverse(stored.version));
+
+    let mut expired = Vec::new();
+    let mut referenced: Vec<i32> = versions
+        .iter()
+        .filter(|stored| stored.legacy)
+        .filter_map(|stored| stored.unchanged_since)
+        .collect();
+
+    let own = versions.iter().filter(|stored| !stored.legacy);
+    for (index, stored) in own.enumerate() {
+        let too_many = config.keep_last.is_some_and(|keep| index >= keep);
+        let too_old = config.max_age_days.is_some_and(|days| {
+            stored
+                .date
+                .is_some_and(|date| now.signed_duration_since(date).num_days() >= days)
+        });
+
+        if index > 0 && (too_many || too_old) {
+            expired.push(stored.version);
+        } else if let Some(since) = stored.unchanged_since {
+            referenced.push(since);
+        }
+    }
+
+    expired.retain(|version| !referenced.contains(version));
+    expired
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::{env, fs};
+
+    use chrono::{DateTime, Duration, Local};
+
+    use crate::commands::snapshot_creation::UnchangedMode;
+
+    use super::{
+        parse_schedule, read_config, select_expired, DaemonConfig, PathConfig, StoredVersion,
+    };
+
+    fn get_test_path(name: &str) -> PathBuf {
+        let args: Vec<String> = env::args().collect();
+        let mut path = PathBuf::from(&args[0]);
+        path.pop();
+        path.push(name);
+        path
+    }
+
+    fn create_config(keep_last: Option<usize>, max_age_days: Option<i64>) -> PathConfig {
+        PathConfig {
+            path: String::from("test"),
+            schedule: String::from("0 * * * *"),
+            exclude: Vec::new(),
+            unchanged: UnchangedMode::Store,
+            keep_last,
+            max_age_days,
+            tags: Vec::new(),
+        }
+    }
+
+    fn create_version(
+        version: i32,
+        date: Option<DateTime<Local>>,
+        unchanged_since: Option<i32>,
+    ) -> StoredVersion {
+        StoredVersion {
+            version,
+            date: date.map(|date| date.to_utc()),
+            unchanged_since,
+            legacy: false,
+        }
+    }
+
+    #[test]
+    fn test_parse_config() {
+        let text = "[[paths]]\npath = \"test\"\nschedule = \"0 * * * *\"\nkeep_last = 3\n";
+        let config: DaemonConfig = toml::from_str(text).unwrap();
+        assert_eq!(config.paths, Vec::from([create_config(Some(3), None)]));
+    }
+
+    #[test]
+    fn test_read_config_canonicalizes_paths() {
+        let dir = get_test_path("");
+        let config_path = get_test_path("daemon-config.toml");
+        let entry =
+            |path: &str| format!("[[paths]]\npath = \"{path}\"\nschedule = \"0 * * * *\"\n");
+
+        fs::write(&config_path, entry(&format!("{}/./", dir.display()))).unwrap();
+        let config = read_config(&config_path);
+        fs::write(&config_path, entry("/non-existent/path")).unwrap();
+        let missing = read_config(&config_path);
+        fs::remove_file(&config_path).unwrap();
+
+        let expected = fs::canonicalize(&dir).unwrap();
+        assert_eq!(config.unwrap().paths[0].path, expected.to_str().unwrap());
+        assert!(missing.is_err());
+    }
+
+    #[test]
+    fn test_parse_schedule() {
+        assert!(parse_schedule("*/5 * * * *").is_ok());
+        assert!(parse_schedule("0 0 3 * * Mon").is_ok());
+        assert!(parse_schedule("not a schedule").is_err());
+    }
+
+    #[test]
+    fn test_select_expired_by_count() {
+        let versions: Vec<StoredVersion> = (1..=5).map(|v| create_version(v, None, None)).collect();
+        let result = select_expired(&versions, &create_config(Some(2), None), Local::now());
+        assert_eq!(result, Vec::from([3, 2, 1]));
+    }
+
+    #[test]
+    fn test_select_expired_by_age() {
+        let now = Local::now();
+        let versions = Vec::from([
+            create_version(1, Some(now - Duration::days(40)), None),
+            create_version(2, Some(now - Duration::days(10)), None),
+            create_version(3, Some(now - Duration::days(50)), None),
+            create_version(4, None, None),
+        ]);
+        let result = select_expired(&versions, &create_config(None, Some(30)), now);
+        assert_eq!(result, Vec::from([3, 1]));
+    }
+
+    #[test]
+    fn test_select_expired_keeps_referenced_versions() {
+        let versions = Vec::from([
+            create_version(1, None, None),
+            create_version(2, None, None),
+            create_version(3, None, Some(1)),
+        ]);
+        let result = select_expired(&versions, &create_config(Some(1), None), Local::now());
+        assert_eq!(result, Vec::from([2]));
+    }
+
+    #[test]
+    fn test_select_expired_keeps_legacy_versions() {
+        let legacy = |version| StoredVersion {
+            legacy: true,
+            ..create_version(version, None, None)
+        };
+        let versions = Vec::from([
+            legacy(1),
+            legacy(2),
+            create_version(3, None, None),
+            create_version(4, None, None),
+            legacy(5),
+        ]);
+        let result = select_expired(&versions, &create_config(Some(1), None), Local::now());
+        assert_eq!(result, Vec::from([3]));
+    }
+}
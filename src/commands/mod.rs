@@ -1,13 +1,16 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use mongodb::Database;
 
+use crate::cli::Args;
 use crate::errors::ServiceError;
 
+mod daemon;
 mod exit;
 mod navigation;
 mod snapshot_creation;
 mod snapshot_gallery;
+mod watch;
 
 pub async fn run_command(
     input: i32,
@@ -19,7 +22,8 @@ pub async fn run_command(
             navigation::nav_menu(path);
         }
         2 => {
-            snapshot_creation::create_snapshot(path, db).await?;
+            let options = snapshot_creation::read_options();
+            snapshot_creation::create_snapshot(path, db, &options).await?;
         }
         3 => {
             snapshot_gallery::snapshot_gallery_menu(path, db).await?;
@@ -35,6 +39,33 @@ pub async fn run_command(
     Ok(())
 }
 
+#[cfg(not(tarpaulin_include))]
+pub async fn run_cli_command(
+    command: &str,
+    args: &Args,
+    path: &Path,
+    db: &Database,
+) -> Result<(), ServiceError> {
+    match command {
+        "watch" => watch::watch(args, path, db).await,
+        "daemon" => daemon::daemon(args, db).await,
+        "diff" => snapshot_gallery::snapshot_diff::diff_command(args, path, db).await,
+        "duplicates" => {
+            snapshot_gallery::snapshot_duplicates::duplicates_command(args, path, db).await
+        }
+        "browse" => snapshot_gallery::snapshot_browser::browse_command(args, path, db).await,
+        "growth" => snapshot_gallery::snapshot_growth::growth_command(args, path, db).await,
+        "history" => snapshot_gallery::snapshot_history::history_command(args, path, db).await,
+        "merge" => snapshot_gallery::snapshot_merge::merge_command(args, path, db).await,
+        "search" => snapshot_gallery::snapshot_search::search_command(args, path, db).await,
+        "show" => snapshot_gallery::snapshot_tree::show_command(args, path, db).await,
+        "stats" => snapshot_gallery::snapshot_stats::stats_command(args, path, db).await,
+        "usage" => snapshot_gallery::snapshot_usage::usage_command(args, path, db).await,
+        "versions" => snapshot_gallery::snapshot_listing::versions_command(args, path, db).await,
+        _ => Err(ServiceError::FailedToParseCommand),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::{env, path::PathBuf};
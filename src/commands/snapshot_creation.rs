@@ -1,39 +1,217 @@
 use std::env;
 use std::path::Path;
+use std::str::FromStr;
 
+use crate::archive;
+use crate::commands::snapshot_gallery::get_snapshot;
+use crate::commands::snapshot_gallery::snapshot_labels;
+use crate::common;
 use crate::errors::ServiceError;
-use crate::snapshot::{self, Snapshot};
+use crate::snapshot::{self, Exclusions, Series, Snapshot};
+use crate::storage;
 
-use chrono::Local;
+use chrono::{DateTime, FixedOffset, Local};
+use mongodb::bson::{doc, Bson, Document};
+use mongodb::options::FindOneOptions;
 use mongodb::Collection;
 use mongodb::Database;
+use serde::de::value::{self, StrDeserializer};
+use serde::de::IntoDeserializer;
+use serde::Deserialize;
 
-pub async fn create_snapshot(path: &Path, db: &Database) -> Result<(), ServiceError> {
+/// What to do when a freshly scanned tree is identical to the latest version.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UnchangedMode {
+    /// Store a full new version anyway.
+    #[default]
+    Store,
+    /// Do not store anything.
+    Skip,
+    /// Store a version without a tree that refers to the identical one.
+    Marker,
+}
+
+/// Parses the names of the configuration file, so that `--unchanged` takes
+/// the same values.
+impl FromStr for UnchangedMode {
+    type Err = ServiceError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let deserializer: StrDeserializer<value::Error> = value.into_deserializer();
+        UnchangedMode::deserialize(deserializer).or(Err(ServiceError::InvalidArgument))
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct SnapshotOptions {
+    pub unchanged: UnchangedMode,
+    pub exclusions: Exclusions,
+    pub label: Option<String>,
+    pub note: Option<String>,
+    pub tags: Vec<String>,
+}
+
+/// Asks for the options of a snapshot taken from the menu.
+#[cfg(not(tarpaulin_include))]
+pub fn read_options() -> SnapshotOptions {
+    println!("If nothing has changed since the last version: 1 - store it anyway, 2 - skip it, 3 - store a marker that refers to it");
+    let unchanged = match common::get_input() {
+        2 => UnchangedMode::Skip,
+        3 => UnchangedMode::Marker,
+        _ => UnchangedMode::Store,
+    };
+
+    println!("Please input a label for the snapshot or leave it empty:");
+    let label = Some(common::get_string_input()).filter(|label| !label.is_empty());
+
+    println!("Please input a note for the snapshot or leave it empty:");
+    let note = Some(common::get_string_input()).filter(|note| !note.is_empty());
+
+    println!("Please input tags separated by commas or leave it empty:");
+    let tags = snapshot_labels::parse_tags(&common::get_string_input());
+
+    SnapshotOptions {
+        unchanged,
+        label,
+        note,
+        tags,
+        ..SnapshotOptions::default()
+    }
+}
+
+pub async fn create_snapshot(
+    path: &Path,
+    db: &Database,
+    options: &SnapshotOptions,
+) -> Result<(), ServiceError> {
+    if store_snapshot(path, db, options).await?.is_none() {
+        println!("Nothing has changed since the last version, snapshot skipped");
+    }
+
+    Ok(())
+}
+
+/// Scans `path` and stores it as the next version, unless it is identical to
+/// the latest one and `options.unchanged` says otherwise. Returns the stored
+/// version, if any.
+pub async fn store_snapshot(
+    path: &Path,
+    db: &Database,
+    options: &SnapshotOptions,
+) -> Result<Option<i32>, ServiceError> {
     let collection_name = &env::var("COLL_NAME").expect("COLL_NAME must be set");
     let snapshot_path = String::from(path.to_str().unwrap());
-    let collection: Collection<Snapshot> = db.collection(&collection_name);
+    let series = Series::local(&snapshot_path);
+    let collection: Collection<Snapshot> = db.collection(collection_name);
 
-    let version = snapshot::get_version(&collection, &snapshot_path)
+    let last_version = snapshot::get_version(&collection, &series)
         .await
-        .expect("Version Error")
-        + 1;
-    let date = Local::now().to_string();
+        .expect("Version Error");
+    let version = last_version + 1;
+    let date = Local::now().fixed_offset();
+
+    if let Some(label) = &options.label {
+        snapshot_labels::validate_label(label)?;
+        snapshot_labels::ensure_label_free(&series, &collection, label, version).await?;
+    }
+
+    let mut snapshot = scan_snapshot(
+        path,
+        version,
+        date,
+        snapshot_path.clone(),
+        &options.exclusions,
+    )?;
+    snapshot.root_digest = Some(snapshot.compute_digest());
+    snapshot.set_origin(&series.host);
+    snapshot.set_counts();
+    snapshot.label = options.label.clone();
+    snapshot.note = options.note.clone();
+    snapshot.tags = options.tags.clone();
+
+    if options.unchanged != UnchangedMode::Store && last_version > 0 {
+        if let Some(since) = find_unchanged(&series, &collection, last_version, &snapshot).await? {
+            if options.unchanged == UnchangedMode::Skip {
+                return Ok(None);
+            }
+            snapshot.unchanged_since = Some(since);
+            snapshot.files.clear();
+            snapshot.dirs.clear();
+        }
+    }
 
-    let mut snapshot = snapshot::Snapshot::create(version, date, snapshot_path);
+    storage::insert_snapshot(&collection, snapshot).await?;
 
-    let size = snapshot::fill_and_return_size(path, &mut snapshot.files, &mut snapshot.dirs);
-    snapshot.set_size(size);
+    Ok(Some(version))
+}
 
-    match db
-        .collection(&env::var("COLL_NAME").expect("COLL_NAME must be set"))
-        .insert_one(snapshot, None)
+/// Returns the version holding the tree of `version` when that tree is the
+/// one of `snapshot`. Only the stored digest is read; the tree is loaded for
+/// versions taken before digests existed.
+async fn find_unchanged(
+    series: &Series,
+    collection: &Collection<Snapshot>,
+    version: i32,
+    snapshot: &Snapshot,
+) -> Result<Option<i32>, ServiceError> {
+    let options = FindOneOptions::builder()
+        .projection(doc! {"size_kb": 1, "root_digest": 1, "unchanged_since": 1})
+        .build();
+    let latest = match collection
+        .clone_with_type::<Document>()
+        .find_one(series.version_filter(version), options)
         .await
     {
-        Ok(res) => res,
+        Ok(Some(latest)) => latest,
+        Ok(None) => return Ok(None),
         Err(_) => return Err(ServiceError::FailedToFoundCollection),
     };
+    let since = latest.get_i32("unchanged_since").unwrap_or(version);
 
-    Ok(())
+    let same = match latest.get_str("root_digest") {
+        Ok(digest) => {
+            let size = match latest.get("size_kb") {
+                Some(Bson::Int64(size)) => u64::try_from(*size).ok(),
+                Some(Bson::Int32(size)) => u64::try_from(*size).ok(),
+                _ => None,
+            };
+            Some(digest) == snapshot.root_digest.as_deref() && size == Some(snapshot.size_kb)
+        }
+        Err(_) => get_snapshot(series, collection, version)
+            .await?
+            .is_some_and(|latest| latest.same_content(snapshot)),
+    };
+
+    Ok(same.then_some(since))
+}
+
+/// Reads the tree under `path`, which is either a directory or an archive.
+/// Exclusions only apply to directories.
+pub fn scan_snapshot(
+    path: &Path,
+    version: i32,
+    date: DateTime<FixedOffset>,
+    snapshot_path: String,
+    exclusions: &Exclusions,
+) -> Result<Snapshot, ServiceError> {
+    let snapshot = match archive::detect_kind(path) {
+        Some(kind) => archive::read_archive(path, &kind, version, date, snapshot_path)?,
+        None => {
+            let mut snapshot = snapshot::Snapshot::create(version, date, snapshot_path);
+
+            let size = snapshot::fill_and_return_size(
+                path,
+                &mut snapshot.files,
+                &mut snapshot.dirs,
+                exclusions,
+            );
+            snapshot.set_size(size);
+            snapshot
+        }
+    };
+
+    Ok(snapshot)
 }
 
 #[cfg(test)]
@@ -45,9 +223,9 @@ mod tests {
 
     use crate::commands::snapshot_gallery::get_snapshot;
     use crate::db;
-    use crate::snapshot::Snapshot;
+    use crate::snapshot::{Series, Snapshot};
 
-    use crate::commands::snapshot_creation::create_snapshot;
+    use crate::commands::snapshot_creation::{create_snapshot, SnapshotOptions, UnchangedMode};
 
     fn set_env_variables() {
         env::set_var("DB_NAME", "snapshots_gallery");
@@ -57,6 +235,13 @@ mod tests {
         );
         env::set_var("COLL_NAME", "snapshots");
     }
+    #[test]
+    fn test_parse_unchanged_mode() {
+        assert_eq!("marker".parse().ok(), Some(UnchangedMode::Marker));
+        assert_eq!("skip".parse().ok(), Some(UnchangedMode::Skip));
+        assert!("Skip".parse::<UnchangedMode>().is_err());
+    }
+
     #[tokio::test]
     async fn test_create_snapshot() {
         set_env_variables();
@@ -73,16 +258,17 @@ mod tests {
         let collection: Collection<Snapshot> = db.collection(&collection_name);
         let version = 1;
 
-        create_snapshot(&path, &db).await.unwrap();
+        create_snapshot(&path, &db, &SnapshotOptions::default())
+            .await
+            .unwrap();
 
         fs::remove_dir_all(&path).unwrap();
 
-        match get_snapshot(&path.to_str().unwrap(), &collection, version)
-            .await
-            .unwrap()
-        {
-            Some(_) => true,
-            None => false,
-        };
+        assert!(
+            get_snapshot(&Series::local(path.to_str().unwrap()), &collection, version)
+                .await
+                .unwrap()
+                .is_some()
+        );
     }
 }
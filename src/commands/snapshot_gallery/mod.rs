@@ -1,18 +1,36 @@
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::{env, fs};
 
-use crate::commands::snapshot_gallery::snapshot_comparison::Mode;
 use crate::common::{self, get_input};
 use crate::errors::ServiceError;
-use crate::snapshot::{self, Snapshot};
+use crate::filter;
+use crate::snapshot::{self, Series, Snapshot};
+use crate::storage;
 use mongodb::bson::doc;
+use mongodb::options::FindOptions;
 use mongodb::Collection;
 use mongodb::Database;
 
+pub mod snapshot_browser;
 mod snapshot_comparison;
+pub mod snapshot_diff;
+pub mod snapshot_duplicates;
+mod snapshot_export;
+pub mod snapshot_growth;
+pub mod snapshot_history;
+mod snapshot_html;
+pub mod snapshot_labels;
+pub mod snapshot_listing;
+pub mod snapshot_merge;
+pub mod snapshot_search;
+pub mod snapshot_stats;
+mod snapshot_timeline;
+pub mod snapshot_tree;
+pub mod snapshot_usage;
 
 pub async fn snapshot_gallery_menu(path: &PathBuf, db: &Database) -> Result<(), ServiceError> {
-    let path = path.to_str().unwrap();
+    let mut series = Series::local(path.to_str().unwrap());
     let collection: Collection<Snapshot> =
         db.collection(&env::var("COLL_NAME").expect("COLL_NAME must be set"));
 
@@ -21,25 +39,43 @@ pub async fn snapshot_gallery_menu(path: &PathBuf, db: &Database) -> Result<(),
         println!("1. Show list of versions");
         println!("2. Show the version");
         println!("3. Compare snapshots");
+        println!("4. Export snapshots to a file");
+        println!("5. Import snapshots from a file");
+        println!("6. Export the version as an mtree manifest");
+        println!("7. Import an mtree manifest as a new version");
+        println!("8. Edit the label, note and tags of the version");
+        println!("9. Show versions within a date range");
+        println!("10. Choose the host (now {})", series.host);
+        println!("11. Compare with a version from another host");
+        println!("12. Browse and compare versions in full screen");
+        println!("13. Show the disk usage of the version");
+        println!("14. Show how a directory grew across versions");
+        println!("15. Show the history of a file across versions");
+        println!("16. Search for files and directories");
+        println!("17. Find duplicate files");
+        println!("18. Show statistics of the version");
+        println!("19. Compare a base version with two versions derived from it");
         println!("ENTER ANOTHER NUMBER TO EXIT FROM SNAPSHOT GALLERY!");
 
         match common::get_input() {
             1 => {
-                show_list_of_versions(path, &collection).await?;
+                show_list_of_versions(&series, &collection).await?;
             }
             2 => {
-                println!("Please, input the number of version or 0 to choose the last one:");
-                let version = common::get_input();
-                show_version(path, &collection, version).await?;
+                println!(
+                    "Please, input the number or label of version or 0 to choose the last one:"
+                );
+                let version = snapshot_labels::read_version(&series, &collection).await?;
+                show_version(&series, &collection, version).await?;
             }
             3 => {
-                println!("Please input the first version of snapshot to compare or 0 - to choose the last one: ");
-                let version_1 = common::get_input();
+                println!("Please input the first version or label of snapshot to compare or 0 - to choose the last one: ");
+                let version_1 = snapshot_labels::read_version(&series, &collection).await?;
 
-                println!("Please input the second version of snapshot to compare or 0 - to choose the last one: ");
-                let version_2 = common::get_input();
+                println!("Please input the second version or label of snapshot to compare or 0 - to choose the last one: ");
+                let version_2 = snapshot_labels::read_version(&series, &collection).await?;
 
-                let last_version = snapshot::get_version(&collection, path).await?;
+                let last_version = snapshot::get_version(&collection, &series).await?;
 
                 if version_1 == version_2
                     || version_1 == 0 && version_2 == last_version
@@ -49,7 +85,57 @@ pub async fn snapshot_gallery_menu(path: &PathBuf, db: &Database) -> Result<(),
                     continue;
                 }
 
-                compare(path, &collection, version_1, version_2).await?;
+                compare(&series, &collection, version_1, version_2).await?;
+            }
+            4 => {
+                snapshot_export::export_menu(&series, &collection).await?;
+            }
+            5 => {
+                snapshot_export::import_menu(&series, &collection).await?;
+            }
+            6 => {
+                snapshot_export::export_mtree_menu(&series, &collection).await?;
+            }
+            7 => {
+                snapshot_export::import_mtree_menu(&series, &collection).await?;
+            }
+            8 => {
+                snapshot_labels::edit_menu(&series, &collection).await?;
+            }
+            9 => {
+                snapshot_timeline::range_menu(&series, &collection).await?;
+            }
+            10 => {
+                if let Some(chosen) = choose_host(&series, &collection).await? {
+                    series = chosen;
+                }
+            }
+            11 => {
+                compare_across_hosts(&series, &collection).await?;
+            }
+            12 => {
+                snapshot_browser::browse(&series, &collection).await?;
+            }
+            13 => {
+                snapshot_usage::usage_menu(&series, &collection).await?;
+            }
+            14 => {
+                snapshot_growth::growth_menu(&series, &collection).await?;
+            }
+            15 => {
+                snapshot_history::history_menu(&series, &collection).await?;
+            }
+            16 => {
+                snapshot_search::search_menu(&series, &collection).await?;
+            }
+            17 => {
+                snapshot_duplicates::duplicates_menu(&series, &collection).await?;
+            }
+            18 => {
+                snapshot_stats::stats_menu(&series, &collection).await?;
+            }
+            19 => {
+                snapshot_merge::merge_menu(&series, &collection).await?;
             }
             _ => break,
         }
@@ -59,126 +145,276 @@ pub async fn snapshot_gallery_menu(path: &PathBuf, db: &Database) -> Result<(),
 
 #[cfg(not(tarpaulin_include))]
 pub async fn show_list_of_versions(
-    path: &str,
+    series: &Series,
     collection: &Collection<Snapshot>,
 ) -> Result<(), ServiceError> {
-    let mut cursor = match collection.find(doc! {"path": path}, None).await {
-        Ok(cursor) => cursor,
-        Err(_) => return Err(ServiceError::FailedToFoundCollection),
-    };
-
-    while cursor.advance().await.unwrap() {
-        println!(
-            "version: {:?}",
-            cursor
-                .current()
-                .get("version")
-                .unwrap()
-                .expect("There isn't version")
-                .as_i32()
-                .unwrap()
-        );
-    }
-
-    Ok(())
+    snapshot_listing::show_summaries(series, collection, None, None).await
 }
 
 #[cfg(not(tarpaulin_include))]
 pub async fn show_version(
-    path: &str,
+    series: &Series,
     collection: &Collection<Snapshot>,
     version: i32,
 ) -> Result<(), ServiceError> {
-    let snapshot = get_snapshot(path, collection, version).await?;
-
-    if snapshot.is_none() {
-        println!("Snapshot isn't found. Check the version");
-    } else {
-        println!("{:#?}", snapshot);
-    }
-
-    Ok(())
+    snapshot_tree::tree_menu(series, collection, version).await
 }
 
 pub async fn get_snapshot(
-    path: &str,
+    series: &Series,
     collection: &Collection<Snapshot>,
     version: i32,
 ) -> Result<Option<Snapshot>, ServiceError> {
     let mut v = version;
     if v == 0 {
-        v = snapshot::get_version(&collection, path).await?;
+        v = snapshot::get_version(&collection, series).await?;
     }
-    let found_item = match collection
-        .find_one(doc! {"path": path, "version": v}, None)
+    let found_item = match collection.find_one(series.version_filter(v), None).await {
+        Ok(item) => item,
+        Err(_) => return Err(ServiceError::FailedToFoundCollection),
+    };
+
+    let Some(snapshot) = found_item else {
+        return Ok(None);
+    };
+
+    let mut cache = HashMap::new();
+    let snapshot = storage::load_snapshot(collection, snapshot, &mut cache).await?;
+
+    Ok(Some(
+        resolve_marker(series, collection, snapshot, &mut cache).await?,
+    ))
+}
+
+/// Fills the tree of a marker version from the version it refers to.
+async fn resolve_marker(
+    series: &Series,
+    collection: &Collection<Snapshot>,
+    mut snapshot: Snapshot,
+    cache: &mut HashMap<String, storage::TreeNode>,
+) -> Result<Snapshot, ServiceError> {
+    let Some(since) = snapshot.unchanged_since else {
+        return Ok(snapshot);
+    };
+
+    let original = match collection
+        .find_one(series.version_filter(since), None)
         .await
     {
         Ok(item) => item,
         Err(_) => return Err(ServiceError::FailedToFoundCollection),
     };
 
-    Ok(found_item)
+    if let Some(original) = original {
+        let original = storage::load_snapshot(collection, original, cache).await?;
+        snapshot.files = original.files;
+        snapshot.dirs = original.dirs;
+    }
+
+    Ok(snapshot)
+}
+
+pub async fn get_all_snapshots(
+    series: &Series,
+    collection: &Collection<Snapshot>,
+) -> Result<Vec<Snapshot>, ServiceError> {
+    let options = FindOptions::builder().sort(doc! {"version": 1}).build();
+
+    let mut cursor = match collection.find(series.filter(), options).await {
+        Ok(cursor) => cursor,
+        Err(_) => return Err(ServiceError::FailedToFoundCollection),
+    };
+
+    let mut cache = HashMap::new();
+    let mut snapshots: Vec<Snapshot> = Vec::new();
+    while cursor.advance().await.unwrap() {
+        let snapshot: Snapshot = match cursor.deserialize_current() {
+            Ok(snapshot) => snapshot,
+            Err(_) => return Err(ServiceError::FailedToFoundCollection),
+        };
+        let mut snapshot = storage::load_snapshot(collection, snapshot, &mut cache).await?;
+
+        let original = snapshots
+            .iter()
+            .find(|original| Some(original.version) == snapshot.unchanged_since);
+        if let Some(original) = original {
+            snapshot.files = original.files.clone();
+            snapshot.dirs = original.dirs.clone();
+        }
+        snapshots.push(snapshot);
+    }
+
+    Ok(snapshots)
 }
 
 pub async fn compare(
-    path: &str,
+    series: &Series,
     collection: &Collection<Snapshot>,
     v1: i32,
     v2: i32,
 ) -> Result<(), ServiceError> {
-    let snapshot_1 = get_snapshot(path, &collection, v1).await?;
-    let snapshot_2 = get_snapshot(path, &collection, v2).await?;
+    let snapshot_1 = get_snapshot(series, collection, v1).await?;
+    let snapshot_2 = get_snapshot(series, collection, v2).await?;
 
-    if snapshot_1.is_none() || snapshot_2.is_none() {
-        println!("Sorry, but we can't find snapshot. Please check versions");
-    } else {
-        let snapshot_1 = snapshot_1.unwrap();
-        let snapshot_2 = snapshot_2.unwrap();
-        let mut path = PathBuf::new();
-        println!("Select the display option: 1 - show comparison on the screen else upload comparison to the file (result.txt)");
-        let selection = get_input();
+    show_comparison(snapshot_1, snapshot_2)
+}
 
-        let mut file_path = PathBuf::from(&path);
-        file_path.push("result.txt");
+/// Compares a version of `series` with a version of the same path taken on
+/// another host.
+#[cfg(not(tarpaulin_include))]
+pub async fn compare_across_hosts(
+    series: &Series,
+    collection: &Collection<Snapshot>,
+) -> Result<(), ServiceError> {
+    println!(
+        "Please input the version or label on {} or 0 - to choose the last one: ",
+        series.host
+    );
+    let version_1 = snapshot_labels::read_version(series, collection).await?;
+
+    let Some(other) = choose_host(series, collection).await? else {
+        return Ok(());
+    };
+    println!(
+        "Please input the version or label on {} or 0 - to choose the last one: ",
+        other.host
+    );
+    let version_2 = snapshot_labels::read_version(&other, collection).await?;
 
-        if Path::exists(&file_path) {
-            fs::remove_file(&file_path).expect("File Error");
-        }
+    let snapshot_1 = get_snapshot(series, collection, version_1).await?;
+    let snapshot_2 = get_snapshot(&other, collection, version_2).await?;
+
+    show_comparison(snapshot_1, snapshot_2)
+}
 
-        if selection == 1 {
-            println!("Snapshot comparison: ");
-            snapshot_comparison::compare_snapshots(
-                &snapshot_1,
-                &snapshot_2,
-                &mut path,
-                print_on_the_screen,
-                &Mode::ScreenMode,
-                &file_path.to_str().unwrap(),
-            );
-        } else {
-            println!("Now you can see snapshot comparison in result.txt");
-            snapshot_comparison::compare_snapshots(
-                &snapshot_1,
-                &snapshot_2,
-                &mut path,
-                print_on_the_screen,
-                &Mode::FileMode,
-                &file_path.to_str().unwrap(),
-            );
+/// Lets the user pick one of the hosts that have snapshots of the path of
+/// `series`.
+#[cfg(not(tarpaulin_include))]
+pub async fn choose_host(
+    series: &Series,
+    collection: &Collection<Snapshot>,
+) -> Result<Option<Series>, ServiceError> {
+    let hosts = get_hosts(&series.path, collection).await?;
+
+    if hosts.is_empty() {
+        println!("There are no snapshots of this path yet");
+        return Ok(None);
+    }
+
+    println!("Please choose the host:");
+    for (index, host) in hosts.iter().enumerate() {
+        println!("{}. {host}", index + 1);
+    }
+
+    let index = usize::try_from(common::get_input() - 1).ok();
+    match index.and_then(|index| hosts.get(index)) {
+        Some(host) => Ok(Some(Series::create(host, &series.path))),
+        None => {
+            println!("Incorrect input. Please repeat your attempt");
+            Ok(None)
         }
+    }
+}
+
+/// Hosts that have snapshots of `path`, sorted by name.
+pub async fn get_hosts(
+    path: &str,
+    collection: &Collection<Snapshot>,
+) -> Result<Vec<String>, ServiceError> {
+    let hosts = match collection.distinct("host", doc! {"path": path}, None).await {
+        Ok(hosts) => hosts,
+        Err(_) => return Err(ServiceError::FailedToFoundCollection),
     };
 
-    Ok(())
+    let mut hosts: Vec<String> = hosts
+        .iter()
+        .filter_map(|host| host.as_str().map(String::from))
+        .collect();
+    hosts.sort();
+
+    Ok(hosts)
 }
 
-pub fn print_on_the_screen() {}
+#[cfg(not(tarpaulin_include))]
+fn show_comparison(
+    snapshot_1: Option<Snapshot>,
+    snapshot_2: Option<Snapshot>,
+) -> Result<(), ServiceError> {
+    let (Some(mut snapshot_1), Some(mut snapshot_2)) = (snapshot_1, snapshot_2) else {
+        println!("Sorry, but we can't find snapshot. Please check versions");
+        return Ok(());
+    };
+
+    println!(
+        "Please input a filter expression to compare only matching entries or leave it empty:"
+    );
+    if let Some(filter) = filter::read_filter()? {
+        snapshot_1 = filter::filter_snapshot(&snapshot_1, &filter);
+        snapshot_2 = filter::filter_snapshot(&snapshot_2, &filter);
+    }
+
+    println!("Limit the changes by size, kind or directory and summarize them? 1 - yes else no");
+    if get_input() == 1 {
+        let options = snapshot_diff::read_options()?;
+        let report = snapshot_diff::diff_report(&snapshot_1, &snapshot_2, None, &options);
+        snapshot_diff::print_report(&report, common::use_color(false));
+        return Ok(());
+    }
+    let mut path = PathBuf::new();
+    println!("Select the display option: 1 - show comparison on the screen, 2 - save an HTML report (result.html) else upload comparison to the file (result.txt)");
+    let selection = get_input();
+
+    if selection == 2 {
+        let report = snapshot_diff::diff_report(
+            &snapshot_1,
+            &snapshot_2,
+            None,
+            &snapshot_diff::DiffOptions::default(),
+        );
+        snapshot_html::write_html(&report, &snapshot_2.path, Path::new("result.html"))?;
+        println!("Now you can open the comparison report in result.html");
+        return Ok(());
+    }
+
+    let mut file_path = PathBuf::from(&path);
+    file_path.push("result.txt");
+
+    if Path::exists(&file_path) {
+        fs::remove_file(&file_path).expect("File Error");
+    }
+
+    if selection == 1 {
+        let report = snapshot_diff::diff_report(
+            &snapshot_1,
+            &snapshot_2,
+            None,
+            &snapshot_diff::DiffOptions::default(),
+        );
+        snapshot_diff::print_report(&report, common::use_color(false));
+    } else {
+        println!("Now you can see snapshot comparison in result.txt");
+        snapshot_comparison::compare_snapshots(
+            &snapshot_1,
+            &snapshot_2,
+            &mut path,
+            file_path.to_str().unwrap(),
+        );
+    }
+
+    Ok(())
+}
 
 #[cfg(test)]
 mod tests {
+    use chrono::DateTime;
     use mongodb::Collection;
     use std::env;
 
-    use crate::{commands::snapshot_gallery::get_snapshot, db, snapshot::Snapshot};
+    use crate::{
+        commands::snapshot_gallery::get_snapshot,
+        db,
+        snapshot::{Series, Snapshot},
+    };
 
     fn set_env_variables() {
         env::set_var("DB_NAME", "snapshots_gallery");
@@ -198,18 +434,31 @@ mod tests {
             db.collection(&env::var("COLL_NAME").expect("COLL_NAME must be set"));
         let version = 1;
 
-        let result = get_snapshot(path, &collection, version)
+        let result = get_snapshot(&Series::local(path), &collection, version)
             .await
             .unwrap()
             .unwrap();
 
         let test_value = Snapshot {
             version: 1,
-            date: String::from("date"),
+            date: DateTime::UNIX_EPOCH,
+            utc_offset: 0,
             path: String::from("test"),
             size_kb: 0,
             files: Vec::new(),
             dirs: Vec::new(),
+            root_digest: None,
+            tree: None,
+            unchanged_since: None,
+            label: None,
+            note: None,
+            tags: Vec::new(),
+            host: None,
+            machine_id: None,
+            user: None,
+            tool_version: None,
+            file_count: None,
+            dir_count: None,
         };
 
         assert_eq!(result, test_value);
@@ -225,18 +474,31 @@ mod tests {
             db.collection(&env::var("COLL_NAME").expect("COLL_NAME must be set"));
         let version = 0;
 
-        let result = get_snapshot(path, &collection, version)
+        let result = get_snapshot(&Series::local(path), &collection, version)
             .await
             .unwrap()
             .unwrap();
 
         let test_value = Snapshot {
             version: 1,
-            date: String::from("date"),
+            date: DateTime::UNIX_EPOCH,
+            utc_offset: 0,
             path: String::from("test"),
             size_kb: 0,
             files: Vec::new(),
             dirs: Vec::new(),
+            root_digest: None,
+            tree: None,
+            unchanged_since: None,
+            label: None,
+            note: None,
+            tags: Vec::new(),
+            host: None,
+            machine_id: None,
+            user: None,
+            tool_version: None,
+            file_count: None,
+            dir_count: None,
         };
 
         assert_eq!(result, test_value);
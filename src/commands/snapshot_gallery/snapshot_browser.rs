@@ -0,0 +1,655 @@
+use std::collections::HashSet;
+use std::env;
+use std::path::Path;
+
+use mongodb::{Collection, Database};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+
+use crate::cli::Args;
+use crate::common::format_size;
+use crate::errors::ServiceError;
+use crate::snapshot::{self, Directory, File, Series, Snapshot};
+
+use super::get_snapshot;
+use super::snapshot_comparison::{self, Change, ChangeKind};
+use super::snapshot_listing::{self, VersionSummary};
+use super::snapshot_usage;
+
+const USAGE_TOP: usize = 20;
+
+const HELP: &str =
+    "q quit | Tab switch pane | Enter open | m mark base | d diff with base | t tree | v diff | u usage";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pane {
+    Versions,
+    Content,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum View {
+    Tree,
+    Diff,
+    Usage,
+}
+
+/// Work a key asks for that needs the store.
+#[derive(Debug, PartialEq)]
+pub enum Action {
+    Nothing,
+    Quit,
+    LoadTree(i32),
+    LoadDiff(i32, i32),
+}
+
+/// Visible line of the tree browser.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TreeRow {
+    pub path: String,
+    pub name: String,
+    pub depth: usize,
+    pub is_dir: bool,
+    pub bytes: u64,
+    pub expanded: bool,
+}
+
+/// State of the full-screen browser, kept apart from the terminal so that
+/// key handling can be tested.
+#[derive(Debug)]
+pub struct Browser {
+    pub versions: Vec<VersionSummary>,
+    pub selected: usize,
+    pub focus: Pane,
+    pub view: View,
+    pub snapshot: Option<Snapshot>,
+    pub expanded: HashSet<String>,
+    pub rows: Vec<TreeRow>,
+    pub cursor: usize,
+    /// Version marked with `m` as the left side of the diff.
+    pub base: Option<i32>,
+    pub diff: Option<(i32, i32, Vec<Change>)>,
+    pub scroll: usize,
+    /// Disk usage report of the open version, made when first shown.
+    pub usage: Option<String>,
+    pub status: String,
+}
+
+impl Browser {
+    pub fn new(versions: Vec<VersionSummary>) -> Browser {
+        Browser {
+            selected: versions.len().saturating_sub(1),
+            versions,
+            focus: Pane::Versions,
+            view: View::Tree,
+            snapshot: None,
+            expanded: HashSet::new(),
+            rows: Vec::new(),
+            cursor: 0,
+            base: None,
+            diff: None,
+            scroll: 0,
+            usage: None,
+            status: String::from(HELP),
+        }
+    }
+
+    pub fn handle_key(&mut self, key: KeyCode) -> Action {
+        match key {
+            KeyCode::Char('q') | KeyCode::Esc => return Action::Quit,
+            KeyCode::Tab => {
+                self.focus = match self.focus {
+                    Pane::Versions => Pane::Content,
+                    Pane::Content => Pane::Versions,
+                };
+            }
+            KeyCode::Char('t') => self.view = View::Tree,
+            KeyCode::Char('v') if self.diff.is_some() => {
+                self.view = View::Diff;
+                self.scroll = 0;
+            }
+            KeyCode::Char('u') => match &self.snapshot {
+                Some(snapshot) => {
+                    if self.usage.is_none() {
+                        let report = snapshot_usage::usage_report(snapshot, USAGE_TOP);
+                        self.usage = Some(snapshot_usage::render_report(&report));
+                    }
+                    self.view = View::Usage;
+                    self.focus = Pane::Content;
+                    self.scroll = 0;
+                }
+                None => self.status = String::from("Open a version with Enter first"),
+            },
+            KeyCode::Char('m') => {
+                let version = self.selected_version();
+                self.base = version;
+                if let Some(version) = version {
+                    self.status = format!("Version {version} is the base of the diff");
+                }
+            }
+            KeyCode::Char('d') => match (self.base, self.selected_version()) {
+                (Some(base), Some(version)) if base != version => {
+                    return Action::LoadDiff(base, version);
+                }
+                _ => self.status = String::from("Mark another version with m first"),
+            },
+            _ => {
+                return match self.focus {
+                    Pane::Versions => self.handle_versions_key(key),
+                    Pane::Content => {
+                        self.handle_content_key(key);
+                        Action::Nothing
+                    }
+                }
+            }
+        }
+
+        Action::Nothing
+    }
+
+    fn handle_versions_key(&mut self, key: KeyCode) -> Action {
+        match key {
+            KeyCode::Up | KeyCode::Char('k') => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Down | KeyCode::Char('j') => {
+                self.selected = (self.selected + 1).min(self.versions.len().saturating_sub(1));
+            }
+            KeyCode::Enter => {
+                if let Some(version) = self.selected_version() {
+                    return Action::LoadTree(version);
+                }
+            }
+            _ => {}
+        }
+
+        Action::Nothing
+    }
+
+    fn handle_content_key(&mut self, key: KeyCode) {
+        if self.view != View::Tree {
+            let lines = match self.view {
+                View::Diff => self.diff.as_ref().map_or(0, |diff| diff.2.len()),
+                _ => self.usage.as_ref().map_or(0, |usage| usage.lines().count()),
+            };
+            let last = lines.saturating_sub(1);
+            match key {
+                KeyCode::Up | KeyCode::Char('k') => self.scroll = self.scroll.saturating_sub(1),
+                KeyCode::Down | KeyCode::Char('j') => self.scroll = (self.scroll + 1).min(last),
+                _ => {}
+            }
+            return;
+        }
+
+        let Some(row) = self.rows.get(self.cursor).cloned() else {
+            return;
+        };
+        match key {
+            KeyCode::Up | KeyCode::Char('k') => self.cursor = self.cursor.saturating_sub(1),
+            KeyCode::Down | KeyCode::Char('j') => {
+                self.cursor = (self.cursor + 1).min(self.rows.len() - 1);
+            }
+            KeyCode::Enter | KeyCode::Char(' ') if row.is_dir => {
+                if !self.expanded.remove(&row.path) {
+                    self.expanded.insert(row.path);
+                }
+                self.refresh_rows();
+            }
+            KeyCode::Right | KeyCode::Char('l') if row.is_dir => {
+                self.expanded.insert(row.path);
+                self.refresh_rows();
+            }
+            KeyCode::Left | KeyCode::Char('h') => {
+                // Collapses the directory, or the one containing the entry.
+                let path = if row.expanded {
+                    row.path
+                } else {
+                    match row.path.rsplit_once('/') {
+                        Some((parent, _)) => String::from(parent),
+                        None => return,
+                    }
+                };
+                self.expanded.remove(&path);
+                self.refresh_rows();
+                self.cursor = self
+                    .rows
+                    .iter()
+                    .position(|row| row.path == path)
+                    .unwrap_or(0);
+            }
+            _ => {}
+        }
+    }
+
+    fn selected_version(&self) -> Option<i32> {
+        self.versions
+            .get(self.selected)
+            .map(|summary| summary.version)
+    }
+
+    pub fn set_snapshot(&mut self, snapshot: Snapshot) {
+        self.status = format!("Version {} of {}", snapshot.version, snapshot.path);
+        self.snapshot = Some(snapshot);
+        self.usage = None;
+        self.expanded.clear();
+        self.cursor = 0;
+        self.focus = Pane::Content;
+        self.view = View::Tree;
+        self.refresh_rows();
+    }
+
+    pub fn set_diff(&mut self, base: i32, version: i32, changes: Vec<Change>) {
+        self.status = format!("{} changes from version {base} to {version}", changes.len());
+        self.diff = Some((base, version, changes));
+        self.scroll = 0;
+        self.focus = Pane::Content;
+        self.view = View::Diff;
+    }
+
+    fn refresh_rows(&mut self) {
+        self.rows.clear();
+        if let Some(snapshot) = &self.snapshot {
+            tree_rows(
+                &snapshot.files,
+                &snapshot.dirs,
+                &self.expanded,
+                "",
+                0,
+                &mut self.rows,
+            );
+        }
+        self.cursor = self.cursor.min(self.rows.len().saturating_sub(1));
+    }
+}
+
+/// Flattens the listing into the lines shown by the tree browser, going
+/// into the directories whose path is in `expanded`.
+pub fn tree_rows(
+    files: &[File],
+    dirs: &[Directory],
+    expanded: &HashSet<String>,
+    prefix: &str,
+    depth: usize,
+    rows: &mut Vec<TreeRow>,
+) {
+    let mut dirs: Vec<&Directory> = dirs.iter().collect();
+    dirs.sort_by(|a, b| a.name.cmp(&b.name));
+    let mut files: Vec<&File> = files.iter().collect();
+    files.sort_by(|a, b| a.name.cmp(&b.name));
+
+    for dir in dirs {
+        let path = format!("{prefix}{}", dir.name);
+        let is_expanded = expanded.contains(&path);
+        rows.push(TreeRow {
+            path: path.clone(),
+            name: dir.name.clone(),
+            depth,
+            is_dir: true,
+            bytes: snapshot::total_bytes(&dir.files, &dir.dirs),
+            expanded: is_expanded,
+        });
+        if is_expanded {
+            tree_rows(
+                &dir.files,
+                &dir.dirs,
+                expanded,
+                &format!("{path}/"),
+                depth + 1,
+                rows,
+            );
+        }
+    }
+
+    for file in files {
+        rows.push(TreeRow {
+            path: format!("{prefix}{}", file.name),
+            name: file.name.clone(),
+            depth,
+            is_dir: false,
+            bytes: file.size_kb,
+            expanded: false,
+        });
+    }
+}
+
+/// Left and right cells of a diff line: the entry in the base version and
+/// in the compared one.
+pub fn diff_cells(change: &Change) -> (String, String) {
+    let describe = |size: Option<u64>| {
+        size.map_or(String::new(), |size| {
+            let slash = if change.is_dir { "/" } else { "" };
+            format!("{}{slash} ({})", change.path, format_size(size))
+        })
+    };
+
+    (describe(change.old_size), describe(change.new_size))
+}
+
+fn change_style(kind: ChangeKind) -> Style {
+    match kind {
+        ChangeKind::Added => Style::default().fg(Color::Green),
+        ChangeKind::Removed => Style::default().fg(Color::Red),
+        ChangeKind::Changed => Style::default().fg(Color::Yellow),
+    }
+}
+
+fn pane_block(title: String, focused: bool) -> Block<'static> {
+    let style = if focused {
+        Style::default().fg(Color::Cyan)
+    } else {
+        Style::default()
+    };
+
+    Block::default()
+        .borders(Borders::ALL)
+        .border_style(style)
+        .title(title)
+}
+
+pub fn draw(frame: &mut Frame, browser: &Browser) {
+    let [main, status] =
+        Layout::vertical([Constraint::Min(1), Constraint::Length(1)]).areas(frame.area());
+    let [versions, content] =
+        Layout::horizontal([Constraint::Length(36), Constraint::Min(1)]).areas(main);
+    let highlight = Style::default().add_modifier(Modifier::REVERSED);
+
+    let items: Vec<ListItem> = browser
+        .versions
+        .iter()
+        .map(|summary| {
+            let mark = if browser.base == Some(summary.version) {
+                "*"
+            } else {
+                " "
+            };
+            let date = summary
+                .date
+                .as_deref()
+                .map_or("", |date| &date[..date.len().min(16)]);
+            let label = summary.label.as_deref().unwrap_or_default();
+            ListItem::new(format!(
+                "{mark}{:>4} {} {label}",
+                summary.version,
+                date.replace('T', " ")
+            ))
+        })
+        .collect();
+    let mut state = ListState::default().with_selected(Some(browser.selected));
+    frame.render_stateful_widget(
+        List::new(items)
+            .block(pane_block(
+                String::from("Versions"),
+                browser.focus == Pane::Versions,
+            ))
+            .highlight_style(highlight),
+        versions,
+        &mut state,
+    );
+
+    let focused = browser.focus == Pane::Content;
+    match (browser.view, &browser.diff) {
+        (View::Usage, _) => {
+            let title = browser.snapshot.as_ref().map_or(String::new(), |snapshot| {
+                format!("Disk usage of version {}", snapshot.version)
+            });
+            frame.render_widget(
+                Paragraph::new(browser.usage.as_deref().unwrap_or_default())
+                    .block(pane_block(title, focused))
+                    .scroll((u16::try_from(browser.scroll).unwrap_or(u16::MAX), 0)),
+                content,
+            );
+        }
+        (View::Diff, Some((base, version, changes))) => {
+            let [left, right] =
+                Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)])
+                    .areas(content);
+            let (old, new): (Vec<ListItem>, Vec<ListItem>) = changes
+                .iter()
+                .map(|change| {
+                    let (old, new) = diff_cells(change);
+                    let style = change_style(change.kind);
+                    (
+                        ListItem::new(old).style(style),
+                        ListItem::new(new).style(style),
+                    )
+                })
+                .unzip();
+
+            for (items, area, title) in [
+                (old, left, format!("Version {base}")),
+                (new, right, format!("Version {version}")),
+            ] {
+                let mut state = ListState::default().with_selected(Some(browser.scroll));
+                frame.render_stateful_widget(
+                    List::new(items)
+                        .block(pane_block(title, focused))
+                        .highlight_style(highlight),
+                    area,
+                    &mut state,
+                );
+            }
+        }
+        _ => {
+            let title = browser.snapshot.as_ref().map_or(
+                String::from("Press Enter on a version to open it"),
+                |snapshot| format!("Version {}", snapshot.version),
+            );
+            let items: Vec<ListItem> = browser
+                .rows
+                .iter()
+                .map(|row| {
+                    let marker = match (row.is_dir, row.expanded) {
+                        (true, true) => "▾ ",
+                        (true, false) => "▸ ",
+                        _ => "  ",
+                    };
+                    ListItem::new(Line::from(format!(
+                        "{}{marker}{} ({})",
+                        "  ".repeat(row.depth),
+                        row.name,
+                        format_size(row.bytes)
+                    )))
+                })
+                .collect();
+            let mut state = ListState::default().with_selected(Some(browser.cursor));
+            frame.render_stateful_widget(
+                List::new(items)
+                    .block(pane_block(title, focused))
+                    .highlight_style(highlight),
+                content,
+                &mut state,
+            );
+        }
+    }
+
+    frame.render_widget(Paragraph::new(browser.status.as_str()), status);
+}
+
+#[cfg(not(tarpaulin_include))]
+pub async fn browse(
+    series: &Series,
+    collection: &Collection<Snapshot>,
+) -> Result<(), ServiceError> {
+    let versions = snapshot_listing::load_summaries(series, collection).await?;
+    if versions.is_empty() {
+        println!("There are no versions to show");
+        return Ok(());
+    }
+
+    let mut browser = Browser::new(versions);
+    let mut terminal = ratatui::try_init().or(Err(ServiceError::FailedToUseTerminal))?;
+    let result = run(&mut terminal, &mut browser, series, collection).await;
+    ratatui::restore();
+
+    result
+}
+
+/// `browse [path] [--host=H]`
+#[cfg(not(tarpaulin_include))]
+pub async fn browse_command(args: &Args, path: &Path, db: &Database) -> Result<(), ServiceError> {
+    let series = snapshot_listing::get_series(args, path)?;
+    let collection: Collection<Snapshot> =
+        db.collection(&env::var("COLL_NAME").expect("COLL_NAME must be set"));
+
+    browse(&series, &collection).await
+}
+
+#[cfg(not(tarpaulin_include))]
+async fn run(
+    terminal: &mut DefaultTerminal,
+    browser: &mut Browser,
+    series: &Series,
+    collection: &Collection<Snapshot>,
+) -> Result<(), ServiceError> {
+    loop {
+        if terminal.draw(|frame| draw(frame, browser)).is_err() {
+            return Err(ServiceError::FailedToUseTerminal);
+        }
+
+        let Event::Key(key) = event::read().or(Err(ServiceError::FailedToUseTerminal))? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+
+        match browser.handle_key(key.code) {
+            Action::Nothing => {}
+            Action::Quit => return Ok(()),
+            Action::LoadTree(version) => match get_snapshot(series, collection, version).await? {
+                Some(snapshot) => browser.set_snapshot(snapshot),
+                None => browser.status = format!("Version {version} isn't found"),
+            },
+            Action::LoadDiff(base, version) => {
+                let old = get_snapshot(series, collection, base).await?;
+                let new = get_snapshot(series, collection, version).await?;
+                match (old, new) {
+                    (Some(old), Some(new)) => {
+                        let changes = snapshot_comparison::diff_trees(&old, &new);
+                        browser.set_diff(base, version, changes);
+                    }
+                    _ => browser.status = String::from("One of the versions isn't found"),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ratatui::backend::TestBackend;
+    use ratatui::crossterm::event::KeyCode;
+    use ratatui::Terminal;
+
+    use crate::commands::snapshot_gallery::snapshot_comparison::{Change, ChangeKind};
+    use crate::commands::snapshot_gallery::snapshot_listing::summarize;
+    use crate::snapshot::fixtures::{self, create_dir, create_file};
+    use crate::snapshot::Snapshot;
+
+    use super::{draw, Action, Browser, Pane, View};
+
+    fn create_browser() -> Browser {
+        let versions = [1, 2]
+            .into_iter()
+            .map(|version| summarize(&mongodb::bson::doc! {"version": version}))
+            .collect();
+        Browser::new(versions)
+    }
+
+    fn create_snapshot() -> Snapshot {
+        let file = |name: &str| create_file(name, 10);
+        fixtures::create_snapshot(
+            2,
+            "/data",
+            Vec::from([file("readme")]),
+            Vec::from([create_dir(
+                "src",
+                Vec::from([file("main.rs"), file("lib.rs")]),
+                Vec::new(),
+            )]),
+        )
+    }
+
+    #[test]
+    fn test_versions_keys() {
+        let mut browser = create_browser();
+
+        assert_eq!(browser.handle_key(KeyCode::Enter), Action::LoadTree(2));
+        assert_eq!(browser.handle_key(KeyCode::Char('d')), Action::Nothing);
+        browser.handle_key(KeyCode::Char('m'));
+        browser.handle_key(KeyCode::Up);
+        assert_eq!(
+            browser.handle_key(KeyCode::Char('d')),
+            Action::LoadDiff(2, 1)
+        );
+        assert_eq!(browser.handle_key(KeyCode::Char('q')), Action::Quit);
+    }
+
+    #[test]
+    fn test_expand_and_collapse_tree() {
+        let mut browser = create_browser();
+        browser.set_snapshot(create_snapshot());
+        assert_eq!(browser.focus, Pane::Content);
+        assert_eq!(browser.rows.len(), 2);
+
+        browser.handle_key(KeyCode::Enter);
+        let paths: Vec<&str> = browser.rows.iter().map(|row| row.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            Vec::from(["src", "src/lib.rs", "src/main.rs", "readme"])
+        );
+
+        browser.handle_key(KeyCode::Down);
+        browser.handle_key(KeyCode::Left);
+        assert_eq!(browser.rows.len(), 2);
+        assert_eq!(browser.cursor, 0);
+    }
+
+    #[test]
+    fn test_usage_view() {
+        let mut browser = create_browser();
+        browser.handle_key(KeyCode::Char('u'));
+        assert_eq!(browser.view, View::Tree);
+
+        browser.set_snapshot(create_snapshot());
+        browser.handle_key(KeyCode::Char('u'));
+        assert_eq!(browser.view, View::Usage);
+        assert!(browser.usage.as_deref().unwrap().contains("30 B"));
+
+        browser.handle_key(KeyCode::Down);
+        assert_eq!(browser.scroll, 1);
+    }
+
+    #[test]
+    fn test_draw_diff() {
+        let mut browser = create_browser();
+        browser.set_diff(
+            1,
+            2,
+            Vec::from([Change {
+                path: String::from("src/new.rs"),
+                kind: ChangeKind::Added,
+                is_dir: false,
+                old_size: None,
+                new_size: Some(2048),
+            }]),
+        );
+        assert_eq!(browser.view, View::Diff);
+
+        let mut terminal = Terminal::new(TestBackend::new(120, 10)).unwrap();
+        terminal.draw(|frame| draw(frame, &browser)).unwrap();
+        let screen: String = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|cell| cell.symbol())
+            .collect();
+
+        assert!(screen.contains("Version 1"));
+        assert!(screen.contains("src/new.rs (2.0 KiB)"));
+        assert!(screen.contains("1 changes from version 1 to 2"));
+    }
+}
@@ -1,26 +1,23 @@
+use std::collections::HashMap;
 use std::path::PathBuf;
 
-use crate::snapshot::{Comparison, Info};
+use serde::Serialize;
 
-#[derive(PartialEq)]
-pub enum Mode {
-    ScreenMode,
-    FileMode,
-}
+use crate::common::append_to_file;
+use crate::snapshot::{self, Comparison, Directory, File, Info};
 
+/// Writes the size changes and deletions from `snap_1` to `snap_2` to
+/// `result_file`, one sentence per line.
 pub fn compare_snapshots<T: Comparison>(
     snap_1: &T,
     snap_2: &T,
     path: &mut PathBuf,
-    output_fn: fn(),
-    mode: &Mode,
     result_file: &str,
 ) {
     compare_size(
         snap_1.get_size(),
         snap_2.get_size(),
         "snapshot",
-        &mode,
         result_file,
     );
 
@@ -28,17 +25,11 @@ pub fn compare_snapshots<T: Comparison>(
         let index = find_item(&file.name, snap_2.get_files());
         if index != -1 {
             let new_file = &snap_2.get_files()[index as usize];
-            compare_size(
-                file.size_kb,
-                new_file.size_kb,
-                &file.name,
-                &mode,
-                result_file,
-            );
+            compare_size(file.size_kb, new_file.size_kb, &file.name, result_file);
         } else {
             let mut new_path = PathBuf::from(path.to_str().unwrap().trim());
             new_path.push(&file.name);
-            print_file_del(&mode, &new_path.to_str().unwrap(), result_file);
+            print_file_del(new_path.to_str().unwrap(), result_file);
         }
     }
 
@@ -46,15 +37,267 @@ pub fn compare_snapshots<T: Comparison>(
         let index = find_item(&dir.name, snap_2.get_dirs());
         if index != -1 {
             let new_dir = &snap_2.get_dirs()[index as usize];
-            compare_size(dir.size_kb, new_dir.size_kb, &dir.name, &mode, result_file);
+            // Directories loaded from the deduplicated storage carry their
+            // digest, so identical subtrees are skipped without walking them.
+            if dir.hash.is_some() && dir.hash == new_dir.hash {
+                continue;
+            }
+            compare_size(dir.size_kb, new_dir.size_kb, &dir.name, result_file);
             path.push(dir.get_name());
-            compare_snapshots(dir, new_dir, path, output_fn, &mode, result_file);
+            compare_snapshots(dir, new_dir, path, result_file);
         } else {
             let mut new_path = PathBuf::from(path.to_str().unwrap().trim());
             new_path.push(&dir.name);
-            print_dir_del(&mode, &new_path.to_str().unwrap(), result_file);
+            print_dir_del(new_path.to_str().unwrap(), result_file);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Changed,
+}
+
+/// Difference of one entry between two trees. `path` is relative to the
+/// root and sizes are in bytes.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Change {
+    pub path: String,
+    pub kind: ChangeKind,
+    pub is_dir: bool,
+    pub old_size: Option<u64>,
+    pub new_size: Option<u64>,
+}
+
+/// Lists the entries added, removed or changed between `old` and `new`,
+/// ordered by path. Added and removed directories are reported once,
+/// without their content; a changed directory precedes its changes.
+pub fn diff_trees<T: Comparison>(old: &T, new: &T) -> Vec<Change> {
+    let mut changes = Vec::new();
+    diff_level(
+        old.get_files(),
+        old.get_dirs(),
+        new.get_files(),
+        new.get_dirs(),
+        "",
+        &mut changes,
+    );
+    changes.sort_by(|a, b| a.path.split('/').cmp(b.path.split('/')));
+    changes
+}
+
+fn diff_level(
+    old_files: &[File],
+    old_dirs: &[Directory],
+    new_files: &[File],
+    new_dirs: &[Directory],
+    prefix: &str,
+    changes: &mut Vec<Change>,
+) -> bool {
+    let count = changes.len();
+    let join = |name: &str| format!("{prefix}{name}");
+
+    for file in old_files {
+        match new_files.iter().find(|new_file| new_file.name == file.name) {
+            Some(new_file) if file_changed(file, new_file) => changes.push(Change {
+                path: join(&file.name),
+                kind: ChangeKind::Changed,
+                is_dir: false,
+                old_size: Some(file.size_kb),
+                new_size: Some(new_file.size_kb),
+            }),
+            Some(_) => {}
+            None => changes.push(Change {
+                path: join(&file.name),
+                kind: ChangeKind::Removed,
+                is_dir: false,
+                old_size: Some(file.size_kb),
+                new_size: None,
+            }),
+        }
+    }
+    for file in new_files {
+        if !old_files.iter().any(|old_file| old_file.name == file.name) {
+            changes.push(Change {
+                path: join(&file.name),
+                kind: ChangeKind::Added,
+                is_dir: false,
+                old_size: None,
+                new_size: Some(file.size_kb),
+            });
+        }
+    }
+
+    for dir in old_dirs {
+        let old_size = snapshot::total_bytes(&dir.files, &dir.dirs);
+        match new_dirs.iter().find(|new_dir| new_dir.name == dir.name) {
+            // Identical subtrees from the deduplicated storage are skipped
+            // without walking them.
+            Some(new_dir) if dir.hash.is_some() && dir.hash == new_dir.hash => {}
+            Some(new_dir) => {
+                let index = changes.len();
+                let path = join(&dir.name);
+                if diff_level(
+                    &dir.files,
+                    &dir.dirs,
+                    &new_dir.files,
+                    &new_dir.dirs,
+                    &format!("{path}/"),
+                    changes,
+                ) {
+                    changes.insert(
+                        index,
+                        Change {
+                            path,
+                            kind: ChangeKind::Changed,
+                            is_dir: true,
+                            old_size: Some(old_size),
+                            new_size: Some(snapshot::total_bytes(&new_dir.files, &new_dir.dirs)),
+                        },
+                    );
+                }
+            }
+            None => changes.push(Change {
+                path: join(&dir.name),
+                kind: ChangeKind::Removed,
+                is_dir: true,
+                old_size: Some(old_size),
+                new_size: None,
+            }),
         }
     }
+    for dir in new_dirs {
+        if !old_dirs.iter().any(|old_dir| old_dir.name == dir.name) {
+            changes.push(Change {
+                path: join(&dir.name),
+                kind: ChangeKind::Added,
+                is_dir: true,
+                old_size: None,
+                new_size: Some(snapshot::total_bytes(&dir.files, &dir.dirs)),
+            });
+        }
+    }
+
+    changes.len() > count
+}
+
+/// How an entry changed from a base version in two versions derived
+/// from it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MergeKind {
+    OnlyInA,
+    OnlyInB,
+    /// Changed in both the same way.
+    Identical,
+    /// Changed in both differently.
+    Conflict,
+}
+
+/// Entry changed from the base in at least one of `a` and `b`. Sizes are
+/// in bytes and `None` where the entry does not exist.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MergeChange {
+    pub path: String,
+    pub kind: MergeKind,
+    pub is_dir: bool,
+    pub base_size: Option<u64>,
+    pub a_size: Option<u64>,
+    pub b_size: Option<u64>,
+}
+
+enum TreeEntry<'a> {
+    File(&'a File),
+    Dir(&'a Directory),
+}
+
+impl TreeEntry<'_> {
+    fn size(&self) -> u64 {
+        match self {
+            TreeEntry::File(file) => file.size_kb,
+            TreeEntry::Dir(dir) => snapshot::total_bytes(&dir.files, &dir.dirs),
+        }
+    }
+}
+
+/// Classifies every entry of the three trees that `a` or `b` changed from
+/// `base`, ordered by path. A directory counts as changed only when it is
+/// added, removed or replaced by a file; changes inside it are listed
+/// entry by entry.
+pub fn compare_three<T: Comparison>(base: &T, a: &T, b: &T) -> Vec<MergeChange> {
+    let trees = [base, a, b].map(|tree| {
+        let mut entries = HashMap::new();
+        flatten(tree.get_files(), tree.get_dirs(), "", &mut entries);
+        entries
+    });
+
+    let mut paths: Vec<&String> = trees.iter().flat_map(|tree| tree.keys()).collect();
+    paths.sort_by(|x, y| x.split('/').cmp(y.split('/')));
+    paths.dedup();
+
+    let mut changes = Vec::new();
+    for path in paths {
+        let [in_base, in_a, in_b] = [0, 1, 2].map(|index| trees[index].get(path));
+
+        let kind = match (entry_changed(in_base, in_a), entry_changed(in_base, in_b)) {
+            (false, false) => continue,
+            (true, false) => MergeKind::OnlyInA,
+            (false, true) => MergeKind::OnlyInB,
+            (true, true) if !entry_changed(in_a, in_b) => MergeKind::Identical,
+            (true, true) => MergeKind::Conflict,
+        };
+
+        changes.push(MergeChange {
+            path: path.clone(),
+            kind,
+            is_dir: [in_a, in_b, in_base]
+                .into_iter()
+                .flatten()
+                .next()
+                .is_some_and(|entry| matches!(entry, TreeEntry::Dir(_))),
+            base_size: in_base.map(TreeEntry::size),
+            a_size: in_a.map(TreeEntry::size),
+            b_size: in_b.map(TreeEntry::size),
+        });
+    }
+
+    changes
+}
+
+fn flatten<'a>(
+    files: &'a [File],
+    dirs: &'a [Directory],
+    prefix: &str,
+    entries: &mut HashMap<String, TreeEntry<'a>>,
+) {
+    for file in files {
+        entries.insert(format!("{prefix}{}", file.name), TreeEntry::File(file));
+    }
+    for dir in dirs {
+        let path = format!("{prefix}{}", dir.name);
+        flatten(&dir.files, &dir.dirs, &format!("{path}/"), entries);
+        entries.insert(path, TreeEntry::Dir(dir));
+    }
+}
+
+fn entry_changed(old: Option<&TreeEntry>, new: Option<&TreeEntry>) -> bool {
+    match (old, new) {
+        (None, None) => false,
+        (Some(TreeEntry::File(old)), Some(TreeEntry::File(new))) => file_changed(old, new),
+        (Some(TreeEntry::Dir(_)), Some(TreeEntry::Dir(_))) => false,
+        _ => true,
+    }
+}
+
+/// Files differ in size, or in content when both digests are known.
+fn file_changed(old: &File, new: &File) -> bool {
+    old.size_kb != new.size_kb
+        || old.meta.sha256.is_some()
+            && new.meta.sha256.is_some()
+            && old.meta.sha256 != new.meta.sha256
 }
 
 pub fn find_item<T: Info>(item_name: &str, items: &Vec<T>) -> i32 {
@@ -67,61 +310,40 @@ pub fn find_item<T: Info>(item_name: &str, items: &Vec<T>) -> i32 {
 }
 
 #[cfg(not(tarpaulin_include))]
-pub fn compare_size(size_1: u64, size_2: u64, item_name: &str, mode: &Mode, result_file: &str) {
-    use crate::common::append_to_file;
-
-    match mode {
-        Mode::ScreenMode => {
-            if size_1 != size_2 {
-                println!("{item_name} was changed (size: {size_1}kb -> {size_2}kb)");
-            }
-        }
-        Mode::FileMode => {
-            if size_1 != size_2 {
-                append_to_file(
-                    result_file,
-                    &format!("{item_name} was changed (size: {size_1}kb -> {size_2}kb)"),
-                )
-                .expect("File Error");
-            }
-        }
-    };
+pub fn compare_size(size_1: u64, size_2: u64, item_name: &str, result_file: &str) {
+    if size_1 != size_2 {
+        append_to_file(
+            result_file,
+            &format!("{item_name} was changed (size: {size_1}kb -> {size_2}kb)"),
+        )
+        .expect("File Error");
+    }
 }
 
 #[cfg(not(tarpaulin_include))]
-pub fn print_file_del(mode: &Mode, path: &str, result_file: &str) {
-    use crate::common::append_to_file;
-
-    match mode {
-        Mode::ScreenMode => println!("File \"{path}\" was deleted"),
-        Mode::FileMode => {
-            append_to_file(result_file, &format!("File \"{path}\" was deleted"))
-                .expect("File Error");
-        }
-    };
+pub fn print_file_del(path: &str, result_file: &str) {
+    append_to_file(result_file, &format!("File \"{path}\" was deleted")).expect("File Error");
 }
 
 #[cfg(not(tarpaulin_include))]
-pub fn print_dir_del(mode: &Mode, path: &str, result_file: &str) {
-    use crate::common::append_to_file;
-
-    match mode {
-        Mode::ScreenMode => println!("Directory \"{path}\" was deleted"),
-        Mode::FileMode => {
-            append_to_file(result_file, &format!("Directory \"{path}\" was deleted"))
-                .expect("File Error");
-        }
-    }
+pub fn print_dir_del(path: &str, result_file: &str) {
+    append_to_file(result_file, &format!("Directory \"{path}\" was deleted")).expect("File Error");
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{commands::snapshot_gallery::snapshot_comparison::find_item, snapshot::File};
+    use crate::{
+        commands::snapshot_gallery::snapshot_comparison::find_item,
+        snapshot::{Directory, File, Metadata},
+    };
+
+    use super::{compare_three, diff_trees, ChangeKind, MergeKind};
 
     fn create_file(name: &str) -> File {
         File {
             name: String::from(name),
             size_kb: 10,
+            meta: Metadata::default(),
         }
     }
 
@@ -155,4 +377,109 @@ mod tests {
         let result = find_item("fourth", &files);
         assert_eq!(result, -1);
     }
+
+    #[test]
+    fn test_diff_trees() {
+        let create_dir = |name: &str, files: Vec<File>| Directory {
+            name: String::from(name),
+            size_kb: 0,
+            files,
+            dirs: Vec::new(),
+            meta: Metadata::default(),
+            hash: None,
+        };
+        let mut bigger = create_file("second");
+        bigger.size_kb = 20;
+
+        let mut old_root = create_dir("", Vec::from([create_file("first"), create_file("second")]));
+        old_root.dirs = Vec::from([
+            create_dir("src", Vec::from([create_file("main")])),
+            create_dir("gone", Vec::new()),
+        ]);
+        let mut new_root = create_dir("", Vec::from([bigger, create_file("third")]));
+        new_root.dirs = Vec::from([create_dir(
+            "src",
+            Vec::from([create_file("main"), create_file("lib")]),
+        )]);
+
+        let changes: Vec<(String, ChangeKind, bool)> = diff_trees(&old_root, &new_root)
+            .into_iter()
+            .map(|change| (change.path, change.kind, change.is_dir))
+            .collect();
+
+        assert_eq!(
+            changes,
+            Vec::from([
+                (String::from("first"), ChangeKind::Removed, false),
+                (String::from("gone"), ChangeKind::Removed, true),
+                (String::from("second"), ChangeKind::Changed, false),
+                (String::from("src"), ChangeKind::Changed, true),
+                (String::from("src/lib"), ChangeKind::Added, false),
+                (String::from("third"), ChangeKind::Added, false),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_compare_three() {
+        let sized = |name: &str, size: u64| {
+            let mut file = create_file(name);
+            file.size_kb = size;
+            file
+        };
+        let create_root = |files: Vec<File>| Directory {
+            name: String::new(),
+            size_kb: 0,
+            files,
+            dirs: Vec::new(),
+            meta: Metadata::default(),
+            hash: None,
+        };
+
+        let base = create_root(Vec::from([
+            sized("same", 1),
+            sized("ours", 1),
+            sized("theirs", 1),
+            sized("both", 1),
+            sized("clash", 1),
+            sized("dropped", 1),
+        ]));
+        let a = create_root(Vec::from([
+            sized("same", 1),
+            sized("ours", 2),
+            sized("theirs", 1),
+            sized("both", 3),
+            sized("clash", 4),
+            sized("new", 7),
+        ]));
+        let mut b = create_root(Vec::from([
+            sized("same", 1),
+            sized("ours", 1),
+            sized("theirs", 5),
+            sized("both", 3),
+            sized("clash", 6),
+            sized("dropped", 1),
+        ]));
+        b.dirs = Vec::from([create_root(Vec::from([sized("inner", 2)]))]);
+        b.dirs[0].name = String::from("extra");
+
+        let changes: Vec<(String, MergeKind, bool)> = compare_three(&base, &a, &b)
+            .into_iter()
+            .map(|change| (change.path, change.kind, change.is_dir))
+            .collect();
+
+        assert_eq!(
+            changes,
+            Vec::from([
+                (String::from("both"), MergeKind::Identical, false),
+                (String::from("clash"), MergeKind::Conflict, false),
+                (String::from("dropped"), MergeKind::OnlyInA, false),
+                (String::from("extra"), MergeKind::OnlyInB, true),
+                (String::from("extra/inner"), MergeKind::OnlyInB, false),
+                (String::from("new"), MergeKind::OnlyInA, false),
+                (String::from("ours"), MergeKind::OnlyInA, false),
+                (String::from("theirs"), MergeKind::OnlyInB, false),
+            ])
+        );
+    }
 }
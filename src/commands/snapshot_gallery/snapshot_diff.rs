@@ -0,0 +1,430 @@
+use std::env;
+use std::path::Path;
+
+use mongodb::{Collection, Database};
+use serde::Serialize;
+
+use crate::cli::Args;
+use crate::common::{self, format_delta, format_size};
+use crate::errors::ServiceError;
+use crate::filter::{self, Filter};
+use crate::snapshot::Snapshot;
+
+use super::snapshot_comparison::{self, Change, ChangeKind};
+use super::snapshot_search::Kind;
+use super::{snapshot_html, snapshot_labels, snapshot_listing, snapshot_tree};
+
+const BOLD: &str = "1";
+const GREEN: &str = "32";
+const RED: &str = "31";
+const YELLOW: &str = "33";
+
+/// Limits what a comparison shows.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DiffOptions {
+    /// Smallest size change shown, in bytes.
+    pub min_bytes: Option<u64>,
+    /// Smallest size change shown, in percent of the old size. Added and
+    /// removed entries always pass it.
+    pub min_percent: Option<f64>,
+    pub kind: Option<Kind>,
+    /// Directory the comparison is limited to, relative to the snapshot.
+    pub subtree: Option<String>,
+    /// Shows only the entries directly in the root, or in the subtree.
+    pub collapse: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct DiffSummary {
+    pub added: usize,
+    pub removed: usize,
+    pub changed: usize,
+    /// Size change of the whole comparison, shown or not.
+    pub net_bytes: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DiffReport {
+    pub from: i32,
+    pub to: i32,
+    pub changes: Vec<Change>,
+    pub summary: DiffSummary,
+}
+
+/// Asks how to limit a comparison.
+#[cfg(not(tarpaulin_include))]
+pub fn read_options() -> Result<DiffOptions, ServiceError> {
+    println!("Please input the smallest size change to show (like 1MiB) or leave it empty:");
+    let min_bytes = common::get_string_input();
+    println!("Please input the smallest change to show in percent or 0 to show every change:");
+    let min_percent = common::get_input();
+
+    println!("Show: 1. Files and directories 2. Files 3. Directories");
+    let kind = match common::get_input() {
+        2 => Some(Kind::File),
+        3 => Some(Kind::Dir),
+        _ => None,
+    };
+
+    println!("Please input the directory to compare, relative to the snapshot, or leave it empty for the whole tree:");
+    let subtree = common::get_string_input();
+
+    println!("Collapse the changes to the top-level entries? 1 - yes else no");
+    let collapse = common::get_input() == 1;
+
+    Ok(DiffOptions {
+        min_bytes: match min_bytes.as_str() {
+            "" => None,
+            size => Some(common::parse_size(size).ok_or(ServiceError::InvalidArgument)?),
+        },
+        min_percent: Some(min_percent as f64).filter(|percent| *percent > 0.0),
+        kind,
+        subtree: Some(subtree).filter(|subtree| !subtree.is_empty()),
+        collapse,
+    })
+}
+
+/// `diff [path] --from=V [--to=V] [--host=H] [--filter=EXPR]
+/// [--min-size=SIZE] [--min-percent=N] [--kind=file|dir] [--subtree=DIR]
+/// [--top-level] [--no-color] [--json | --html=FILE]`
+///
+/// `--to` defaults to the last version.
+#[cfg(not(tarpaulin_include))]
+pub async fn diff_command(args: &Args, path: &Path, db: &Database) -> Result<(), ServiceError> {
+    let series = snapshot_listing::get_series(args, path)?;
+    let collection: Collection<Snapshot> =
+        db.collection(&env::var("COLL_NAME").expect("COLL_NAME must be set"));
+
+    let from = args.get("from").ok_or(ServiceError::WrongNumberOfArgs)?;
+    let to = args.get("to").unwrap_or("0");
+    let filter = args.get("filter").map(Filter::parse).transpose()?;
+    let options = DiffOptions {
+        min_bytes: args
+            .get("min-size")
+            .map(|size| common::parse_size(size).ok_or(ServiceError::InvalidArgument))
+            .transpose()?,
+        min_percent: args
+            .get("min-percent")
+            .map(str::parse)
+            .transpose()
+            .or(Err(ServiceError::InvalidArgument))?,
+        kind: args.get("kind").map(str::parse).transpose()?,
+        subtree: args.get("subtree").map(String::from),
+        collapse: args.has_flag("top-level"),
+    };
+
+    let old = snapshot_labels::load_version(&series, &collection, from).await?;
+    let new = snapshot_labels::load_version(&series, &collection, to).await?;
+    let report = diff_report(&old, &new, filter.as_ref(), &options);
+
+    if let Some(file_path) = args.get("html") {
+        let title = format!("{} on {}", series.path, series.host);
+        snapshot_html::write_html(&report, &title, Path::new(file_path))?;
+        println!("Comparison report written to {file_path}");
+    } else if args.has_flag("json") {
+        let json = serde_json::to_string_pretty(&report).or(Err(ServiceError::FailedToExport))?;
+        println!("{json}");
+    } else {
+        print_report(&report, common::use_color(args.has_flag("no-color")));
+    }
+
+    Ok(())
+}
+
+/// Changes from `old` to `new`, looking only at the entries the filter
+/// accepts when one is given.
+pub fn diff_versions(old: &Snapshot, new: &Snapshot, filter: Option<&Filter>) -> Vec<Change> {
+    match filter {
+        Some(filter) => snapshot_comparison::diff_trees(
+            &filter::filter_snapshot(old, filter),
+            &filter::filter_snapshot(new, filter),
+        ),
+        None => snapshot_comparison::diff_trees(old, new),
+    }
+}
+
+pub fn diff_report(
+    old: &Snapshot,
+    new: &Snapshot,
+    filter: Option<&Filter>,
+    options: &DiffOptions,
+) -> DiffReport {
+    let changes = diff_versions(old, new, filter);
+    let summary = summarize(&changes, options);
+
+    DiffReport {
+        from: old.version,
+        to: new.version,
+        changes: apply_options(changes, options),
+        summary,
+    }
+}
+
+/// Depth of the change below the subtree of `options`, entries directly in
+/// it being at depth 1, or `None` outside of it.
+fn depth_in_scope(change: &Change, options: &DiffOptions) -> Option<usize> {
+    let scope = options
+        .subtree
+        .as_deref()
+        .map(snapshot_tree::split_subtree)
+        .unwrap_or_default();
+    let components: Vec<&str> = change.path.split('/').collect();
+
+    if components.len() <= scope.len() || !components.iter().zip(&scope).all(|(a, b)| a == b) {
+        return None;
+    }
+
+    Some(components.len() - scope.len())
+}
+
+fn size_change(change: &Change) -> u64 {
+    change
+        .old_size
+        .unwrap_or(0)
+        .abs_diff(change.new_size.unwrap_or(0))
+}
+
+/// Keeps the changes passing the options, in their order.
+pub fn apply_options(changes: Vec<Change>, options: &DiffOptions) -> Vec<Change> {
+    changes
+        .into_iter()
+        .filter(|change| {
+            let Some(depth) = depth_in_scope(change, options) else {
+                return false;
+            };
+            let kind = if change.is_dir { Kind::Dir } else { Kind::File };
+            let delta = size_change(change);
+            let percent = match (change.kind, change.old_size) {
+                (ChangeKind::Changed, Some(old)) if old > 0 => delta as f64 * 100.0 / old as f64,
+                _ => f64::INFINITY,
+            };
+
+            (!options.collapse || depth == 1)
+                && options.kind.is_none_or(|wanted| wanted == kind)
+                && options.min_bytes.is_none_or(|min| delta >= min)
+                && options.min_percent.is_none_or(|min| percent >= min)
+        })
+        .collect()
+}
+
+/// Counts the changes shown with `options`; the net size change covers the
+/// whole root or subtree.
+pub fn summarize(changes: &[Change], options: &DiffOptions) -> DiffSummary {
+    let mut summary = DiffSummary::default();
+
+    for change in changes {
+        if depth_in_scope(change, options) == Some(1) {
+            summary.net_bytes +=
+                change.new_size.unwrap_or(0) as i64 - change.old_size.unwrap_or(0) as i64;
+        }
+    }
+
+    for change in apply_options(changes.to_vec(), options) {
+        match change.kind {
+            ChangeKind::Added => summary.added += 1,
+            ChangeKind::Removed => summary.removed += 1,
+            ChangeKind::Changed => summary.changed += 1,
+        }
+    }
+
+    summary
+}
+
+#[cfg(not(tarpaulin_include))]
+pub fn print_report(report: &DiffReport, color: bool) {
+    common::print_paged(&render_report(report, color));
+}
+
+fn paint(text: String, code: &str, color: bool) -> String {
+    if color {
+        format!("\x1b[{code}m{text}\x1b[0m")
+    } else {
+        text
+    }
+}
+
+/// Renders the changes like a unified diff: `+ path (size)` for added
+/// entries, `- path` for removed ones and `~ path old -> new` for changed
+/// ones, with paths relative to the snapshot root and directories ending
+/// in `/`. `color` adds ANSI colors.
+pub fn render_report(report: &DiffReport, color: bool) -> String {
+    let mut lines = Vec::from([
+        paint(format!("--- version {}", report.from), BOLD, color),
+        paint(format!("+++ version {}", report.to), BOLD, color),
+    ]);
+
+    for change in &report.changes {
+        let slash = if change.is_dir { "/" } else { "" };
+        let line = match (change.kind, change.old_size, change.new_size) {
+            (ChangeKind::Added, _, new) => paint(
+                format!(
+                    "+ {}{slash} ({})",
+                    change.path,
+                    format_size(new.unwrap_or(0))
+                ),
+                GREEN,
+                color,
+            ),
+            (ChangeKind::Removed, _, _) => paint(format!("- {}{slash}", change.path), RED, color),
+            (ChangeKind::Changed, old, new) => paint(
+                format!(
+                    "~ {}{slash} {} -> {}",
+                    change.path,
+                    format_size(old.unwrap_or(0)),
+                    format_size(new.unwrap_or(0))
+                ),
+                YELLOW,
+                color,
+            ),
+        };
+        lines.push(line);
+    }
+
+    let summary = &report.summary;
+    lines.push(String::new());
+    lines.push(format!(
+        "{} added, {} removed, {} modified, net change {}",
+        summary.added,
+        summary.removed,
+        summary.changed,
+        format_delta(summary.net_bytes)
+    ));
+
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::snapshot_gallery::snapshot_search::Kind;
+    use crate::filter::Filter;
+    use crate::snapshot::fixtures::{self, create_dir, create_file};
+    use crate::snapshot::{File, Snapshot};
+
+    use super::{diff_report, diff_versions, render_report, DiffOptions};
+
+    fn create_files(files: &[(&str, u64)]) -> Vec<File> {
+        files
+            .iter()
+            .map(|(name, size)| create_file(name, *size))
+            .collect()
+    }
+
+    fn create_snapshot(version: i32, files: &[(&str, u64)], logs: &[(&str, u64)]) -> Snapshot {
+        fixtures::create_snapshot(
+            version,
+            "/data",
+            create_files(files),
+            Vec::from([create_dir("logs", create_files(logs), Vec::new())]),
+        )
+    }
+
+    fn create_versions() -> (Snapshot, Snapshot) {
+        (
+            create_snapshot(
+                1,
+                &[("app.bin", 1000), ("notes.txt", 10)],
+                &[("a.log", 100), ("b.log", 5000)],
+            ),
+            create_snapshot(
+                2,
+                &[("app.bin", 1010), ("todo.txt", 20)],
+                &[("a.log", 300), ("b.log", 5001)],
+            ),
+        )
+    }
+
+    fn paths(old: &Snapshot, new: &Snapshot, options: &DiffOptions) -> Vec<String> {
+        diff_report(old, new, None, options)
+            .changes
+            .into_iter()
+            .map(|change| change.path)
+            .collect()
+    }
+
+    #[test]
+    fn test_diff_versions_with_filter() {
+        let (old, new) = create_versions();
+
+        assert_eq!(diff_versions(&old, &new, None).len(), 6);
+
+        let filter = Filter::parse("ext = txt").unwrap();
+        let paths: Vec<String> = diff_versions(&old, &new, Some(&filter))
+            .into_iter()
+            .map(|change| change.path)
+            .collect();
+        assert_eq!(paths, Vec::from(["notes.txt", "todo.txt"]));
+    }
+
+    #[test]
+    fn test_diff_options() {
+        let (old, new) = create_versions();
+
+        let options = DiffOptions {
+            min_bytes: Some(15),
+            ..DiffOptions::default()
+        };
+        assert_eq!(
+            paths(&old, &new, &options),
+            Vec::from(["logs", "logs/a.log", "todo.txt"])
+        );
+
+        let options = DiffOptions {
+            min_percent: Some(50.0),
+            kind: Some(Kind::File),
+            ..DiffOptions::default()
+        };
+        assert_eq!(
+            paths(&old, &new, &options),
+            Vec::from(["logs/a.log", "notes.txt", "todo.txt"])
+        );
+
+        let options = DiffOptions {
+            subtree: Some(String::from("logs")),
+            ..DiffOptions::default()
+        };
+        assert_eq!(
+            paths(&old, &new, &options),
+            Vec::from(["logs/a.log", "logs/b.log"])
+        );
+
+        let options = DiffOptions {
+            collapse: true,
+            ..DiffOptions::default()
+        };
+        assert_eq!(
+            paths(&old, &new, &options),
+            Vec::from(["app.bin", "logs", "notes.txt", "todo.txt"])
+        );
+    }
+
+    #[test]
+    fn test_render_report() {
+        let (old, new) = create_versions();
+        let options = DiffOptions {
+            subtree: Some(String::from("logs")),
+            min_bytes: Some(100),
+            ..DiffOptions::default()
+        };
+        let rendered = render_report(&diff_report(&old, &new, None, &options), false);
+
+        assert_eq!(
+            rendered,
+            "--- version 1\n\
+             +++ version 2\n\
+             ~ logs/a.log 100 B -> 300 B\n\
+             \n\
+             0 added, 0 removed, 1 modified, net change +201 B"
+        );
+
+        let options = DiffOptions {
+            collapse: true,
+            kind: Some(Kind::File),
+            ..DiffOptions::default()
+        };
+        let rendered = render_report(&diff_report(&old, &new, None, &options), true);
+        let lines: Vec<&str> = rendered.lines().collect();
+        assert_eq!(lines[0], "\x1b[1m--- version 1\x1b[0m");
+        assert_eq!(lines[3], "\x1b[31m- notes.txt\x1b[0m");
+        assert_eq!(lines[4], "\x1b[32m+ todo.txt (20 B)\x1b[0m");
+    }
+}
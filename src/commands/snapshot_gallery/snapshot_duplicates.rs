@@ -0,0 +1,422 @@
+use std::collections::HashMap;
+use std::env;
+use std::path::Path;
+
+use mongodb::{Collection, Database};
+use serde::Serialize;
+
+use crate::cli::Args;
+use crate::common::{self, format_size};
+use crate::errors::ServiceError;
+use crate::snapshot::{Directory, File, Series, Snapshot};
+
+use super::{get_snapshot, snapshot_labels, snapshot_listing, snapshot_search};
+
+const DEFAULT_TOP: usize = 20;
+
+/// One copy of a duplicated file; `entry` is relative to the snapshot path.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DuplicateCopy {
+    pub host: String,
+    pub path: String,
+    pub version: i32,
+    pub entry: String,
+}
+
+/// Files sharing a content digest. Keeping one copy saves `wasted_bytes`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DuplicateGroup {
+    pub digest: String,
+    pub bytes: u64,
+    pub wasted_bytes: u64,
+    pub copies: Vec<DuplicateCopy>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DuplicateReport {
+    /// Files that have a content digest and could be compared at all.
+    pub hashed_files: usize,
+    pub groups: usize,
+    pub duplicate_files: usize,
+    pub wasted_bytes: u64,
+    /// The groups saving the most, largest first.
+    pub largest: Vec<DuplicateGroup>,
+}
+
+#[cfg(not(tarpaulin_include))]
+pub async fn duplicates_menu(
+    series: &Series,
+    collection: &Collection<Snapshot>,
+) -> Result<(), ServiceError> {
+    println!("Please, input the number or label of version or 0 to choose the last one:");
+    let version = snapshot_labels::read_version(series, collection).await?;
+
+    let Some(snapshot) = get_snapshot(series, collection, version).await? else {
+        println!("Snapshot isn't found. Check the version");
+        return Ok(());
+    };
+
+    println!("Look for copies in the last versions of the other paths too? 1 - yes else no");
+    let across_paths = common::get_input() == 1;
+
+    let mut sources = Vec::from([(series.clone(), snapshot)]);
+    if across_paths {
+        sources.extend(load_other_paths(series, collection).await?);
+    }
+
+    let report = duplicate_report(&sources, DEFAULT_TOP);
+    println!("{}", render_report(&report));
+
+    Ok(())
+}
+
+/// `duplicates [path] [--version=V] [--host=H] [--across-paths] [--top=N]
+/// [--json]`
+#[cfg(not(tarpaulin_include))]
+pub async fn duplicates_command(
+    args: &Args,
+    path: &Path,
+    db: &Database,
+) -> Result<(), ServiceError> {
+    let series = snapshot_listing::get_series(args, path)?;
+    let collection: Collection<Snapshot> =
+        db.collection(&env::var("COLL_NAME").expect("COLL_NAME must be set"));
+
+    let snapshot =
+        snapshot_labels::load_version(&series, &collection, args.get("version").unwrap_or("0"))
+            .await?;
+
+    let mut sources = Vec::from([(series.clone(), snapshot)]);
+    if args.has_flag("across-paths") {
+        sources.extend(load_other_paths(&series, &collection).await?);
+    }
+
+    let report = duplicate_report(&sources, args.get_or("top", DEFAULT_TOP)?);
+
+    if args.has_flag("json") {
+        let json = serde_json::to_string_pretty(&report).or(Err(ServiceError::FailedToExport))?;
+        println!("{json}");
+    } else {
+        println!("{}", render_report(&report));
+    }
+
+    Ok(())
+}
+
+/// The last version of every other series stored. Legacy versions without
+/// a host belong to the series of their path, as in `Series::filter`.
+async fn load_other_paths(
+    series: &Series,
+    collection: &Collection<Snapshot>,
+) -> Result<Vec<(Series, Snapshot)>, ServiceError> {
+    let listed = snapshot_search::list_snapshots(collection).await?;
+
+    let mut snapshots = Vec::new();
+    for (other, version) in latest_versions(series, listed) {
+        if let Some(snapshot) = get_snapshot(&other, collection, version).await? {
+            snapshots.push((other, snapshot));
+        }
+    }
+
+    Ok(snapshots)
+}
+
+/// The last version of every series but `series`. Versions without a host
+/// count for every hosted series of their path and only stand on their own
+/// when the path has no hosted series.
+fn latest_versions(series: &Series, listed: Vec<(Series, i32)>) -> Vec<(Series, i32)> {
+    let mut latest: Vec<(Series, i32)> = Vec::new();
+    let mut legacy: Vec<(Series, i32)> = Vec::new();
+    for (other, version) in listed {
+        if other.path == series.path && (other.host == series.host || other.host.is_empty()) {
+            continue;
+        }
+        let known = if other.host.is_empty() {
+            &mut legacy
+        } else {
+            &mut latest
+        };
+        match known.iter_mut().find(|(known, _)| *known == other) {
+            Some((_, last)) => *last = (*last).max(version),
+            None => known.push((other, version)),
+        }
+    }
+
+    for (other, version) in legacy {
+        let mut hosted = latest
+            .iter_mut()
+            .filter(|(known, _)| known.path == other.path)
+            .peekable();
+        if hosted.peek().is_none() {
+            latest.push((other, version));
+        } else {
+            for (_, last) in hosted {
+                *last = (*last).max(version);
+            }
+        }
+    }
+
+    latest
+}
+
+/// Groups the files of the snapshots by digest, keeping the `top` groups
+/// that waste the most. Empty files and files without a digest are left out.
+pub fn duplicate_report(sources: &[(Series, Snapshot)], top: usize) -> DuplicateReport {
+    let mut by_digest: HashMap<&str, DuplicateGroup> = HashMap::new();
+    let mut hashed_files = 0;
+
+    for (series, snapshot) in sources {
+        let mut files = Vec::new();
+        collect(&snapshot.files, &snapshot.dirs, "", &mut files);
+
+        for (entry, file) in files {
+            let Some(digest) = file.meta.sha256.as_deref() else {
+                continue;
+            };
+            hashed_files += 1;
+            if file.size_kb == 0 {
+                continue;
+            }
+
+            let group = by_digest.entry(digest).or_insert_with(|| DuplicateGroup {
+                digest: String::from(digest),
+                bytes: file.size_kb,
+                wasted_bytes: 0,
+                copies: Vec::new(),
+            });
+            group.copies.push(DuplicateCopy {
+                host: series.host.clone(),
+                path: series.path.clone(),
+                version: snapshot.version,
+                entry,
+            });
+        }
+    }
+
+    let mut groups: Vec<DuplicateGroup> = by_digest
+        .into_values()
+        .filter(|group| group.copies.len() > 1)
+        .map(|mut group| {
+            group.wasted_bytes = group.bytes * (group.copies.len() as u64 - 1);
+            group
+        })
+        .collect();
+    groups.sort_by(|a, b| {
+        b.wasted_bytes
+            .cmp(&a.wasted_bytes)
+            .then(a.digest.cmp(&b.digest))
+    });
+
+    DuplicateReport {
+        hashed_files,
+        groups: groups.len(),
+        duplicate_files: groups.iter().map(|group| group.copies.len() - 1).sum(),
+        wasted_bytes: groups.iter().map(|group| group.wasted_bytes).sum(),
+        largest: groups.into_iter().take(top).collect(),
+    }
+}
+
+fn collect<'a>(
+    files: &'a [File],
+    dirs: &'a [Directory],
+    prefix: &str,
+    found: &mut Vec<(String, &'a File)>,
+) {
+    for file in files {
+        found.push((format!("{prefix}{}", file.name), file));
+    }
+    for dir in dirs {
+        collect(
+            &dir.files,
+            &dir.dirs,
+            &format!("{prefix}{}/", dir.name),
+            found,
+        );
+    }
+}
+
+pub fn render_report(report: &DuplicateReport) -> String {
+    if report.hashed_files == 0 {
+        return String::from(
+            "No file has a content digest, so duplicates can't be found. Take snapshots with SNAPSHOT_DIGESTS=1 to record digests",
+        );
+    }
+    if report.groups == 0 {
+        return String::from("No duplicate files found");
+    }
+
+    let several_paths = report
+        .largest
+        .iter()
+        .flat_map(|group| &group.copies)
+        .any(|copy| {
+            let first = &report.largest[0].copies[0];
+            copy.host != first.host || copy.path != first.path
+        });
+
+    let mut lines = Vec::from([format!(
+        "{} duplicate file(s) in {} group(s), {} could be saved",
+        report.duplicate_files,
+        report.groups,
+        format_size(report.wasted_bytes)
+    )]);
+
+    for group in &report.largest {
+        lines.push(String::new());
+        lines.push(format!(
+            "{} x {} = {} wasted ({})",
+            group.copies.len(),
+            format_size(group.bytes),
+            format_size(group.wasted_bytes),
+            &group.digest[..group.digest.len().min(12)]
+        ));
+        for copy in &group.copies {
+            if several_paths {
+                lines.push(format!("  {}:{}/{}", copy.host, copy.path, copy.entry));
+            } else {
+                lines.push(format!("  {}", copy.entry));
+            }
+        }
+    }
+
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::snapshot::fixtures::{self, create_dir};
+    use crate::snapshot::{File, Metadata, Series, Snapshot};
+
+    use super::{duplicate_report, latest_versions, render_report};
+
+    fn create_file(name: &str, size: u64, digest: Option<&str>) -> File {
+        File {
+            meta: Metadata {
+                sha256: digest.map(String::from),
+                ..Metadata::default()
+            },
+            ..fixtures::create_file(name, size)
+        }
+    }
+
+    fn create_sources() -> Vec<(Series, Snapshot)> {
+        let backup = create_dir(
+            "backup",
+            Vec::from([
+                create_file("photo.jpg", 1000, Some("aaa")),
+                create_file("notes.txt", 10, Some("bbb")),
+            ]),
+            Vec::new(),
+        );
+        let data = fixtures::create_snapshot(
+            3,
+            "/data",
+            Vec::from([
+                create_file("photo.jpg", 1000, Some("aaa")),
+                create_file("notes.txt", 10, Some("bbb")),
+                create_file("empty", 0, Some("e3b0")),
+                create_file("empty-too", 0, Some("e3b0")),
+                create_file("unknown", 500, None),
+            ]),
+            Vec::from([backup]),
+        );
+        let other = fixtures::create_snapshot(
+            3,
+            "/srv",
+            Vec::from([create_file("copy.jpg", 1000, Some("aaa"))]),
+            Vec::new(),
+        );
+
+        Vec::from([
+            (Series::create("web-1", "/data"), data),
+            (Series::create("web-1", "/srv"), other),
+        ])
+    }
+
+    #[test]
+    fn test_duplicates_within_snapshot() {
+        let sources = create_sources();
+        let report = duplicate_report(&sources[..1], 10);
+
+        assert_eq!(report.hashed_files, 6);
+        assert_eq!(report.groups, 2);
+        assert_eq!(report.duplicate_files, 2);
+        assert_eq!(report.wasted_bytes, 1010);
+        let groups: Vec<(&str, u64, Vec<&str>)> = report
+            .largest
+            .iter()
+            .map(|group| {
+                (
+                    group.digest.as_str(),
+                    group.wasted_bytes,
+                    group
+                        .copies
+                        .iter()
+                        .map(|copy| copy.entry.as_str())
+                        .collect(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            groups,
+            Vec::from([
+                ("aaa", 1000, Vec::from(["photo.jpg", "backup/photo.jpg"])),
+                ("bbb", 10, Vec::from(["notes.txt", "backup/notes.txt"])),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_duplicates_across_paths() {
+        let report = duplicate_report(&create_sources(), 1);
+
+        assert_eq!(report.groups, 2);
+        assert_eq!(report.wasted_bytes, 2010);
+        assert_eq!(report.largest.len(), 1);
+        assert_eq!(report.largest[0].copies.len(), 3);
+        assert_eq!(report.largest[0].copies[2].path, "/srv");
+    }
+
+    #[test]
+    fn test_latest_versions_folds_legacy_versions() {
+        let series = Series::create("web-1", "/data");
+        let listed = Vec::from([
+            (Series::create("", "/data"), 1),
+            (Series::create("web-2", "/data"), 2),
+            (Series::create("", "/srv"), 5),
+            (Series::create("web-1", "/srv"), 3),
+            (Series::create("web-1", "/srv"), 4),
+            (Series::create("", "/tmp"), 1),
+            (Series::create("", "/tmp"), 2),
+        ]);
+
+        assert_eq!(
+            latest_versions(&series, listed),
+            Vec::from([
+                (Series::create("web-2", "/data"), 2),
+                (Series::create("web-1", "/srv"), 5),
+                (Series::create("", "/tmp"), 2),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_render_report() {
+        let sources = create_sources();
+        let rendered = render_report(&duplicate_report(&sources, 10));
+        let lines: Vec<&str> = rendered.lines().collect();
+
+        assert_eq!(
+            lines[0],
+            "3 duplicate file(s) in 2 group(s), 2.0 KiB could be saved"
+        );
+        assert_eq!(lines[2], "3 x 1000 B = 2.0 KiB wasted (aaa)");
+        assert_eq!(lines[3], "  web-1:/data/photo.jpg");
+        assert!(render_report(&duplicate_report(&sources[..0], 10))
+            .starts_with("No file has a content digest"));
+        assert_eq!(
+            render_report(&duplicate_report(&sources[1..], 10)),
+            "No duplicate files found"
+        );
+    }
+}
@@ -0,0 +1,405 @@
+use std::fs;
+use std::io::Cursor;
+use std::path::Path;
+
+use chrono::Local;
+use mongodb::bson::{self, doc, Document};
+use mongodb::options::FindOneOptions;
+use mongodb::Collection;
+use serde::{Deserialize, Serialize};
+
+use crate::common;
+use crate::errors::ServiceError;
+use crate::filter;
+use crate::mtree;
+use crate::snapshot::{self, Series, Snapshot};
+use crate::storage;
+
+use super::{get_all_snapshots, get_snapshot, snapshot_labels};
+
+const MAGIC: &str = "folder-snapshoter";
+const FORMAT_VERSION: i32 = 1;
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xB5, 0x2F, 0xFD];
+const ZSTD_LEVEL: i32 = 3;
+
+#[derive(Debug, PartialEq)]
+pub enum ExportFormat {
+    Json,
+    Binary,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ConflictPolicy {
+    Skip,
+    Renumber,
+}
+
+/// Version stored by an import. `dropped_label` is the label it lost because
+/// it isn't a valid label or another version of the target series already
+/// had it.
+#[derive(Debug, PartialEq)]
+pub struct ImportedVersion {
+    pub original: i32,
+    pub stored: i32,
+    pub dropped_label: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct ExportFile {
+    pub magic: String,
+    pub format_version: i32,
+    pub tool_version: String,
+    pub path: String,
+    pub snapshots: Vec<Snapshot>,
+}
+
+impl ExportFile {
+    pub fn create(path: String, snapshots: Vec<Snapshot>) -> ExportFile {
+        ExportFile {
+            magic: String::from(MAGIC),
+            format_version: FORMAT_VERSION,
+            tool_version: String::from(env!("CARGO_PKG_VERSION")),
+            path,
+            snapshots,
+        }
+    }
+}
+
+#[cfg(not(tarpaulin_include))]
+pub async fn export_menu(
+    series: &Series,
+    collection: &Collection<Snapshot>,
+) -> Result<(), ServiceError> {
+    println!("Please, input the number or label of version, 0 to choose the last one or -1 to export all versions:");
+    let version = snapshot_labels::read_version(series, collection).await?;
+
+    let snapshots = if version == -1 {
+        get_all_snapshots(series, collection).await?
+    } else {
+        get_snapshot(series, collection, version)
+            .await?
+            .into_iter()
+            .collect()
+    };
+
+    if snapshots.is_empty() {
+        println!("Snapshot isn't found. Check the version");
+        return Ok(());
+    }
+
+    println!("Please input a filter expression to export only matching entries or leave it empty:");
+    let filter = filter::read_filter()?;
+
+    println!("Select the format: 1 - JSON else binary");
+    let format = if common::get_input() == 1 {
+        ExportFormat::Json
+    } else {
+        ExportFormat::Binary
+    };
+
+    println!("Compress the file with zstd? 1 - yes else no");
+    let compress = common::get_input() == 1;
+
+    println!("Please input the file to export to:");
+    let file_path = common::get_string_input();
+
+    let count = snapshots.len();
+    let snapshots = snapshots
+        .into_iter()
+        .map(|mut snapshot| {
+            if let Some(filter) = &filter {
+                snapshot = filter::filter_snapshot(&snapshot, filter);
+            }
+            snapshot.unchanged_since = None;
+            snapshot.tree = None;
+            snapshot
+        })
+        .collect();
+    let export = ExportFile::create(series.path.clone(), snapshots);
+    write_export(Path::new(&file_path), &export, &format, compress)?;
+
+    println!("{count} version(s) exported to {file_path}");
+
+    Ok(())
+}
+
+#[cfg(not(tarpaulin_include))]
+pub async fn import_menu(
+    series: &Series,
+    collection: &Collection<Snapshot>,
+) -> Result<(), ServiceError> {
+    println!("Please input the file to import from:");
+    let file_path = common::get_string_input();
+
+    let export = read_export(Path::new(&file_path))?;
+
+    println!(
+        "Import into the current path? 1 - yes else keep the original path ({})",
+        export.path
+    );
+    let target = if common::get_input() == 1 {
+        series.clone()
+    } else {
+        Series::create(&series.host, &export.path)
+    };
+
+    println!("If a version already exists: 1 - skip it else renumber it");
+    let policy = if common::get_input() == 1 {
+        ConflictPolicy::Skip
+    } else {
+        ConflictPolicy::Renumber
+    };
+
+    let imported = import_snapshots(export, &target, collection, &policy).await?;
+
+    println!(
+        "{} version(s) imported into {} on {}",
+        imported.len(),
+        target.path,
+        target.host
+    );
+    for version in imported {
+        if version.original != version.stored {
+            println!(
+                "version {} was renumbered to {}",
+                version.original, version.stored
+            );
+        }
+        if let Some(label) = version.dropped_label {
+            println!(
+                "label \"{label}\" of version {} is invalid or already taken and was dropped",
+                version.stored
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(not(tarpaulin_include))]
+pub async fn export_mtree_menu(
+    series: &Series,
+    collection: &Collection<Snapshot>,
+) -> Result<(), ServiceError> {
+    println!("Please, input the number or label of version or 0 to choose the last one:");
+    let version = snapshot_labels::read_version(series, collection).await?;
+
+    let Some(mut snapshot) = get_snapshot(series, collection, version).await? else {
+        println!("Snapshot isn't found. Check the version");
+        return Ok(());
+    };
+
+    println!("Please input a filter expression to export only matching entries or leave it empty:");
+    if let Some(filter) = filter::read_filter()? {
+        snapshot = filter::filter_snapshot(&snapshot, &filter);
+    }
+
+    println!("Please input the file to export to:");
+    let file_path = common::get_string_input();
+
+    if fs::write(&file_path, mtree::render(&snapshot)).is_err() {
+        return Err(ServiceError::FailedToExport);
+    }
+
+    println!("Version {} exported to {file_path}", snapshot.version);
+
+    Ok(())
+}
+
+#[cfg(not(tarpaulin_include))]
+pub async fn import_mtree_menu(
+    series: &Series,
+    collection: &Collection<Snapshot>,
+) -> Result<(), ServiceError> {
+    println!("Please input the mtree file to import:");
+    let file_path = common::get_string_input();
+
+    let text = fs::read_to_string(&file_path).or(Err(ServiceError::FailedToImport))?;
+    let version = snapshot::get_version(collection, series).await? + 1;
+    let mut snapshot = mtree::parse(
+        &text,
+        version,
+        Local::now().fixed_offset(),
+        series.path.clone(),
+    )?;
+    snapshot.set_origin(&series.host);
+
+    if storage::insert_snapshot(collection, snapshot)
+        .await
+        .is_err()
+    {
+        return Err(ServiceError::FailedToImport);
+    }
+
+    println!("{file_path} imported as version {version}");
+
+    Ok(())
+}
+
+/// Inserts the snapshots of `export` into the `target` series, keeping their
+/// version numbers when free.
+pub async fn import_snapshots(
+    export: ExportFile,
+    target: &Series,
+    collection: &Collection<Snapshot>,
+    policy: &ConflictPolicy,
+) -> Result<Vec<ImportedVersion>, ServiceError> {
+    let mut imported = Vec::new();
+
+    for mut snapshot in export.snapshots {
+        let original = snapshot.version;
+        snapshot.path = target.path.clone();
+        snapshot.host = Some(target.host.clone());
+
+        if version_exists(target, collection, original).await? {
+            match policy {
+                ConflictPolicy::Skip => continue,
+                ConflictPolicy::Renumber => {
+                    snapshot.version = snapshot::get_version(collection, target).await? + 1;
+                }
+            }
+        }
+
+        // Labels are unique per path and must pass the checks of the CLI;
+        // other ones are dropped rather than failing the whole import.
+        let mut dropped_label = None;
+        if let Some(label) = &snapshot.label {
+            if snapshot_labels::validate_label(label).is_err()
+                || snapshot_labels::find_label(target, collection, label)
+                    .await?
+                    .is_some()
+            {
+                dropped_label = snapshot.label.take();
+            }
+        }
+
+        let stored = snapshot.version;
+        if storage::insert_snapshot(collection, snapshot)
+            .await
+            .is_err()
+        {
+            return Err(ServiceError::FailedToImport);
+        }
+        imported.push(ImportedVersion {
+            original,
+            stored,
+            dropped_label,
+        });
+    }
+
+    Ok(imported)
+}
+
+async fn version_exists(
+    series: &Series,
+    collection: &Collection<Snapshot>,
+    version: i32,
+) -> Result<bool, ServiceError> {
+    let options = FindOneOptions::builder()
+        .projection(doc! {"_id": 1})
+        .build();
+
+    match collection
+        .clone_with_type::<Document>()
+        .find_one(series.version_filter(version), options)
+        .await
+    {
+        Ok(found) => Ok(found.is_some()),
+        Err(_) => Err(ServiceError::FailedToFoundCollection),
+    }
+}
+
+pub fn encode_export(
+    export: &ExportFile,
+    format: &ExportFormat,
+    compress: bool,
+) -> Result<Vec<u8>, ServiceError> {
+    let data = match format {
+        ExportFormat::Json => serde_json::to_vec_pretty(export).ok(),
+        ExportFormat::Binary => bson::to_vec(export).ok(),
+    };
+    let data = data.ok_or(ServiceError::FailedToExport)?;
+
+    if compress {
+        zstd::encode_all(Cursor::new(data), ZSTD_LEVEL).or(Err(ServiceError::FailedToExport))
+    } else {
+        Ok(data)
+    }
+}
+
+pub fn decode_export(data: &[u8]) -> Result<ExportFile, ServiceError> {
+    if data.starts_with(&ZSTD_MAGIC) {
+        let data = zstd::decode_all(data).or(Err(ServiceError::FailedToImport))?;
+        return decode_export(&data);
+    }
+
+    let export: ExportFile = if data.first() == Some(&b'{') {
+        serde_json::from_slice(data).or(Err(ServiceError::FailedToImport))?
+    } else {
+        bson::from_slice(data).or(Err(ServiceError::FailedToImport))?
+    };
+
+    if export.magic != MAGIC || export.format_version > FORMAT_VERSION {
+        return Err(ServiceError::FailedToImport);
+    }
+
+    Ok(export)
+}
+
+pub fn write_export(
+    file_path: &Path,
+    export: &ExportFile,
+    format: &ExportFormat,
+    compress: bool,
+) -> Result<(), ServiceError> {
+    let data = encode_export(export, format, compress)?;
+    fs::write(file_path, data).or(Err(ServiceError::FailedToExport))
+}
+
+pub fn read_export(file_path: &Path) -> Result<ExportFile, ServiceError> {
+    let data = fs::read(file_path).or(Err(ServiceError::FailedToImport))?;
+    decode_export(&data)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::snapshot::fixtures::{self, create_dir, create_file};
+
+    use super::{decode_export, encode_export, ExportFile, ExportFormat};
+
+    fn create_export() -> ExportFile {
+        let file = create_file("test", 10);
+        let dir = create_dir("dir", Vec::from([file.clone()]), Vec::new());
+        let snapshot = fixtures::create_snapshot(3, "test", Vec::from([file]), Vec::from([dir]));
+
+        ExportFile::create(String::from("test"), Vec::from([snapshot]))
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let export = create_export();
+        let data = encode_export(&export, &ExportFormat::Json, false).unwrap();
+        assert_eq!(data[0], b'{');
+        assert_eq!(decode_export(&data).unwrap(), export);
+    }
+
+    #[test]
+    fn test_binary_round_trip() {
+        let export = create_export();
+        let data = encode_export(&export, &ExportFormat::Binary, false).unwrap();
+        assert_eq!(decode_export(&data).unwrap(), export);
+    }
+
+    #[test]
+    fn test_compressed_round_trip() {
+        let export = create_export();
+        let data = encode_export(&export, &ExportFormat::Binary, true).unwrap();
+        assert_eq!(decode_export(&data).unwrap(), export);
+    }
+
+    #[test]
+    fn test_decode_foreign_file() {
+        let result = decode_export(b"{\"magic\": \"other\"}");
+        assert!(result.is_err());
+    }
+}
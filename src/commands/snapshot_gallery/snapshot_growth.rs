@@ -0,0 +1,406 @@
+use std::collections::HashMap;
+use std::env;
+use std::path::Path;
+
+use mongodb::{Collection, Database};
+use serde::Serialize;
+
+use crate::cli::Args;
+use crate::common::{self, format_delta, format_size};
+use crate::errors::ServiceError;
+use crate::snapshot::{self, Series, Snapshot};
+
+use super::snapshot_listing::{self, VersionSummary};
+use super::snapshot_tree;
+
+const DEFAULT_TOP: usize = 3;
+/// Levels of the sparkline from the smallest to the largest value.
+const SPARK_LEVELS: &[u8] = b"_.:-=+*#%@";
+
+/// Size of the directory in one version, as read from its tree.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GrowthSample {
+    pub summary: VersionSummary,
+    pub bytes: u64,
+    pub files: u64,
+    pub dirs: u64,
+    /// Size of every entry directly inside the directory.
+    pub children: HashMap<String, u64>,
+}
+
+/// Entry whose size changed the most since the previous version.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Contributor {
+    pub name: String,
+    pub delta_bytes: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GrowthPoint {
+    pub version: i32,
+    pub date: Option<String>,
+    pub label: Option<String>,
+    pub bytes: u64,
+    pub files: u64,
+    pub dirs: u64,
+    pub delta_bytes: Option<i64>,
+    pub top_contributors: Vec<Contributor>,
+}
+
+#[cfg(not(tarpaulin_include))]
+pub async fn growth_menu(
+    series: &Series,
+    collection: &Collection<Snapshot>,
+) -> Result<(), ServiceError> {
+    println!("Please input the directory to follow, relative to the snapshot, or leave it empty for the whole tree:");
+    let subtree = common::get_string_input();
+
+    let points = load_history(series, collection, &subtree, DEFAULT_TOP).await?;
+    if points.is_empty() {
+        println!("There are no versions to show");
+        return Ok(());
+    }
+
+    println!("{}", render_table(&points));
+    println!();
+    println!("{}", render_chart(&points));
+
+    Ok(())
+}
+
+/// `growth [path] [--host=H] [--subtree=DIR] [--top=N] [--csv | --json]`
+#[cfg(not(tarpaulin_include))]
+pub async fn growth_command(args: &Args, path: &Path, db: &Database) -> Result<(), ServiceError> {
+    let series = snapshot_listing::get_series(args, path)?;
+    let collection: Collection<Snapshot> =
+        db.collection(&env::var("COLL_NAME").expect("COLL_NAME must be set"));
+
+    let points = load_history(
+        &series,
+        &collection,
+        args.get("subtree").unwrap_or_default(),
+        args.get_or("top", DEFAULT_TOP)?,
+    )
+    .await?;
+
+    if args.has_flag("json") {
+        let json = serde_json::to_string_pretty(&points).or(Err(ServiceError::FailedToExport))?;
+        println!("{json}");
+    } else if args.has_flag("csv") {
+        print!("{}", render_csv(&points));
+    } else {
+        println!("{}", render_table(&points));
+        println!();
+        println!("{}", render_chart(&points));
+    }
+
+    Ok(())
+}
+
+/// Reads every version of the series and follows the size of `subtree`
+/// (relative to the snapshot path) across them.
+pub async fn load_history(
+    series: &Series,
+    collection: &Collection<Snapshot>,
+    subtree: &str,
+    top: usize,
+) -> Result<Vec<GrowthPoint>, ServiceError> {
+    let components = snapshot_tree::split_subtree(subtree);
+    let mut samples = Vec::new();
+
+    for (summary, loaded) in snapshot_listing::load_snapshots(series, collection).await? {
+        samples.push(sample(summary, &loaded, &components));
+    }
+
+    Ok(build_history(&samples, top))
+}
+
+/// Measures the directory reached by `components`; a directory missing
+/// from the version counts as empty.
+pub fn sample(summary: VersionSummary, snapshot: &Snapshot, components: &[String]) -> GrowthSample {
+    let (files, dirs) = if components.is_empty() {
+        (&snapshot.files[..], &snapshot.dirs[..])
+    } else {
+        match snapshot::find_dir(&snapshot.dirs, components) {
+            Some(dir) => (&dir.files[..], &dir.dirs[..]),
+            None => (&[][..], &[][..]),
+        }
+    };
+
+    let (file_count, dir_count) = snapshot::count_entries(files, dirs);
+    let children = files
+        .iter()
+        .map(|file| (file.name.clone(), file.size_kb))
+        .chain(dirs.iter().map(|dir| {
+            (
+                format!("{}/", dir.name),
+                snapshot::total_bytes(&dir.files, &dir.dirs),
+            )
+        }))
+        .collect();
+
+    GrowthSample {
+        summary,
+        bytes: snapshot::total_bytes(files, dirs),
+        files: file_count,
+        dirs: dir_count,
+        children,
+    }
+}
+
+/// Turns the samples, in version order, into the time series with the
+/// `top` entries that changed the most since the previous version.
+pub fn build_history(samples: &[GrowthSample], top: usize) -> Vec<GrowthPoint> {
+    let mut points = Vec::new();
+    let mut previous: Option<&GrowthSample> = None;
+
+    for sample in samples {
+        let mut contributors = Vec::new();
+        if let Some(previous) = previous {
+            let names = sample.children.keys().chain(previous.children.keys());
+            for name in names {
+                if contributors
+                    .iter()
+                    .any(|known: &Contributor| &known.name == name)
+                {
+                    continue;
+                }
+                let now = sample.children.get(name).copied().unwrap_or_default();
+                let before = previous.children.get(name).copied().unwrap_or_default();
+                if now != before {
+                    contributors.push(Contributor {
+                        name: name.clone(),
+                        delta_bytes: now as i64 - before as i64,
+                    });
+                }
+            }
+        }
+        contributors.sort_by(|a, b| {
+            b.delta_bytes
+                .abs()
+                .cmp(&a.delta_bytes.abs())
+                .then(a.name.cmp(&b.name))
+        });
+        contributors.truncate(top);
+
+        points.push(GrowthPoint {
+            version: sample.summary.version,
+            date: sample.summary.date.clone(),
+            label: sample.summary.label.clone(),
+            bytes: sample.bytes,
+            files: sample.files,
+            dirs: sample.dirs,
+            delta_bytes: previous.map(|previous| sample.bytes as i64 - previous.bytes as i64),
+            top_contributors: contributors,
+        });
+        previous = Some(sample);
+    }
+
+    points
+}
+
+fn describe_contributors(contributors: &[Contributor]) -> Vec<String> {
+    contributors
+        .iter()
+        .map(|contributor| {
+            format!(
+                "{} {}",
+                contributor.name,
+                format_delta(contributor.delta_bytes)
+            )
+        })
+        .collect()
+}
+
+pub fn render_table(points: &[GrowthPoint]) -> String {
+    let mut rows = Vec::from([[
+        "VERSION",
+        "DATE",
+        "SIZE",
+        "FILES",
+        "DIRS",
+        "DELTA",
+        "TOP CHANGES",
+    ]
+    .map(String::from)
+    .to_vec()]);
+
+    for point in points {
+        rows.push(Vec::from([
+            point.version.to_string(),
+            point
+                .date
+                .as_deref()
+                .map_or(String::from("?"), |date| date.replace('T', " ")),
+            format_size(point.bytes),
+            point.files.to_string(),
+            point.dirs.to_string(),
+            point.delta_bytes.map_or(String::new(), format_delta),
+            describe_contributors(&point.top_contributors).join(", "),
+        ]));
+    }
+
+    common::render_columns(&rows)
+}
+
+/// One character per value, from `_` for the smallest to `@` for the
+/// largest.
+pub fn sparkline(values: &[u64]) -> String {
+    let min = values.iter().copied().min().unwrap_or_default();
+    let max = values.iter().copied().max().unwrap_or_default();
+    let top = SPARK_LEVELS.len() - 1;
+
+    values
+        .iter()
+        .map(|value| {
+            let level = if max == min {
+                top
+            } else {
+                ((value - min) as f64 / (max - min) as f64 * top as f64).round() as usize
+            };
+            SPARK_LEVELS[level] as char
+        })
+        .collect()
+}
+
+pub fn render_chart(points: &[GrowthPoint]) -> String {
+    let bytes: Vec<u64> = points.iter().map(|point| point.bytes).collect();
+    let files: Vec<u64> = points.iter().map(|point| point.files).collect();
+    let range = |values: &[u64], format: fn(u64) -> String| {
+        format!(
+            "{} .. {}",
+            format(values.iter().copied().min().unwrap_or_default()),
+            format(values.iter().copied().max().unwrap_or_default())
+        )
+    };
+
+    format!(
+        "size  {}  {}\nfiles {}  {}",
+        sparkline(&bytes),
+        range(&bytes, format_size),
+        sparkline(&files),
+        range(&files, |count| count.to_string())
+    )
+}
+
+pub fn render_csv(points: &[GrowthPoint]) -> String {
+    let mut csv =
+        String::from("version,date,label,bytes,files,dirs,delta_bytes,top_contributors\n");
+
+    for point in points {
+        let contributors: Vec<String> = point
+            .top_contributors
+            .iter()
+            .map(|contributor| format!("{}:{:+}", contributor.name, contributor.delta_bytes))
+            .collect();
+        let fields = [
+            point.version.to_string(),
+            point.date.clone().unwrap_or_default(),
+            point.label.clone().unwrap_or_default(),
+            point.bytes.to_string(),
+            point.files.to_string(),
+            point.dirs.to_string(),
+            point
+                .delta_bytes
+                .map_or(String::new(), |delta| delta.to_string()),
+            contributors.join(";"),
+        ];
+        let fields: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+        csv.push_str(&fields.join(","));
+        csv.push('\n');
+    }
+
+    csv
+}
+
+/// Quotes a CSV field when it holds a separator, a quote or a line break.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        String::from(field)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mongodb::bson::doc;
+
+    use crate::commands::snapshot_gallery::snapshot_listing::summarize;
+    use crate::snapshot::fixtures::{self, create_dir, create_file};
+    use crate::snapshot::Snapshot;
+
+    use super::{build_history, csv_field, render_csv, render_table, sample, sparkline};
+
+    fn create_snapshot(version: i32, log_size: u64, extra: bool) -> Snapshot {
+        let mut files = Vec::from([create_file("app.log", log_size)]);
+        if extra {
+            files.push(create_file("new, file", 100));
+        }
+        fixtures::create_snapshot(
+            version,
+            "/data",
+            Vec::new(),
+            Vec::from([create_dir("var", files, Vec::new())]),
+        )
+    }
+
+    fn create_history() -> Vec<super::GrowthPoint> {
+        let components = [String::from("var")];
+        let samples: Vec<_> = [(1, 1000, false), (2, 1500, true), (3, 500, true)]
+            .into_iter()
+            .map(|(version, log_size, extra)| {
+                sample(
+                    summarize(&doc! {"version": version}),
+                    &create_snapshot(version, log_size, extra),
+                    &components,
+                )
+            })
+            .collect();
+        build_history(&samples, 1)
+    }
+
+    #[test]
+    fn test_build_history() {
+        let points = create_history();
+
+        let sizes: Vec<(u64, u64, Option<i64>)> = points
+            .iter()
+            .map(|point| (point.bytes, point.files, point.delta_bytes))
+            .collect();
+        assert_eq!(
+            sizes,
+            Vec::from([(1000, 1, None), (1600, 2, Some(600)), (600, 2, Some(-1000))])
+        );
+        assert!(points[0].top_contributors.is_empty());
+        assert_eq!(points[1].top_contributors[0].name, "app.log");
+        assert_eq!(points[2].top_contributors[0].delta_bytes, -1000);
+    }
+
+    #[test]
+    fn test_sparkline() {
+        assert_eq!(sparkline(&[0, 50, 100]), "_+@");
+        assert_eq!(sparkline(&[7, 7]), "@@");
+        assert_eq!(sparkline(&[]), "");
+    }
+
+    #[test]
+    fn test_render_table_and_csv() {
+        let points = create_history();
+
+        let table = render_table(&points);
+        let line = table.lines().nth(2).unwrap();
+        assert!(line.contains("+600 B"));
+        assert!(line.ends_with("app.log +500 B"));
+
+        let csv = render_csv(&points);
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(
+            lines[0],
+            "version,date,label,bytes,files,dirs,delta_bytes,top_contributors"
+        );
+        assert_eq!(lines[1], "1,,,1000,1,0,,");
+        assert_eq!(lines[3], "3,,,600,2,0,-1000,app.log:-1000");
+        assert_eq!(csv_field("new, \"file\""), "\"new, \"\"file\"\"\"");
+    }
+}
@@ -0,0 +1,337 @@
+use std::env;
+use std::path::Path;
+
+use chrono::DateTime;
+use mongodb::{Collection, Database};
+use serde::Serialize;
+
+use crate::cli::Args;
+use crate::common::{self, format_size};
+use crate::errors::ServiceError;
+use crate::snapshot::{self, Series, Snapshot};
+
+use super::snapshot_listing::{self, VersionSummary};
+use super::snapshot_tree;
+
+/// The entry as stored in one version.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EntryState {
+    pub is_dir: bool,
+    pub bytes: u64,
+    /// SHA-256 of a file, or the tree digest of a directory, when known.
+    pub digest: Option<String>,
+    pub mtime: Option<i64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Event {
+    Created,
+    Modified,
+    Unchanged,
+    Deleted,
+    Recreated,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HistoryEntry {
+    pub version: i32,
+    pub date: Option<String>,
+    pub event: Event,
+    /// `None` for the version the entry was deleted in.
+    pub state: Option<EntryState>,
+}
+
+#[cfg(not(tarpaulin_include))]
+pub async fn history_menu(
+    series: &Series,
+    collection: &Collection<Snapshot>,
+) -> Result<(), ServiceError> {
+    println!("Please input the path of the file or directory, relative to the snapshot:");
+    let path = common::get_string_input();
+
+    let history = load_history(series, collection, &path).await?;
+    if history.is_empty() {
+        println!("\"{path}\" isn't found in any version");
+        return Ok(());
+    }
+
+    println!("{}", render_history(&history));
+
+    Ok(())
+}
+
+/// `history [path] --file=REL [--host=H] [--json]`
+#[cfg(not(tarpaulin_include))]
+pub async fn history_command(args: &Args, path: &Path, db: &Database) -> Result<(), ServiceError> {
+    let series = snapshot_listing::get_series(args, path)?;
+    let collection: Collection<Snapshot> =
+        db.collection(&env::var("COLL_NAME").expect("COLL_NAME must be set"));
+
+    let file = args.get("file").ok_or(ServiceError::WrongNumberOfArgs)?;
+    let history = load_history(&series, &collection, file).await?;
+
+    if args.has_flag("json") {
+        let json = serde_json::to_string_pretty(&history).or(Err(ServiceError::FailedToExport))?;
+        println!("{json}");
+    } else {
+        println!("{}", render_history(&history));
+    }
+
+    Ok(())
+}
+
+/// Looks the entry up in every version of the series.
+pub async fn load_history(
+    series: &Series,
+    collection: &Collection<Snapshot>,
+    path: &str,
+) -> Result<Vec<HistoryEntry>, ServiceError> {
+    let components = snapshot_tree::split_subtree(path);
+    if components.is_empty() {
+        return Err(ServiceError::InvalidArgument);
+    }
+
+    let mut observations = Vec::new();
+    for (summary, loaded) in snapshot_listing::load_snapshots(series, collection).await? {
+        let state = find_entry(&loaded, &components);
+        observations.push((summary, state));
+    }
+
+    Ok(build_history(&observations))
+}
+
+/// State of the file or directory at `components` in the snapshot.
+pub fn find_entry(snapshot: &Snapshot, components: &[String]) -> Option<EntryState> {
+    let (name, parents) = components.split_last()?;
+    let (files, dirs) = if parents.is_empty() {
+        (&snapshot.files, &snapshot.dirs)
+    } else {
+        let parent = snapshot::find_dir(&snapshot.dirs, parents)?;
+        (&parent.files, &parent.dirs)
+    };
+
+    if let Some(file) = files.iter().find(|file| &file.name == name) {
+        return Some(EntryState {
+            is_dir: false,
+            bytes: file.size_kb,
+            digest: file.meta.sha256.clone(),
+            mtime: file.meta.mtime,
+        });
+    }
+
+    dirs.iter()
+        .find(|dir| &dir.name == name)
+        .map(|dir| EntryState {
+            is_dir: true,
+            bytes: snapshot::total_bytes(&dir.files, &dir.dirs),
+            digest: dir.hash.clone(),
+            mtime: dir.meta.mtime,
+        })
+}
+
+/// Classifies every version in which the entry exists, or stops existing,
+/// from the observations in version order.
+pub fn build_history(observations: &[(VersionSummary, Option<EntryState>)]) -> Vec<HistoryEntry> {
+    let mut history = Vec::new();
+    let mut previous: Option<&EntryState> = None;
+    let mut seen = false;
+
+    for (summary, state) in observations {
+        let event = match (previous, state) {
+            (None, None) => continue,
+            (None, Some(_)) if seen => Event::Recreated,
+            (None, Some(_)) => Event::Created,
+            (Some(_), None) => Event::Deleted,
+            (Some(before), Some(now)) if changed(before, now) => Event::Modified,
+            (Some(_), Some(_)) => Event::Unchanged,
+        };
+
+        history.push(HistoryEntry {
+            version: summary.version,
+            date: summary.date.clone(),
+            event,
+            state: state.clone(),
+        });
+        previous = state.as_ref();
+        seen = true;
+    }
+
+    history
+}
+
+/// Size, kind, and digest or mtime when both versions know them.
+fn changed(before: &EntryState, now: &EntryState) -> bool {
+    before.is_dir != now.is_dir
+        || before.bytes != now.bytes
+        || differs(&before.digest, &now.digest)
+        || differs(&before.mtime, &now.mtime)
+}
+
+fn differs<T: PartialEq>(before: &Option<T>, now: &Option<T>) -> bool {
+    before.is_some() && now.is_some() && before != now
+}
+
+fn describe_event(event: Event) -> &'static str {
+    match event {
+        Event::Created => "created",
+        Event::Modified => "modified",
+        Event::Unchanged => "unchanged",
+        Event::Deleted => "deleted",
+        Event::Recreated => "re-created",
+    }
+}
+
+/// Versions grouped by what happened in them, e.g.
+/// `created in 1; modified in 3, 4; deleted in 5`.
+pub fn summarize_lifecycle(history: &[HistoryEntry]) -> String {
+    let mut parts: Vec<(Event, Vec<String>)> = Vec::new();
+
+    for entry in history
+        .iter()
+        .filter(|entry| entry.event != Event::Unchanged)
+    {
+        match parts.iter_mut().find(|(event, _)| *event == entry.event) {
+            Some((_, versions)) => versions.push(entry.version.to_string()),
+            None => parts.push((entry.event, Vec::from([entry.version.to_string()]))),
+        }
+    }
+
+    parts
+        .iter()
+        .map(|(event, versions)| format!("{} in {}", describe_event(*event), versions.join(", ")))
+        .collect::<Vec<String>>()
+        .join("; ")
+}
+
+pub fn render_history(history: &[HistoryEntry]) -> String {
+    let mut rows = Vec::from([["VERSION", "DATE", "EVENT", "SIZE", "MTIME", "DIGEST"]
+        .map(String::from)
+        .to_vec()]);
+
+    for entry in history {
+        let state = entry.state.as_ref();
+        rows.push(Vec::from([
+            entry.version.to_string(),
+            entry
+                .date
+                .as_deref()
+                .map_or(String::from("?"), |date| date.replace('T', " ")),
+            String::from(describe_event(entry.event)),
+            state.map_or(String::new(), |state| format_size(state.bytes)),
+            state
+                .and_then(|state| state.mtime)
+                .and_then(|mtime| DateTime::from_timestamp(mtime, 0))
+                .map_or(String::new(), |mtime| {
+                    mtime.format("%Y-%m-%d %H:%M:%S").to_string()
+                }),
+            state
+                .and_then(|state| state.digest.as_deref())
+                .map_or(String::new(), |digest| {
+                    String::from(&digest[..digest.len().min(12)])
+                }),
+        ]));
+    }
+
+    format!(
+        "{}\n\n{}",
+        common::render_columns(&rows),
+        summarize_lifecycle(history)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use mongodb::bson::doc;
+
+    use crate::commands::snapshot_gallery::snapshot_listing::summarize;
+    use crate::snapshot::fixtures::{self, create_dir, create_file};
+    use crate::snapshot::{File, Metadata, Snapshot};
+
+    use super::{build_history, find_entry, render_history, Event};
+
+    fn create_snapshot(version: i32, size: Option<u64>) -> Snapshot {
+        let files = size
+            .map(|size| File {
+                meta: Metadata {
+                    mtime: Some(1_700_000_000),
+                    ..Metadata::default()
+                },
+                ..create_file("app.yaml", size)
+            })
+            .into_iter()
+            .collect();
+        fixtures::create_snapshot(
+            version,
+            "/data",
+            Vec::new(),
+            Vec::from([create_dir("config", files, Vec::new())]),
+        )
+    }
+
+    fn create_history() -> Vec<super::HistoryEntry> {
+        let components = [String::from("config"), String::from("app.yaml")];
+        let observations: Vec<_> = [None, Some(10), Some(10), Some(20), None, None, Some(5)]
+            .into_iter()
+            .enumerate()
+            .map(|(index, size)| {
+                let version = index as i32 + 1;
+                let snapshot = create_snapshot(version, size);
+                (
+                    summarize(&doc! {"version": version}),
+                    find_entry(&snapshot, &components),
+                )
+            })
+            .collect();
+        build_history(&observations)
+    }
+
+    #[test]
+    fn test_find_entry() {
+        let snapshot = create_snapshot(1, Some(10));
+
+        let file = find_entry(
+            &snapshot,
+            &[String::from("config"), String::from("app.yaml")],
+        );
+        assert_eq!(
+            file.map(|file| (file.is_dir, file.bytes)),
+            Some((false, 10))
+        );
+        let dir = find_entry(&snapshot, &[String::from("config")]);
+        assert_eq!(dir.map(|dir| dir.is_dir), Some(true));
+        assert_eq!(find_entry(&snapshot, &[String::from("missing")]), None);
+    }
+
+    #[test]
+    fn test_build_history() {
+        let events: Vec<(i32, Event)> = create_history()
+            .iter()
+            .map(|entry| (entry.version, entry.event))
+            .collect();
+
+        assert_eq!(
+            events,
+            Vec::from([
+                (2, Event::Created),
+                (3, Event::Unchanged),
+                (4, Event::Modified),
+                (5, Event::Deleted),
+                (7, Event::Recreated),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_render_history() {
+        let rendered = render_history(&create_history());
+        let lines: Vec<&str> = rendered.lines().collect();
+
+        assert!(lines[1].contains("created"));
+        assert!(lines[1].contains("2023-11-14 22:13:20"));
+        assert_eq!(
+            lines.last().unwrap(),
+            &"created in 2; modified in 4; deleted in 5; re-created in 7"
+        );
+    }
+}
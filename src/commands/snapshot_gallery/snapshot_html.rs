@@ -0,0 +1,286 @@
+use std::fs;
+use std::path::Path;
+
+use crate::common::{format_delta, format_size};
+use crate::errors::ServiceError;
+
+use super::snapshot_comparison::{Change, ChangeKind};
+use super::snapshot_diff::DiffReport;
+
+const STYLE: &str = "
+body { font-family: sans-serif; margin: 2em; color: #222; }
+h1 { font-size: 1.4em; }
+.summary span { margin-right: 1.5em; }
+.controls { margin: 1em 0; }
+ul { list-style: none; padding-left: 1.4em; margin: 0; }
+li { margin: 0.15em 0; }
+summary { cursor: pointer; }
+.marker { display: inline-block; width: 1.2em; font-family: monospace; font-weight: bold; }
+.added { color: #1a7f37; }
+.removed { color: #cf222e; }
+.changed { color: #9a6700; }
+.unchanged { color: #888; }
+.size { color: #666; margin-left: 0.6em; font-size: 0.9em; }
+";
+
+const SCRIPT: &str = "
+function sortTree(key) {
+  document.querySelectorAll('ul').forEach(function (list) {
+    var items = Array.from(list.children);
+    items.sort(function (a, b) {
+      if (key === 'delta') {
+        return Number(b.dataset.delta) - Number(a.dataset.delta)
+          || a.dataset.name.localeCompare(b.dataset.name);
+      }
+      return a.dataset.name.localeCompare(b.dataset.name);
+    });
+    items.forEach(function (item) { list.appendChild(item); });
+  });
+}
+function expandAll(open) {
+  document.querySelectorAll('details').forEach(function (node) { node.open = open; });
+}
+";
+
+/// Entry of the report tree. Directories only on the path to a change
+/// have no change of their own.
+#[derive(Debug, PartialEq)]
+struct Node<'a> {
+    name: &'a str,
+    change: Option<&'a Change>,
+    children: Vec<Node<'a>>,
+}
+
+#[cfg(not(tarpaulin_include))]
+pub fn write_html(report: &DiffReport, title: &str, file_path: &Path) -> Result<(), ServiceError> {
+    fs::write(file_path, render_html(report, title)).or(Err(ServiceError::FailedToExport))
+}
+
+/// Self-contained page with the summary of the comparison and its changes
+/// as a collapsible tree that can be sorted by name or by size change.
+pub fn render_html(report: &DiffReport, title: &str) -> String {
+    let summary = &report.summary;
+    let mut html =
+        String::from("<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n");
+    html.push_str(&format!(
+        "<title>{}</title>\n<style>{STYLE}</style>\n<script>{SCRIPT}</script>\n</head>\n<body>\n",
+        escape(title)
+    ));
+    html.push_str(&format!(
+        "<h1>{}: version {} &rarr; version {}</h1>\n",
+        escape(title),
+        report.from,
+        report.to
+    ));
+    html.push_str(&format!(
+        "<p class=\"summary\"><span class=\"added\">{} added</span><span class=\"removed\">{} removed</span><span class=\"changed\">{} modified</span><span>net change {}</span></p>\n",
+        summary.added,
+        summary.removed,
+        summary.changed,
+        format_delta(summary.net_bytes)
+    ));
+    html.push_str(
+        "<p class=\"controls\">Sort by <button onclick=\"sortTree('name')\">name</button> \
+         <button onclick=\"sortTree('delta')\">size change</button> \
+         <button onclick=\"expandAll(true)\">expand all</button> \
+         <button onclick=\"expandAll(false)\">collapse all</button></p>\n",
+    );
+
+    if report.changes.is_empty() {
+        html.push_str("<p>No changes</p>\n");
+    } else {
+        html.push_str("<ul>\n");
+        for node in build_tree(&report.changes) {
+            render_node(&node, &mut html);
+        }
+        html.push_str("</ul>\n");
+    }
+
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+/// Nests the changes by path, keeping their order.
+fn build_tree(changes: &[Change]) -> Vec<Node<'_>> {
+    let mut roots: Vec<Node> = Vec::new();
+
+    for change in changes {
+        let components: Vec<&str> = change.path.split('/').collect();
+        let Some((last, parents)) = components.split_last() else {
+            continue;
+        };
+
+        let mut level = &mut roots;
+        for name in parents {
+            let index = match level.iter().position(|node| node.name == *name) {
+                Some(index) => index,
+                None => {
+                    level.push(Node {
+                        name,
+                        change: None,
+                        children: Vec::new(),
+                    });
+                    level.len() - 1
+                }
+            };
+            level = &mut level[index].children;
+        }
+
+        match level.iter_mut().find(|node| node.name == *last) {
+            Some(node) => node.change = Some(change),
+            None => level.push(Node {
+                name: last,
+                change: Some(change),
+                children: Vec::new(),
+            }),
+        }
+    }
+
+    roots
+}
+
+fn render_node(node: &Node, html: &mut String) {
+    let (class, marker) = match node.change.map(|change| change.kind) {
+        Some(ChangeKind::Added) => ("added", "+"),
+        Some(ChangeKind::Removed) => ("removed", "-"),
+        Some(ChangeKind::Changed) => ("changed", "~"),
+        None => ("unchanged", "&nbsp;"),
+    };
+    let is_dir = !node.children.is_empty() || node.change.is_some_and(|change| change.is_dir);
+    let slash = if is_dir { "/" } else { "" };
+    let delta = node.change.map_or(0, |change| {
+        change
+            .old_size
+            .unwrap_or(0)
+            .abs_diff(change.new_size.unwrap_or(0))
+    });
+    let size = node.change.map_or(String::new(), describe_size);
+
+    let label = format!(
+        "<span class=\"marker {class}\">{marker}</span><span class=\"{class}\">{}{slash}</span><span class=\"size\">{size}</span>",
+        escape(node.name)
+    );
+
+    html.push_str(&format!(
+        "<li data-name=\"{}\" data-delta=\"{delta}\">",
+        escape(node.name)
+    ));
+    if node.children.is_empty() {
+        html.push_str(&label);
+    } else {
+        html.push_str(&format!("<details open><summary>{label}</summary>\n<ul>\n"));
+        for child in &node.children {
+            render_node(child, html);
+        }
+        html.push_str("</ul>\n</details>");
+    }
+    html.push_str("</li>\n");
+}
+
+fn describe_size(change: &Change) -> String {
+    match (change.old_size, change.new_size) {
+        (Some(old), Some(new)) => format!(
+            "{} &rarr; {} ({})",
+            format_size(old),
+            format_size(new),
+            format_delta(new as i64 - old as i64)
+        ),
+        (None, Some(new)) => format!("({})", format_delta(new as i64)),
+        (Some(old), None) => format!("({})", format_delta(-(old as i64))),
+        (None, None) => String::new(),
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::snapshot_gallery::snapshot_comparison::{Change, ChangeKind};
+    use crate::commands::snapshot_gallery::snapshot_diff::{DiffReport, DiffSummary};
+
+    use super::{build_tree, escape, render_html};
+
+    fn change(
+        path: &str,
+        kind: ChangeKind,
+        is_dir: bool,
+        old: Option<u64>,
+        new: Option<u64>,
+    ) -> Change {
+        Change {
+            path: String::from(path),
+            kind,
+            is_dir,
+            old_size: old,
+            new_size: new,
+        }
+    }
+
+    fn create_report() -> DiffReport {
+        DiffReport {
+            from: 1,
+            to: 2,
+            changes: Vec::from([
+                change("logs", ChangeKind::Changed, true, Some(100), Some(300)),
+                change(
+                    "logs/a.log",
+                    ChangeKind::Changed,
+                    false,
+                    Some(100),
+                    Some(300),
+                ),
+                change("notes.txt", ChangeKind::Removed, false, Some(10), None),
+                change("src/<main>.rs", ChangeKind::Added, false, None, Some(20)),
+            ]),
+            summary: DiffSummary {
+                added: 1,
+                removed: 1,
+                changed: 2,
+                net_bytes: 210,
+            },
+        }
+    }
+
+    #[test]
+    fn test_build_tree() {
+        let report = create_report();
+        let tree = build_tree(&report.changes);
+
+        let names: Vec<&str> = tree.iter().map(|node| node.name).collect();
+        assert_eq!(names, Vec::from(["logs", "notes.txt", "src"]));
+        assert_eq!(tree[0].children[0].name, "a.log");
+        assert!(tree[0].change.is_some());
+        assert!(tree[2].change.is_none());
+        assert_eq!(tree[2].children[0].name, "<main>.rs");
+    }
+
+    #[test]
+    fn test_render_html() {
+        let html = render_html(&create_report(), "/data on web-1");
+
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<h1>/data on web-1: version 1 &rarr; version 2</h1>"));
+        assert!(html.contains("<span class=\"changed\">2 modified</span>"));
+        assert!(html.contains("net change +210 B"));
+        assert!(html.contains("<li data-name=\"logs\" data-delta=\"200\"><details open><summary>"));
+        assert!(html.contains("100 B &rarr; 300 B (+200 B)"));
+        assert!(html.contains("<span class=\"marker removed\">-</span><span class=\"removed\">notes.txt</span><span class=\"size\">(-10 B)</span>"));
+        assert!(html.contains("&lt;main&gt;.rs"));
+        assert!(!html.contains("<main>"));
+        assert!(!html.contains("No changes"));
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(
+            escape("a<b> & \"c\" 'd'"),
+            "a&lt;b&gt; &amp; &quot;c&quot; &#39;d&#39;"
+        );
+    }
+}
@@ -0,0 +1,259 @@
+use mongodb::bson::Document;
+use mongodb::Collection;
+
+use crate::common;
+use crate::errors::ServiceError;
+use crate::snapshot::{Series, Snapshot};
+
+use super::{get_snapshot, snapshot_timeline};
+
+#[cfg(not(tarpaulin_include))]
+pub async fn edit_menu(
+    series: &Series,
+    collection: &Collection<Snapshot>,
+) -> Result<(), ServiceError> {
+    println!("Please, input the number or label of version or 0 to choose the last one:");
+    let version = read_version(series, collection).await?;
+
+    let Some(snapshot) = get_snapshot(series, collection, version).await? else {
+        println!("Snapshot isn't found. Check the version");
+        return Ok(());
+    };
+
+    println!("Version {}: {}", snapshot.version, describe(&snapshot));
+    println!("For every field leave the input empty to keep it or enter - to clear it");
+
+    let mut set = Document::new();
+    let mut unset = Document::new();
+
+    println!("Please input the label:");
+    match edit_field(&common::get_string_input()) {
+        Some(Some(label)) => {
+            validate_label(&label)?;
+            ensure_label_free(series, collection, &label, snapshot.version).await?;
+            set.insert("label", label);
+        }
+        Some(None) => {
+            unset.insert("label", "");
+        }
+        None => {}
+    }
+
+    println!("Please input the note:");
+    match edit_field(&common::get_string_input()) {
+        Some(Some(note)) => {
+            set.insert("note", note);
+        }
+        Some(None) => {
+            unset.insert("note", "");
+        }
+        None => {}
+    }
+
+    println!("Please input the tags separated by commas:");
+    match edit_field(&common::get_string_input()) {
+        Some(Some(tags)) => {
+            set.insert("tags", parse_tags(&tags));
+        }
+        Some(None) => {
+            unset.insert("tags", "");
+        }
+        None => {}
+    }
+
+    let mut update = Document::new();
+    if !set.is_empty() {
+        update.insert("$set", set);
+    }
+    if !unset.is_empty() {
+        update.insert("$unset", unset);
+    }
+    if update.is_empty() {
+        return Ok(());
+    }
+
+    if collection
+        .update_one(series.version_filter(snapshot.version), update, None)
+        .await
+        .is_err()
+    {
+        return Err(ServiceError::FailedToStoreSnapshot);
+    }
+
+    println!("Version {} updated", snapshot.version);
+
+    Ok(())
+}
+
+/// Reads a version number or a label from the user. Numbers are returned
+/// as typed, so `0` and `-1` keep the meaning the menu gives them.
+#[cfg(not(tarpaulin_include))]
+pub async fn read_version(
+    series: &Series,
+    collection: &Collection<Snapshot>,
+) -> Result<i32, ServiceError> {
+    loop {
+        let input = common::get_string_input();
+
+        match resolve_version(series, collection, &input).await? {
+            Some(version) => return Ok(version),
+            None => println!("No version matches this label or time. Please repeat your attempt"),
+        }
+    }
+}
+
+/// Turns a version argument into a version number: numbers are taken as
+/// they are, anything else is looked up as a label and then as a time
+/// selection like `as of 2026-09-01` or `before yesterday`.
+pub async fn resolve_version(
+    series: &Series,
+    collection: &Collection<Snapshot>,
+    input: &str,
+) -> Result<Option<i32>, ServiceError> {
+    let input = input.trim();
+
+    if let Ok(version) = input.parse() {
+        return Ok(Some(version));
+    }
+
+    if let Some(version) = find_label(series, collection, input).await? {
+        return Ok(Some(version));
+    }
+
+    snapshot_timeline::resolve_time(series, collection, input).await
+}
+
+/// Resolves a version argument like `resolve_version` and loads it.
+pub async fn load_version(
+    series: &Series,
+    collection: &Collection<Snapshot>,
+    input: &str,
+) -> Result<Snapshot, ServiceError> {
+    let version = resolve_version(series, collection, input)
+        .await?
+        .ok_or(ServiceError::InvalidArgument)?;
+
+    get_snapshot(series, collection, version)
+        .await?
+        .ok_or(ServiceError::InvalidArgument)
+}
+
+pub async fn find_label(
+    series: &Series,
+    collection: &Collection<Snapshot>,
+    label: &str,
+) -> Result<Option<i32>, ServiceError> {
+    let mut label_filter = series.filter();
+    label_filter.insert("label", label);
+
+    match collection
+        .clone_with_type::<Document>()
+        .find_one(label_filter, None)
+        .await
+    {
+        Ok(found) => Ok(found.and_then(|found| found.get_i32("version").ok())),
+        Err(_) => Err(ServiceError::FailedToFoundCollection),
+    }
+}
+
+/// Fails when `label` already names a version of the series other than
+/// `version`.
+pub async fn ensure_label_free(
+    series: &Series,
+    collection: &Collection<Snapshot>,
+    label: &str,
+    version: i32,
+) -> Result<(), ServiceError> {
+    match find_label(series, collection, label).await? {
+        Some(found) if found != version => Err(ServiceError::LabelAlreadyUsed),
+        _ => Ok(()),
+    }
+}
+
+/// Labels must not be confused with version numbers.
+pub fn validate_label(label: &str) -> Result<(), ServiceError> {
+    if label.is_empty() || label.parse::<i32>().is_ok() {
+        return Err(ServiceError::InvalidArgument);
+    }
+
+    Ok(())
+}
+
+pub fn parse_tags(text: &str) -> Vec<String> {
+    let mut tags: Vec<String> = Vec::new();
+
+    for tag in text.split(',').map(str::trim) {
+        if !tag.is_empty() && !tags.iter().any(|known| known == tag) {
+            tags.push(String::from(tag));
+        }
+    }
+
+    tags
+}
+
+/// One-line summary of the label, tags and note of a version.
+pub fn describe(snapshot: &Snapshot) -> String {
+    let mut parts = Vec::new();
+
+    if let Some(label) = &snapshot.label {
+        parts.push(format!("[{label}]"));
+    }
+    if !snapshot.tags.is_empty() {
+        parts.push(format!("(tags: {})", snapshot.tags.join(", ")));
+    }
+    if let Some(note) = &snapshot.note {
+        parts.push(format!("- {note}"));
+    }
+
+    parts.join(" ")
+}
+
+/// Interprets an edit prompt: `None` keeps the field, `Some(None)` clears
+/// it and `Some(Some(value))` replaces it.
+fn edit_field(input: &str) -> Option<Option<String>> {
+    match input.trim() {
+        "" => None,
+        "-" => Some(None),
+        value => Some(Some(String::from(value))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::snapshot::fixtures::create_snapshot;
+
+    use super::{describe, edit_field, parse_tags, validate_label};
+
+    #[test]
+    fn test_validate_label() {
+        assert!(validate_label("before-2.3").is_ok());
+        assert!(validate_label("17").is_err());
+        assert!(validate_label("").is_err());
+    }
+
+    #[test]
+    fn test_parse_tags() {
+        assert_eq!(
+            parse_tags(" release, ,nightly,release "),
+            Vec::from([String::from("release"), String::from("nightly")])
+        );
+    }
+
+    #[test]
+    fn test_describe_and_edit_field() {
+        let mut snapshot = create_snapshot(1, "", Vec::new(), Vec::new());
+        assert_eq!(describe(&snapshot), "");
+
+        snapshot.label = Some(String::from("before-2.3"));
+        snapshot.tags = parse_tags("release");
+        snapshot.note = Some(String::from("last stable"));
+        assert_eq!(
+            describe(&snapshot),
+            "[before-2.3] (tags: release) - last stable"
+        );
+
+        assert_eq!(edit_field(" "), None);
+        assert_eq!(edit_field("-"), Some(None));
+        assert_eq!(edit_field("x"), Some(Some(String::from("x"))));
+    }
+}
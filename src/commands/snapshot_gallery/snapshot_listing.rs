@@ -0,0 +1,401 @@
+use std::path::Path;
+use std::str::FromStr;
+use std::{env, fs};
+
+use chrono::{DateTime, FixedOffset, Utc};
+use mongodb::bson::{doc, Bson, Document};
+use mongodb::options::FindOptions;
+use mongodb::{Collection, Database};
+use serde::Serialize;
+
+use crate::cli::Args;
+use crate::common::{self, format_delta, format_size};
+use crate::errors::ServiceError;
+use crate::snapshot::{self, Series, Snapshot};
+
+use super::{get_snapshot, snapshot_timeline};
+
+const DEFAULT_PAGE_SIZE: usize = 20;
+
+/// One row of the version listing.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct VersionSummary {
+    pub version: i32,
+    pub date: Option<String>,
+    #[serde(skip)]
+    pub timestamp: Option<DateTime<Utc>>,
+    pub label: Option<String>,
+    pub size_kb: u64,
+    pub files: Option<u64>,
+    pub dirs: Option<u64>,
+    /// Size change relative to the previous version.
+    pub delta_kb: Option<i64>,
+    pub unchanged_since: Option<i32>,
+    pub tags: Vec<String>,
+    pub note: Option<String>,
+    pub host: Option<String>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum SortKey {
+    #[default]
+    Version,
+    Date,
+    Size,
+    Delta,
+}
+
+impl FromStr for SortKey {
+    type Err = ServiceError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "version" => Ok(SortKey::Version),
+            "date" => Ok(SortKey::Date),
+            "size" => Ok(SortKey::Size),
+            "delta" => Ok(SortKey::Delta),
+            _ => Err(ServiceError::InvalidArgument),
+        }
+    }
+}
+
+/// Prints the versions of `series` created within `from` and `to` (see
+/// `snapshot_timeline::is_within`) a page at a time.
+#[cfg(not(tarpaulin_include))]
+pub async fn show_summaries(
+    series: &Series,
+    collection: &Collection<Snapshot>,
+    from: Option<DateTime<Utc>>,
+    to: Option<(DateTime<Utc>, bool)>,
+) -> Result<(), ServiceError> {
+    let mut summaries = load_summaries(series, collection).await?;
+    summaries.retain(|summary| match summary.timestamp {
+        Some(date) => snapshot_timeline::is_within(date, from, to),
+        None => from.is_none() && to.is_none(),
+    });
+
+    if summaries.is_empty() {
+        println!("There are no versions to show");
+        return Ok(());
+    }
+
+    let pages = summaries.chunks(DEFAULT_PAGE_SIZE).collect::<Vec<_>>();
+    for (index, page) in pages.iter().enumerate() {
+        println!("{}", render_table(page));
+
+        if index + 1 < pages.len() {
+            println!(
+                "Page {} of {}. Press Enter for the next page or q to stop",
+                index + 1,
+                pages.len()
+            );
+            if common::get_string_input() == "q" {
+                break;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// `versions [path] [--host=H] [--sort=version|date|size|delta] [--desc]
+/// [--page=N] [--per-page=N] [--json]`
+#[cfg(not(tarpaulin_include))]
+pub async fn versions_command(args: &Args, path: &Path, db: &Database) -> Result<(), ServiceError> {
+    let series = get_series(args, path)?;
+    let collection: Collection<Snapshot> =
+        db.collection(&env::var("COLL_NAME").expect("COLL_NAME must be set"));
+
+    let mut summaries = load_summaries(&series, &collection).await?;
+    sort_summaries(
+        &mut summaries,
+        args.get_or("sort", SortKey::Version)?,
+        args.has_flag("desc"),
+    );
+
+    let per_page = args.get_or("per-page", usize::MAX)?;
+    let page = args.get_or("page", 1usize)?;
+    let summaries = get_page(&summaries, page, per_page);
+
+    if args.has_flag("json") {
+        let json = serde_json::to_string_pretty(summaries).or(Err(ServiceError::FailedToExport))?;
+        println!("{json}");
+    } else {
+        println!("{}", render_table(summaries));
+    }
+
+    Ok(())
+}
+
+/// Series named by the first positional argument (or `path`) and `--host`
+/// (or this machine).
+pub fn get_series(args: &Args, path: &Path) -> Result<Series, ServiceError> {
+    let path = match args.positional.first() {
+        Some(path) => fs::canonicalize(path).or(Err(ServiceError::InvalidArgument))?,
+        None => path.to_path_buf(),
+    };
+    let path = path.to_str().ok_or(ServiceError::InvalidArgument)?;
+
+    Ok(match args.get("host") {
+        Some(host) => Series::create(host, path),
+        None => Series::local(path),
+    })
+}
+
+/// Reads the summaries of all versions of `series` in version order. The
+/// tree is only loaded for versions stored before file counts were kept.
+pub async fn load_summaries(
+    series: &Series,
+    collection: &Collection<Snapshot>,
+) -> Result<Vec<VersionSummary>, ServiceError> {
+    let mut summaries = read_summaries(series, collection).await?;
+
+    for summary in summaries
+        .iter_mut()
+        .filter(|summary| summary.files.is_none())
+    {
+        if let Some(loaded) = get_snapshot(series, collection, summary.version).await? {
+            fill_counts(summary, &loaded);
+        }
+    }
+
+    fill_deltas(&mut summaries);
+
+    Ok(summaries)
+}
+
+/// Reads every version of `series` in version order together with its
+/// summary, loading each tree once.
+pub async fn load_snapshots(
+    series: &Series,
+    collection: &Collection<Snapshot>,
+) -> Result<Vec<(VersionSummary, Snapshot)>, ServiceError> {
+    let mut summaries = Vec::new();
+    let mut snapshots = Vec::new();
+
+    for mut summary in read_summaries(series, collection).await? {
+        if let Some(loaded) = get_snapshot(series, collection, summary.version).await? {
+            if summary.files.is_none() {
+                fill_counts(&mut summary, &loaded);
+            }
+            summaries.push(summary);
+            snapshots.push(loaded);
+        }
+    }
+
+    fill_deltas(&mut summaries);
+
+    Ok(summaries.into_iter().zip(snapshots).collect())
+}
+
+fn fill_counts(summary: &mut VersionSummary, snapshot: &Snapshot) {
+    let (files, dirs) = snapshot::count_entries(&snapshot.files, &snapshot.dirs);
+    summary.files = Some(files);
+    summary.dirs = Some(dirs);
+}
+
+async fn read_summaries(
+    series: &Series,
+    collection: &Collection<Snapshot>,
+) -> Result<Vec<VersionSummary>, ServiceError> {
+    let options = FindOptions::builder()
+        .sort(doc! {"version": 1})
+        .projection(doc! {"files": 0, "dirs": 0})
+        .build();
+    let mut cursor = match collection
+        .clone_with_type::<Document>()
+        .find(series.filter(), options)
+        .await
+    {
+        Ok(cursor) => cursor,
+        Err(_) => return Err(ServiceError::FailedToFoundCollection),
+    };
+
+    let mut summaries = Vec::new();
+    while cursor
+        .advance()
+        .await
+        .or(Err(ServiceError::FailedToFoundCollection))?
+    {
+        let current: Document = match cursor.deserialize_current() {
+            Ok(current) => current,
+            Err(_) => return Err(ServiceError::FailedToFoundCollection),
+        };
+        summaries.push(summarize(&current));
+    }
+
+    Ok(summaries)
+}
+
+/// Builds a summary from a snapshot document without its tree.
+pub fn summarize(document: &Document) -> VersionSummary {
+    let timestamp = document.get("date").and_then(snapshot::parse_date);
+    let offset = FixedOffset::east_opt(snapshot::stored_offset(document))
+        .unwrap_or(FixedOffset::east_opt(0).unwrap());
+    let get_u64 = |key: &str| match document.get(key) {
+        Some(Bson::Int64(value)) => u64::try_from(*value).ok(),
+        Some(Bson::Int32(value)) => u64::try_from(*value).ok(),
+        _ => None,
+    };
+
+    VersionSummary {
+        version: document.get_i32("version").unwrap_or_default(),
+        date: timestamp.map(|date| date.with_timezone(&offset).to_rfc3339()),
+        timestamp,
+        label: document.get_str("label").ok().map(String::from),
+        size_kb: get_u64("size_kb").unwrap_or_default(),
+        files: get_u64("file_count"),
+        dirs: get_u64("dir_count"),
+        delta_kb: None,
+        unchanged_since: document.get_i32("unchanged_since").ok(),
+        tags: document
+            .get_array("tags")
+            .map(|tags| {
+                tags.iter()
+                    .filter_map(|tag| tag.as_str().map(String::from))
+                    .collect()
+            })
+            .unwrap_or_default(),
+        note: document.get_str("note").ok().map(String::from),
+        host: document.get_str("host").ok().map(String::from),
+    }
+}
+
+/// Sets the size change of every summary relative to the one before it.
+/// Expects the summaries in version order.
+pub fn fill_deltas(summaries: &mut [VersionSummary]) {
+    let mut previous: Option<u64> = None;
+
+    for summary in summaries.iter_mut() {
+        summary.delta_kb = previous.map(|previous| summary.size_kb as i64 - previous as i64);
+        previous = Some(summary.size_kb);
+    }
+}
+
+pub fn sort_summaries(summaries: &mut [VersionSummary], key: SortKey, descending: bool) {
+    match key {
+        SortKey::Version => summaries.sort_by_key(|summary| summary.version),
+        SortKey::Date => summaries.sort_by_key(|summary| (summary.timestamp, summary.version)),
+        SortKey::Size => summaries.sort_by_key(|summary| (summary.size_kb, summary.version)),
+        SortKey::Delta => summaries.sort_by_key(|summary| (summary.delta_kb, summary.version)),
+    }
+
+    if descending {
+        summaries.reverse();
+    }
+}
+
+/// Page `page` (counted from 1) of `per_page` summaries; empty past the end.
+pub fn get_page(summaries: &[VersionSummary], page: usize, per_page: usize) -> &[VersionSummary] {
+    let per_page = per_page.max(1);
+    let start = page
+        .saturating_sub(1)
+        .saturating_mul(per_page)
+        .min(summaries.len());
+    let end = start.saturating_add(per_page).min(summaries.len());
+
+    &summaries[start..end]
+}
+
+pub fn render_table(summaries: &[VersionSummary]) -> String {
+    let header = [
+        "VERSION", "DATE", "LABEL", "SIZE", "FILES", "DIRS", "DELTA", "NOTES",
+    ]
+    .map(String::from);
+    let mut rows = Vec::from([header.to_vec()]);
+
+    for summary in summaries {
+        let mut notes = Vec::new();
+        if let Some(since) = summary.unchanged_since {
+            notes.push(format!("unchanged since version {since}"));
+        }
+        if !summary.tags.is_empty() {
+            notes.push(format!("tags: {}", summary.tags.join(", ")));
+        }
+        if let Some(note) = &summary.note {
+            notes.push(note.clone());
+        }
+
+        let count = |count: Option<u64>| count.map_or(String::from("?"), |count| count.to_string());
+        rows.push(Vec::from([
+            summary.version.to_string(),
+            summary
+                .date
+                .as_deref()
+                .and_then(|date| DateTime::parse_from_rfc3339(date).ok())
+                .map_or(String::from("?"), |date| {
+                    date.format("%Y-%m-%d %H:%M:%S %:z").to_string()
+                }),
+            summary.label.clone().unwrap_or_default(),
+            format_size(summary.size_kb),
+            count(summary.files),
+            count(summary.dirs),
+            summary.delta_kb.map_or(String::new(), format_delta),
+            notes.join("; "),
+        ]));
+    }
+
+    common::render_columns(&rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use mongodb::bson::{doc, DateTime};
+
+    use super::{
+        fill_deltas, get_page, render_table, sort_summaries, summarize, SortKey, VersionSummary,
+    };
+
+    fn create_summaries() -> Vec<VersionSummary> {
+        let mut summaries: Vec<VersionSummary> = [(1, 100), (2, 150), (3, 120)]
+            .into_iter()
+            .map(|(version, size_kb)| {
+                summarize(&doc! {
+                    "version": version,
+                    "date": DateTime::from_millis(version as i64 * 1000),
+                    "utc_offset": 3600,
+                    "size_kb": size_kb as i64,
+                    "file_count": 2i64,
+                    "dir_count": 1i64,
+                })
+            })
+            .collect();
+        fill_deltas(&mut summaries);
+        summaries
+    }
+
+    #[test]
+    fn test_summarize_and_fill_deltas() {
+        let summaries = create_summaries();
+
+        assert_eq!(
+            summaries[0].date.as_deref(),
+            Some("1970-01-01T01:00:01+01:00")
+        );
+        assert_eq!(summaries[1].files, Some(2));
+        let deltas: Vec<Option<i64>> = summaries.iter().map(|summary| summary.delta_kb).collect();
+        assert_eq!(deltas, Vec::from([None, Some(50), Some(-30)]));
+    }
+
+    #[test]
+    fn test_sort_and_page() {
+        let mut summaries = create_summaries();
+        sort_summaries(&mut summaries, SortKey::Size, true);
+
+        let versions: Vec<i32> = summaries.iter().map(|summary| summary.version).collect();
+        assert_eq!(versions, Vec::from([2, 3, 1]));
+        assert_eq!(get_page(&summaries, 2, 2)[0].version, 1);
+        assert!(get_page(&summaries, 5, 2).is_empty());
+    }
+
+    #[test]
+    fn test_render_table() {
+        let table = render_table(&create_summaries());
+        let lines: Vec<&str> = table.lines().collect();
+
+        assert!(lines[0].starts_with("VERSION  DATE"));
+        assert!(lines[2].contains("150 B"));
+        assert!(lines[2].contains("+50 B"));
+        assert!(lines[1].contains("1970-01-01 01:00:01 +01:00"));
+    }
+}
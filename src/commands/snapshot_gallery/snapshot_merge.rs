@@ -0,0 +1,273 @@
+use std::env;
+use std::path::Path;
+
+use mongodb::{Collection, Database};
+use serde::Serialize;
+
+use crate::cli::Args;
+use crate::common::{self, format_size};
+use crate::errors::ServiceError;
+use crate::snapshot::{Series, Snapshot};
+
+use super::snapshot_comparison::{self, MergeChange, MergeKind};
+use super::{choose_host, get_snapshot, snapshot_labels, snapshot_listing};
+
+/// Side of a three-way comparison: the series and version it was taken from.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Side {
+    pub host: String,
+    pub version: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MergeReport {
+    pub path: String,
+    pub base: Side,
+    pub a: Side,
+    pub b: Side,
+    pub changes: Vec<MergeChange>,
+}
+
+#[cfg(not(tarpaulin_include))]
+pub async fn merge_menu(
+    series: &Series,
+    collection: &Collection<Snapshot>,
+) -> Result<(), ServiceError> {
+    println!("Please input the base version or label or 0 - to choose the last one: ");
+    let base = snapshot_labels::read_version(series, collection).await?;
+
+    let mut sides = Vec::new();
+    for name in ["A", "B"] {
+        println!(
+            "Take {name} from: 1 - this host ({}) else another host",
+            series.host
+        );
+        let side = if common::get_input() == 1 {
+            series.clone()
+        } else {
+            match choose_host(series, collection).await? {
+                Some(other) => other,
+                None => return Ok(()),
+            }
+        };
+        println!("Please input the version or label of {name} or 0 - to choose the last one: ");
+        let version = snapshot_labels::read_version(&side, collection).await?;
+        sides.push((side, version));
+    }
+
+    let (Some(base), Some(a), Some(b)) = (
+        get_snapshot(series, collection, base).await?,
+        get_snapshot(&sides[0].0, collection, sides[0].1).await?,
+        get_snapshot(&sides[1].0, collection, sides[1].1).await?,
+    ) else {
+        println!("Sorry, but we can't find snapshot. Please check versions");
+        return Ok(());
+    };
+
+    let report = merge_report([(series, &base), (&sides[0].0, &a), (&sides[1].0, &b)]);
+    common::print_paged(&render_report(&report, false));
+
+    Ok(())
+}
+
+/// `merge [path] --base=V --a=V --b=V [--host=H] [--a-host=H] [--b-host=H]
+/// [--conflicts-only] [--no-color] [--json]`
+///
+/// `--host` is the host of the base, which `A` and `B` default to.
+#[cfg(not(tarpaulin_include))]
+pub async fn merge_command(args: &Args, path: &Path, db: &Database) -> Result<(), ServiceError> {
+    let series = snapshot_listing::get_series(args, path)?;
+    let collection: Collection<Snapshot> =
+        db.collection(&env::var("COLL_NAME").expect("COLL_NAME must be set"));
+
+    let side = |host: &str| match args.get(host) {
+        Some(host) => Series::create(host, &series.path),
+        None => series.clone(),
+    };
+    let a_series = side("a-host");
+    let b_series = side("b-host");
+
+    let mut snapshots = Vec::new();
+    for (side, name) in [(&series, "base"), (&a_series, "a"), (&b_series, "b")] {
+        let version = args.get(name).ok_or(ServiceError::WrongNumberOfArgs)?;
+        snapshots.push(snapshot_labels::load_version(side, &collection, version).await?);
+    }
+
+    let mut report = merge_report([
+        (&series, &snapshots[0]),
+        (&a_series, &snapshots[1]),
+        (&b_series, &snapshots[2]),
+    ]);
+    if args.has_flag("conflicts-only") {
+        report
+            .changes
+            .retain(|change| change.kind == MergeKind::Conflict);
+    }
+
+    if args.has_flag("json") {
+        let json = serde_json::to_string_pretty(&report).or(Err(ServiceError::FailedToExport))?;
+        println!("{json}");
+    } else {
+        common::print_paged(&render_report(
+            &report,
+            common::use_color(args.has_flag("no-color")),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Compares `A` and `B` with the base they were derived from.
+pub fn merge_report(sides: [(&Series, &Snapshot); 3]) -> MergeReport {
+    let [base, a, b] = sides.map(|(series, snapshot)| Side {
+        host: series.host.clone(),
+        version: snapshot.version,
+    });
+
+    MergeReport {
+        path: sides[0].1.path.clone(),
+        base,
+        a,
+        b,
+        changes: snapshot_comparison::compare_three(sides[0].1, sides[1].1, sides[2].1),
+    }
+}
+
+fn describe_size(size: Option<u64>) -> String {
+    size.map_or(String::from("missing"), format_size)
+}
+
+/// One line per entry: `A` or `B` for a change on one side only, `=` for
+/// the same change on both, `!` for a conflict. `color` adds ANSI colors.
+pub fn render_report(report: &MergeReport, color: bool) -> String {
+    let describe = |side: &Side| format!("version {} on {}", side.version, side.host);
+    let mut lines = Vec::from([
+        format!("base: {}", describe(&report.base)),
+        format!("A:    {}", describe(&report.a)),
+        format!("B:    {}", describe(&report.b)),
+        String::new(),
+    ]);
+
+    for change in &report.changes {
+        let slash = if change.is_dir { "/" } else { "" };
+        let (marker, code, sizes) = match change.kind {
+            MergeKind::OnlyInA => (
+                "A",
+                "32",
+                format!(
+                    "{} -> {}",
+                    describe_size(change.base_size),
+                    describe_size(change.a_size)
+                ),
+            ),
+            MergeKind::OnlyInB => (
+                "B",
+                "36",
+                format!(
+                    "{} -> {}",
+                    describe_size(change.base_size),
+                    describe_size(change.b_size)
+                ),
+            ),
+            MergeKind::Identical => (
+                "=",
+                "33",
+                format!(
+                    "{} -> {}",
+                    describe_size(change.base_size),
+                    describe_size(change.a_size)
+                ),
+            ),
+            MergeKind::Conflict => (
+                "!",
+                "31",
+                format!(
+                    "base {}, A {}, B {}",
+                    describe_size(change.base_size),
+                    describe_size(change.a_size),
+                    describe_size(change.b_size)
+                ),
+            ),
+        };
+
+        let line = format!("{marker} {}{slash} ({sizes})", change.path);
+        lines.push(if color {
+            format!("\x1b[{code}m{line}\x1b[0m")
+        } else {
+            line
+        });
+    }
+
+    let count = |kind: MergeKind| {
+        report
+            .changes
+            .iter()
+            .filter(|change| change.kind == kind)
+            .count()
+    };
+    lines.push(String::new());
+    lines.push(format!(
+        "{} only in A, {} only in B, {} identical in both, {} conflicting",
+        count(MergeKind::OnlyInA),
+        count(MergeKind::OnlyInB),
+        count(MergeKind::Identical),
+        count(MergeKind::Conflict)
+    ));
+
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::snapshot::fixtures::{self, create_file};
+    use crate::snapshot::{Series, Snapshot};
+
+    use super::{merge_report, render_report};
+
+    fn create_snapshot(version: i32, files: &[(&str, u64)]) -> Snapshot {
+        let files = files
+            .iter()
+            .map(|(name, size)| create_file(name, *size))
+            .collect();
+        fixtures::create_snapshot(version, "/etc/app", files, Vec::new())
+    }
+
+    fn create_report() -> super::MergeReport {
+        let golden = Series::create("golden", "/etc/app");
+        let staging = Series::create("staging", "/etc/app");
+        let production = Series::create("production", "/etc/app");
+
+        let base = create_snapshot(1, &[("app.yaml", 100), ("db.yaml", 50), ("old.conf", 10)]);
+        let a = create_snapshot(4, &[("app.yaml", 120), ("db.yaml", 60), ("old.conf", 10)]);
+        let b = create_snapshot(7, &[("app.yaml", 130), ("db.yaml", 60)]);
+
+        merge_report([(&golden, &base), (&staging, &a), (&production, &b)])
+    }
+
+    #[test]
+    fn test_merge_report() {
+        let report = create_report();
+
+        assert_eq!(report.path, "/etc/app");
+        assert_eq!((report.a.host.as_str(), report.a.version), ("staging", 4));
+        assert_eq!(report.changes.len(), 3);
+    }
+
+    #[test]
+    fn test_render_report() {
+        let rendered = render_report(&create_report(), false);
+        let lines: Vec<&str> = rendered.lines().collect();
+
+        assert_eq!(lines[0], "base: version 1 on golden");
+        assert_eq!(lines[4], "! app.yaml (base 100 B, A 120 B, B 130 B)");
+        assert_eq!(lines[5], "= db.yaml (50 B -> 60 B)");
+        assert_eq!(lines[6], "B old.conf (10 B -> missing)");
+        assert_eq!(
+            lines[8],
+            "0 only in A, 1 only in B, 1 identical in both, 1 conflicting"
+        );
+
+        let colored = render_report(&create_report(), true);
+        assert!(colored.contains("\x1b[31m! app.yaml"));
+    }
+}
@@ -0,0 +1,508 @@
+use std::env;
+use std::path::Path;
+use std::str::FromStr;
+
+use glob::Pattern;
+use mongodb::bson::{doc, Document};
+use mongodb::options::FindOptions;
+use mongodb::{Collection, Database};
+use regex::Regex;
+use serde::Serialize;
+
+use crate::cli::Args;
+use crate::common::{self, format_size};
+use crate::errors::ServiceError;
+use crate::filter::{self, Filter};
+use crate::snapshot::{self, Directory, File, Metadata, Series, Snapshot};
+
+use super::{get_snapshot, snapshot_labels, snapshot_listing};
+
+/// How an entry name is matched. Globs without a `/` match the name of the
+/// entry, other globs and regular expressions its relative path.
+#[derive(Debug, Clone)]
+pub enum Matcher {
+    Glob(Pattern),
+    Regex(Regex),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    File,
+    Dir,
+}
+
+impl FromStr for Kind {
+    type Err = ServiceError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "file" | "f" => Ok(Kind::File),
+            "dir" | "d" => Ok(Kind::Dir),
+            _ => Err(ServiceError::InvalidArgument),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SearchQuery {
+    pub matcher: Option<Matcher>,
+    pub kind: Option<Kind>,
+    /// Bounds in bytes, both inclusive.
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+    pub filter: Option<Filter>,
+}
+
+/// Snapshots a search looks into.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scope {
+    Version(i32),
+    Series,
+    Store,
+}
+
+/// Entry found by a search; `entry` is relative to the snapshot path.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SearchHit {
+    pub host: String,
+    pub path: String,
+    pub version: i32,
+    pub entry: String,
+    pub is_dir: bool,
+    pub bytes: u64,
+}
+
+impl Matcher {
+    pub fn glob(pattern: &str) -> Result<Matcher, ServiceError> {
+        match Pattern::new(pattern) {
+            Ok(pattern) => Ok(Matcher::Glob(pattern)),
+            Err(_) => Err(ServiceError::InvalidArgument),
+        }
+    }
+
+    pub fn regex(pattern: &str) -> Result<Matcher, ServiceError> {
+        match Regex::new(pattern) {
+            Ok(regex) => Ok(Matcher::Regex(regex)),
+            Err(_) => Err(ServiceError::InvalidArgument),
+        }
+    }
+
+    pub fn is_match(&self, name: &str, path: &str) -> bool {
+        match self {
+            Matcher::Glob(pattern) if pattern.as_str().contains('/') => pattern.matches(path),
+            Matcher::Glob(pattern) => pattern.matches(name),
+            Matcher::Regex(regex) => regex.is_match(path),
+        }
+    }
+}
+
+impl SearchQuery {
+    fn accepts(&self, entry: &filter::Entry) -> bool {
+        let kind = if entry.is_dir { Kind::Dir } else { Kind::File };
+
+        self.kind.is_none_or(|wanted| wanted == kind)
+            && self.min_size.is_none_or(|min| entry.bytes >= min)
+            && self.max_size.is_none_or(|max| entry.bytes <= max)
+            && self
+                .matcher
+                .as_ref()
+                .is_none_or(|matcher| matcher.is_match(entry.name, entry.path))
+            && self
+                .filter
+                .as_ref()
+                .is_none_or(|filter| filter.matches(entry))
+    }
+}
+
+#[cfg(not(tarpaulin_include))]
+pub async fn search_menu(
+    series: &Series,
+    collection: &Collection<Snapshot>,
+) -> Result<(), ServiceError> {
+    println!(
+        "Please input the pattern (a glob like *.env, or re:<regular expression>) or leave it empty:"
+    );
+    let pattern = common::get_string_input();
+    let matcher = match pattern.strip_prefix("re:") {
+        _ if pattern.is_empty() => None,
+        Some(regex) => Some(Matcher::regex(regex)?),
+        None => Some(Matcher::glob(&pattern)?),
+    };
+
+    println!("Search for: 1. Files and directories 2. Files 3. Directories");
+    let kind = match common::get_input() {
+        2 => Some(Kind::File),
+        3 => Some(Kind::Dir),
+        _ => None,
+    };
+
+    println!("Please input the minimum size (like 10MiB) or leave it empty:");
+    let min_size = read_size()?;
+    println!("Please input the maximum size or leave it empty:");
+    let max_size = read_size()?;
+    println!(
+        "Please input a filter expression (like ext = \"log\" and mtime < 30d) or leave it empty:"
+    );
+    let filter = filter::read_filter()?;
+
+    println!(
+        "Search in: 1. One version 2. All versions of this path 3. Every snapshot in the store"
+    );
+    let scope = match common::get_input() {
+        1 => {
+            println!("Please, input the number or label of version or 0 to choose the last one:");
+            Scope::Version(snapshot_labels::read_version(series, collection).await?)
+        }
+        2 => Scope::Series,
+        _ => Scope::Store,
+    };
+
+    let query = SearchQuery {
+        matcher,
+        kind,
+        min_size,
+        max_size,
+        filter,
+    };
+    let hits = search(series, collection, &query, scope).await?;
+    println!("{}", render_hits(&hits));
+
+    Ok(())
+}
+
+#[cfg(not(tarpaulin_include))]
+fn read_size() -> Result<Option<u64>, ServiceError> {
+    let input = common::get_string_input();
+
+    if input.is_empty() {
+        return Ok(None);
+    }
+
+    common::parse_size(&input)
+        .map(Some)
+        .ok_or(ServiceError::InvalidArgument)
+}
+
+/// `search [path] [--glob=PATTERN | --regex=RE] [--filter=EXPR]
+/// [--kind=file|dir] [--min-size=SIZE] [--max-size=SIZE] [--version=V |
+/// --all-versions | --everywhere] [--host=H] [--json]`
+#[cfg(not(tarpaulin_include))]
+pub async fn search_command(args: &Args, path: &Path, db: &Database) -> Result<(), ServiceError> {
+    let series = snapshot_listing::get_series(args, path)?;
+    let collection: Collection<Snapshot> =
+        db.collection(&env::var("COLL_NAME").expect("COLL_NAME must be set"));
+
+    let filter = args.get("filter").map(Filter::parse).transpose()?;
+    let matcher = match (args.get("glob"), args.get("regex")) {
+        (Some(pattern), None) => Some(Matcher::glob(pattern)?),
+        (None, Some(regex)) => Some(Matcher::regex(regex)?),
+        (None, None) if filter.is_some() => None,
+        _ => return Err(ServiceError::WrongNumberOfArgs),
+    };
+    let size = |name: &str| {
+        args.get(name)
+            .map(|size| common::parse_size(size).ok_or(ServiceError::InvalidArgument))
+            .transpose()
+    };
+    let query = SearchQuery {
+        matcher,
+        kind: args.get("kind").map(str::parse).transpose()?,
+        min_size: size("min-size")?,
+        max_size: size("max-size")?,
+        filter,
+    };
+
+    let scope = if args.has_flag("everywhere") {
+        Scope::Store
+    } else if args.has_flag("all-versions") {
+        Scope::Series
+    } else {
+        let version = args.get("version").unwrap_or("0");
+        Scope::Version(
+            snapshot_labels::resolve_version(&series, &collection, version)
+                .await?
+                .ok_or(ServiceError::InvalidArgument)?,
+        )
+    };
+
+    let hits = search(&series, &collection, &query, scope).await?;
+
+    if args.has_flag("json") {
+        let json = serde_json::to_string_pretty(&hits).or(Err(ServiceError::FailedToExport))?;
+        println!("{json}");
+    } else {
+        println!("{}", render_hits(&hits));
+    }
+
+    Ok(())
+}
+
+pub async fn search(
+    series: &Series,
+    collection: &Collection<Snapshot>,
+    query: &SearchQuery,
+    scope: Scope,
+) -> Result<Vec<SearchHit>, ServiceError> {
+    let targets = match scope {
+        Scope::Version(version) => Vec::from([(series.clone(), version)]),
+        Scope::Series => snapshot_listing::load_summaries(series, collection)
+            .await?
+            .iter()
+            .map(|summary| (series.clone(), summary.version))
+            .collect(),
+        Scope::Store => list_snapshots(collection).await?,
+    };
+
+    let mut hits = Vec::new();
+    for (series, version) in targets {
+        if let Some(loaded) = get_snapshot(&series, collection, version).await? {
+            for (entry, is_dir, bytes) in search_tree(&loaded.files, &loaded.dirs, query) {
+                hits.push(SearchHit {
+                    host: series.host.clone(),
+                    path: series.path.clone(),
+                    version: loaded.version,
+                    entry,
+                    is_dir,
+                    bytes,
+                });
+            }
+        }
+    }
+
+    Ok(hits)
+}
+
+/// Every stored version as its series and number, ordered by path, host
+/// and version. Versions without a host get an empty one.
+pub async fn list_snapshots(
+    collection: &Collection<Snapshot>,
+) -> Result<Vec<(Series, i32)>, ServiceError> {
+    let options = FindOptions::builder()
+        .sort(doc! {"path": 1, "host": 1, "version": 1})
+        .projection(doc! {"path": 1, "host": 1, "version": 1})
+        .build();
+    let mut cursor = match collection
+        .clone_with_type::<Document>()
+        .find(doc! {"version": {"$exists": true}}, options)
+        .await
+    {
+        Ok(cursor) => cursor,
+        Err(_) => return Err(ServiceError::FailedToFoundCollection),
+    };
+
+    let mut snapshots = Vec::new();
+    while cursor
+        .advance()
+        .await
+        .or(Err(ServiceError::FailedToFoundCollection))?
+    {
+        let Ok(current) = cursor.deserialize_current() else {
+            continue;
+        };
+        if let (Ok(path), Ok(version)) = (current.get_str("path"), current.get_i32("version")) {
+            let host = current.get_str("host").unwrap_or_default();
+            snapshots.push((Series::create(host, path), version));
+        }
+    }
+
+    Ok(snapshots)
+}
+
+/// Entries of the tree accepted by the query, as relative path, kind and
+/// size in bytes, in walking order.
+pub fn search_tree(
+    files: &[File],
+    dirs: &[Directory],
+    query: &SearchQuery,
+) -> Vec<(String, bool, u64)> {
+    let mut found = Vec::new();
+    walk(files, dirs, "", 1, query, &mut found);
+    found
+}
+
+fn walk(
+    files: &[File],
+    dirs: &[Directory],
+    prefix: &str,
+    depth: usize,
+    query: &SearchQuery,
+    found: &mut Vec<(String, bool, u64)>,
+) {
+    for dir in dirs {
+        let path = format!("{prefix}{}", dir.name);
+        let bytes = snapshot::total_bytes(&dir.files, &dir.dirs);
+        if query.accepts(&create_entry(
+            &dir.name, &path, true, bytes, depth, &dir.meta,
+        )) {
+            found.push((path.clone(), true, bytes));
+        }
+        walk(
+            &dir.files,
+            &dir.dirs,
+            &format!("{path}/"),
+            depth + 1,
+            query,
+            found,
+        );
+    }
+
+    for file in files {
+        let path = format!("{prefix}{}", file.name);
+        let entry = create_entry(&file.name, &path, false, file.size_kb, depth, &file.meta);
+        if query.accepts(&entry) {
+            found.push((path, false, file.size_kb));
+        }
+    }
+}
+
+fn create_entry<'a>(
+    name: &'a str,
+    path: &'a str,
+    is_dir: bool,
+    bytes: u64,
+    depth: usize,
+    meta: &'a Metadata,
+) -> filter::Entry<'a> {
+    filter::Entry {
+        name,
+        path,
+        is_dir,
+        bytes,
+        depth,
+        meta,
+    }
+}
+
+pub fn render_hits(hits: &[SearchHit]) -> String {
+    if hits.is_empty() {
+        return String::from("Nothing matches");
+    }
+
+    let mut lines: Vec<String> = hits
+        .iter()
+        .map(|hit| {
+            let slash = if hit.is_dir { "/" } else { "" };
+            let host = if hit.host.is_empty() { "-" } else { &hit.host };
+            format!(
+                "{host}:{} version {}: {}{slash} ({})",
+                hit.path,
+                hit.version,
+                hit.entry,
+                format_size(hit.bytes)
+            )
+        })
+        .collect();
+
+    // The latest version of every series that still had a match.
+    let mut last: Vec<&SearchHit> = Vec::new();
+    for hit in hits {
+        match last
+            .iter_mut()
+            .find(|known| known.host == hit.host && known.path == hit.path)
+        {
+            Some(known) if known.version < hit.version => *known = hit,
+            Some(_) => {}
+            None => last.push(hit),
+        }
+    }
+    lines.push(String::new());
+    for hit in last {
+        let host = if hit.host.is_empty() { "-" } else { &hit.host };
+        lines.push(format!(
+            "Last found in version {} of {host}:{}",
+            hit.version, hit.path
+        ));
+    }
+
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::snapshot::fixtures::{create_dir, create_file};
+    use crate::snapshot::{Directory, File};
+
+    use crate::filter::Filter;
+
+    use super::{render_hits, search_tree, Kind, Matcher, SearchHit, SearchQuery};
+
+    fn create_tree() -> (Vec<File>, Vec<Directory>) {
+        let files = Vec::from([create_file("secrets.env", 10), create_file("app.log", 5000)]);
+        let dirs = Vec::from([create_dir(
+            "config",
+            Vec::from([create_file("prod.env", 20), create_file("app.yaml", 30)]),
+            Vec::new(),
+        )]);
+        (files, dirs)
+    }
+
+    fn query(matcher: Matcher) -> SearchQuery {
+        SearchQuery {
+            matcher: Some(matcher),
+            kind: None,
+            min_size: None,
+            max_size: None,
+            filter: None,
+        }
+    }
+
+    #[test]
+    fn test_search_by_glob_and_regex() {
+        let (files, dirs) = create_tree();
+
+        let found = search_tree(&files, &dirs, &query(Matcher::glob("*.env").unwrap()));
+        let paths: Vec<&str> = found.iter().map(|(path, _, _)| path.as_str()).collect();
+        assert_eq!(paths, Vec::from(["config/prod.env", "secrets.env"]));
+
+        let found = search_tree(&files, &dirs, &query(Matcher::glob("config/*").unwrap()));
+        assert_eq!(found.len(), 2);
+
+        let found = search_tree(&files, &dirs, &query(Matcher::regex("^conf").unwrap()));
+        assert_eq!(found[0], (String::from("config"), true, 50));
+        assert!(Matcher::regex("(").is_err());
+    }
+
+    #[test]
+    fn test_search_with_filters() {
+        let (files, dirs) = create_tree();
+        let mut query = query(Matcher::glob("*").unwrap());
+
+        query.kind = Some(Kind::Dir);
+        assert_eq!(search_tree(&files, &dirs, &query).len(), 1);
+
+        query.kind = Some(Kind::File);
+        query.min_size = Some(20);
+        query.max_size = Some(1000);
+        let found = search_tree(&files, &dirs, &query);
+        let paths: Vec<&str> = found.iter().map(|(path, _, _)| path.as_str()).collect();
+        assert_eq!(paths, Vec::from(["config/prod.env", "config/app.yaml"]));
+
+        query.matcher = None;
+        query.kind = None;
+        query.min_size = None;
+        query.max_size = None;
+        query.filter = Some(Filter::parse("ext = env and depth = 2").unwrap());
+        let found = search_tree(&files, &dirs, &query);
+        assert_eq!(
+            found,
+            Vec::from([(String::from("config/prod.env"), false, 20)])
+        );
+    }
+
+    #[test]
+    fn test_render_hits() {
+        let hit = |version: i32| SearchHit {
+            host: String::from("web-1"),
+            path: String::from("/srv"),
+            version,
+            entry: String::from("secrets.env"),
+            is_dir: false,
+            bytes: 10,
+        };
+        let rendered = render_hits(&[hit(2), hit(5)]);
+
+        assert!(rendered.starts_with("web-1:/srv version 2: secrets.env (10 B)"));
+        assert!(rendered.ends_with("Last found in version 5 of web-1:/srv"));
+        assert_eq!(render_hits(&[]), "Nothing matches");
+    }
+}
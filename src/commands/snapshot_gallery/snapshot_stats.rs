@@ -0,0 +1,505 @@
+use std::env;
+use std::path::Path;
+
+use mongodb::{Collection, Database};
+use serde::Serialize;
+
+use crate::cli::Args;
+use crate::common::{self, format_delta, format_size};
+use crate::errors::ServiceError;
+use crate::snapshot::{self, Directory, File, Series, Snapshot};
+
+use super::snapshot_usage::percent;
+use super::{get_snapshot, snapshot_labels, snapshot_listing};
+
+const DEFAULT_TOP: usize = 10;
+
+/// Upper bounds, exclusive, of the size buckets after the one of empty
+/// files. Larger files fall into the last bucket.
+const BUCKETS: [(u64, &str); 6] = [
+    (1 << 10, "< 1 KiB"),
+    (64 << 10, "< 64 KiB"),
+    (1 << 20, "< 1 MiB"),
+    (16 << 20, "< 16 MiB"),
+    (256 << 20, "< 256 MiB"),
+    (1 << 30, "< 1 GiB"),
+];
+
+/// Number of entries and the bytes of their files under one key.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Stat {
+    pub key: String,
+    pub count: u64,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PathStat {
+    pub path: String,
+    pub value: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SnapshotStats {
+    pub version: i32,
+    pub files: u64,
+    pub dirs: u64,
+    pub total_bytes: u64,
+    /// Files by lowercase extension, largest first; `(none)` without one.
+    pub by_extension: Vec<Stat>,
+    /// Entries by kind: `file`, `link` and `dir`.
+    pub by_kind: Vec<Stat>,
+    /// Files by size, smallest bucket first.
+    pub size_buckets: Vec<Stat>,
+    pub max_depth: usize,
+    /// Deepest entries with their depth; root entries are at depth 1.
+    pub deepest: Vec<PathStat>,
+    /// Directories with the most direct entries; the root is `.`.
+    pub widest: Vec<PathStat>,
+}
+
+/// A statistic that differs between two versions.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StatChange {
+    pub section: String,
+    pub key: String,
+    pub old_count: u64,
+    pub new_count: u64,
+    pub old_bytes: u64,
+    pub new_bytes: u64,
+}
+
+#[cfg(not(tarpaulin_include))]
+pub async fn stats_menu(
+    series: &Series,
+    collection: &Collection<Snapshot>,
+) -> Result<(), ServiceError> {
+    println!("Please, input the number or label of version or 0 to choose the last one:");
+    let version = snapshot_labels::read_version(series, collection).await?;
+
+    let Some(snapshot) = get_snapshot(series, collection, version).await? else {
+        println!("Snapshot isn't found. Check the version");
+        return Ok(());
+    };
+    let stats = snapshot_stats(&snapshot, DEFAULT_TOP);
+
+    println!("Compare with another version? 1 - yes else no");
+    if common::get_input() != 1 {
+        println!("{}", render_stats(&stats));
+        return Ok(());
+    }
+
+    println!("Please, input the number or label of the older version:");
+    let base = snapshot_labels::read_version(series, collection).await?;
+    let Some(base) = get_snapshot(series, collection, base).await? else {
+        println!("Snapshot isn't found. Check the version");
+        return Ok(());
+    };
+
+    let changes = diff_stats(&snapshot_stats(&base, DEFAULT_TOP), &stats);
+    println!("{}", render_changes(base.version, stats.version, &changes));
+
+    Ok(())
+}
+
+/// `stats [path] [--version=V] [--compare=V] [--host=H] [--top=N] [--json]`
+///
+/// With `--compare` the changes from that version to `--version` are shown.
+#[cfg(not(tarpaulin_include))]
+pub async fn stats_command(args: &Args, path: &Path, db: &Database) -> Result<(), ServiceError> {
+    let series = snapshot_listing::get_series(args, path)?;
+    let collection: Collection<Snapshot> =
+        db.collection(&env::var("COLL_NAME").expect("COLL_NAME must be set"));
+    let top = args.get_or("top", DEFAULT_TOP)?;
+
+    let stats = snapshot_stats(
+        &snapshot_labels::load_version(&series, &collection, args.get("version").unwrap_or("0"))
+            .await?,
+        top,
+    );
+    let json = match args.get("compare") {
+        Some(base) => {
+            let base = snapshot_stats(
+                &snapshot_labels::load_version(&series, &collection, base).await?,
+                top,
+            );
+            let changes = diff_stats(&base, &stats);
+            if !args.has_flag("json") {
+                println!("{}", render_changes(base.version, stats.version, &changes));
+                return Ok(());
+            }
+            serde_json::to_string_pretty(&changes)
+        }
+        None => {
+            if !args.has_flag("json") {
+                println!("{}", render_stats(&stats));
+                return Ok(());
+            }
+            serde_json::to_string_pretty(&stats)
+        }
+    };
+
+    println!("{}", json.or(Err(ServiceError::FailedToExport))?);
+
+    Ok(())
+}
+
+/// Computes the statistics from the stored tree, keeping the `top` deepest
+/// entries and widest directories.
+pub fn snapshot_stats(snapshot: &Snapshot, top: usize) -> SnapshotStats {
+    let mut stats = SnapshotStats {
+        version: snapshot.version,
+        files: 0,
+        dirs: 0,
+        total_bytes: snapshot::total_bytes(&snapshot.files, &snapshot.dirs),
+        by_extension: Vec::new(),
+        by_kind: ["file", "link", "dir"].map(stat).to_vec(),
+        size_buckets: Vec::from([stat("empty")]),
+        max_depth: 0,
+        deepest: Vec::new(),
+        widest: Vec::new(),
+    };
+    stats
+        .size_buckets
+        .extend(BUCKETS.iter().map(|(_, label)| stat(label)));
+    stats.size_buckets.push(stat(">= 1 GiB"));
+
+    collect(&snapshot.files, &snapshot.dirs, "", 1, &mut stats);
+
+    stats
+        .by_extension
+        .sort_by(|a, b| b.bytes.cmp(&a.bytes).then(a.key.cmp(&b.key)));
+    stats.max_depth = stats
+        .deepest
+        .iter()
+        .map(|entry| entry.value)
+        .max()
+        .unwrap_or(0);
+    for entries in [&mut stats.deepest, &mut stats.widest] {
+        entries.sort_by(|a, b| b.value.cmp(&a.value).then(a.path.cmp(&b.path)));
+        entries.truncate(top);
+    }
+
+    stats
+}
+
+fn stat(key: &str) -> Stat {
+    Stat {
+        key: String::from(key),
+        count: 0,
+        bytes: 0,
+    }
+}
+
+fn add(stats: &mut Vec<Stat>, key: &str, bytes: u64) {
+    match stats.iter_mut().find(|stat| stat.key == key) {
+        Some(stat) => {
+            stat.count += 1;
+            stat.bytes += bytes;
+        }
+        None => stats.push(Stat {
+            key: String::from(key),
+            count: 1,
+            bytes,
+        }),
+    }
+}
+
+fn collect(
+    files: &[File],
+    dirs: &[Directory],
+    prefix: &str,
+    depth: usize,
+    stats: &mut SnapshotStats,
+) {
+    stats.widest.push(PathStat {
+        path: String::from(prefix.strip_suffix('/').unwrap_or(".")),
+        value: files.len() + dirs.len(),
+    });
+
+    for file in files {
+        let bytes = file.size_kb;
+        stats.files += 1;
+
+        let extension = Path::new(&file.name)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_lowercase);
+        add(
+            &mut stats.by_extension,
+            extension.as_deref().unwrap_or("(none)"),
+            bytes,
+        );
+
+        let kind = if file.meta.link.is_some() {
+            "link"
+        } else {
+            "file"
+        };
+        add(&mut stats.by_kind, kind, bytes);
+
+        let bucket = if bytes == 0 {
+            0
+        } else {
+            1 + BUCKETS
+                .iter()
+                .position(|(limit, _)| bytes < *limit)
+                .unwrap_or(BUCKETS.len())
+        };
+        stats.size_buckets[bucket].count += 1;
+        stats.size_buckets[bucket].bytes += bytes;
+
+        stats.deepest.push(PathStat {
+            path: format!("{prefix}{}", file.name),
+            value: depth,
+        });
+    }
+
+    for dir in dirs {
+        stats.dirs += 1;
+        add(&mut stats.by_kind, "dir", 0);
+
+        let path = format!("{prefix}{}", dir.name);
+        stats.deepest.push(PathStat {
+            path: path.clone(),
+            value: depth,
+        });
+        collect(&dir.files, &dir.dirs, &format!("{path}/"), depth + 1, stats);
+    }
+}
+
+/// Statistics that differ from `old` to `new`, section by section.
+pub fn diff_stats(old: &SnapshotStats, new: &SnapshotStats) -> Vec<StatChange> {
+    let mut changes = Vec::new();
+
+    let totals = |stats: &SnapshotStats| {
+        Vec::from([
+            Stat {
+                key: String::from("files"),
+                count: stats.files,
+                bytes: stats.total_bytes,
+            },
+            Stat {
+                key: String::from("dirs"),
+                count: stats.dirs,
+                bytes: 0,
+            },
+            Stat {
+                key: String::from("max depth"),
+                count: stats.max_depth as u64,
+                bytes: 0,
+            },
+            Stat {
+                key: String::from("max width"),
+                count: stats.widest.first().map_or(0, |dir| dir.value as u64),
+                bytes: 0,
+            },
+        ])
+    };
+    let sections = [
+        ("total", totals(old), totals(new)),
+        ("kind", old.by_kind.clone(), new.by_kind.clone()),
+        (
+            "extension",
+            old.by_extension.clone(),
+            new.by_extension.clone(),
+        ),
+        ("size", old.size_buckets.clone(), new.size_buckets.clone()),
+    ];
+
+    for (section, old_stats, new_stats) in sections {
+        let mut keys: Vec<&str> = new_stats.iter().map(|stat| stat.key.as_str()).collect();
+        for stat in &old_stats {
+            if !keys.contains(&stat.key.as_str()) {
+                keys.push(&stat.key);
+            }
+        }
+
+        for key in keys {
+            let find = |stats: &[Stat]| {
+                stats
+                    .iter()
+                    .find(|stat| stat.key == key)
+                    .map_or((0, 0), |stat| (stat.count, stat.bytes))
+            };
+            let (old_count, old_bytes) = find(&old_stats);
+            let (new_count, new_bytes) = find(&new_stats);
+
+            if old_count != new_count || old_bytes != new_bytes {
+                changes.push(StatChange {
+                    section: String::from(section),
+                    key: String::from(key),
+                    old_count,
+                    new_count,
+                    old_bytes,
+                    new_bytes,
+                });
+            }
+        }
+    }
+
+    changes
+}
+
+pub fn render_stats(stats: &SnapshotStats) -> String {
+    let mut lines = Vec::from([format!(
+        "Statistics of version {}: {} files, {} directories, {}",
+        stats.version,
+        stats.files,
+        stats.dirs,
+        format_size(stats.total_bytes)
+    )]);
+
+    let sections = [
+        ("By kind", &stats.by_kind),
+        ("By extension", &stats.by_extension),
+        ("By size", &stats.size_buckets),
+    ];
+    for (title, entries) in sections {
+        lines.push(String::new());
+        lines.push(format!("{title}:"));
+        for entry in entries {
+            lines.push(format!(
+                "{:<12} {:>8} {:>10} {:>6.1}%",
+                entry.key,
+                entry.count,
+                format_size(entry.bytes),
+                percent(entry.bytes, stats.total_bytes)
+            ));
+        }
+    }
+
+    lines.push(String::new());
+    lines.push(format!("Deepest entries (max depth {}):", stats.max_depth));
+    for entry in &stats.deepest {
+        lines.push(format!("{:>5} {}", entry.value, entry.path));
+    }
+
+    lines.push(String::new());
+    lines.push(String::from("Widest directories:"));
+    for entry in &stats.widest {
+        lines.push(format!("{:>5} {}", entry.value, entry.path));
+    }
+
+    lines.join("\n")
+}
+
+pub fn render_changes(old: i32, new: i32, changes: &[StatChange]) -> String {
+    let mut lines = Vec::from([format!(
+        "Statistics changed from version {old} to version {new}:"
+    )]);
+
+    if changes.is_empty() {
+        lines.push(String::from("No changes"));
+    }
+
+    for change in changes {
+        let count = change.new_count as i64 - change.old_count as i64;
+        let mut line = format!(
+            "{:<10} {:<12} {:>8} -> {:<8} ({count:+})",
+            change.section, change.key, change.old_count, change.new_count
+        );
+        if change.old_bytes != change.new_bytes {
+            line.push_str(&format!(
+                " {}",
+                format_delta(change.new_bytes as i64 - change.old_bytes as i64)
+            ));
+        }
+        lines.push(line);
+    }
+
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::snapshot::fixtures::{self, create_dir, create_file};
+    use crate::snapshot::{File, Snapshot};
+
+    use super::{diff_stats, render_changes, snapshot_stats};
+
+    fn create_snapshot(version: i32, extra: Option<File>) -> Snapshot {
+        let mut files = Vec::from([
+            create_file("README", 0),
+            create_file("a.LOG", 2000),
+            create_file("b.log", 500),
+        ]);
+        files.extend(extra);
+        fixtures::create_snapshot(
+            version,
+            "/data",
+            files,
+            Vec::from([create_dir(
+                "media",
+                Vec::from([create_file("clip.mp4", 100 << 20)]),
+                Vec::new(),
+            )]),
+        )
+    }
+
+    #[test]
+    fn test_snapshot_stats() {
+        let stats = snapshot_stats(&create_snapshot(1, None), 2);
+
+        assert_eq!((stats.files, stats.dirs), (4, 1));
+        let extensions: Vec<(&str, u64, u64)> = stats
+            .by_extension
+            .iter()
+            .map(|stat| (stat.key.as_str(), stat.count, stat.bytes))
+            .collect();
+        assert_eq!(
+            extensions,
+            Vec::from([("mp4", 1, 100 << 20), ("log", 2, 2500), ("(none)", 1, 0)])
+        );
+
+        let buckets: Vec<u64> = stats.size_buckets.iter().map(|stat| stat.count).collect();
+        assert_eq!(buckets, Vec::from([1, 1, 1, 0, 0, 1, 0, 0]));
+
+        assert_eq!(stats.max_depth, 2);
+        assert_eq!(stats.deepest[0].path, "media/clip.mp4");
+        let widest: Vec<(&str, usize)> = stats
+            .widest
+            .iter()
+            .map(|dir| (dir.path.as_str(), dir.value))
+            .collect();
+        assert_eq!(widest, Vec::from([(".", 4), ("media", 1)]));
+    }
+
+    #[test]
+    fn test_diff_stats() {
+        let old = snapshot_stats(&create_snapshot(1, None), 10);
+        let new = snapshot_stats(&create_snapshot(2, Some(create_file("c.txt", 100))), 10);
+
+        let changes = diff_stats(&old, &new);
+        let changes: Vec<(&str, &str, u64, u64)> = changes
+            .iter()
+            .map(|change| {
+                (
+                    change.section.as_str(),
+                    change.key.as_str(),
+                    change.old_count,
+                    change.new_count,
+                )
+            })
+            .collect();
+        assert_eq!(changes.len(), 5);
+        assert_eq!(changes[0], ("total", "files", 4, 5));
+        assert!(changes.contains(&("extension", "txt", 0, 1)));
+        assert!(changes.contains(&("size", "< 1 KiB", 1, 2)));
+        assert!(diff_stats(&old, &old).is_empty());
+    }
+
+    #[test]
+    fn test_render_changes() {
+        let old = snapshot_stats(&create_snapshot(1, None), 10);
+        let new = snapshot_stats(&create_snapshot(2, Some(create_file("c.txt", 100))), 10);
+        let rendered = render_changes(1, 2, &diff_stats(&old, &new));
+        let lines: Vec<&str> = rendered.lines().collect();
+
+        assert_eq!(lines[0], "Statistics changed from version 1 to version 2:");
+        assert_eq!(
+            lines[1],
+            "total      files               4 -> 5        (+1) +100 B"
+        );
+    }
+}
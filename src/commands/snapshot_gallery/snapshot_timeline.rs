@@ -0,0 +1,301 @@
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use mongodb::bson::{doc, Document};
+use mongodb::options::FindOptions;
+use mongodb::Collection;
+
+use crate::common;
+use crate::errors::ServiceError;
+use crate::snapshot::{self, Series, Snapshot};
+
+/// Version number and creation time of a stored snapshot.
+#[derive(Debug, PartialEq)]
+pub struct VersionDate {
+    pub version: i32,
+    pub date: DateTime<Utc>,
+}
+
+#[cfg(not(tarpaulin_include))]
+pub async fn range_menu(
+    series: &Series,
+    collection: &Collection<Snapshot>,
+) -> Result<(), ServiceError> {
+    println!("Please input the start of the range (a date, a time like 3d ago, or empty for the beginning):");
+    let from = read_time(parse_time)?;
+
+    println!(
+        "Please input the end of the range (a date, a time like yesterday, or empty for now):"
+    );
+    let to = read_time(parse_end)?;
+
+    super::snapshot_listing::show_summaries(series, collection, from, to).await
+}
+
+#[cfg(not(tarpaulin_include))]
+fn read_time<T>(parse: fn(&str, DateTime<Local>) -> Option<T>) -> Result<Option<T>, ServiceError> {
+    let input = common::get_string_input();
+
+    if input.is_empty() {
+        return Ok(None);
+    }
+
+    match parse(&input, Local::now()) {
+        Some(time) => Ok(Some(time)),
+        None => Err(ServiceError::InvalidArgument),
+    }
+}
+
+/// Resolves `as of <time>` to the latest version taken at or before that
+/// time and `before <time>` to the latest version taken strictly before it.
+/// Returns `None` for other input.
+pub async fn resolve_time(
+    series: &Series,
+    collection: &Collection<Snapshot>,
+    input: &str,
+) -> Result<Option<i32>, ServiceError> {
+    let end = if let Some(text) = input.strip_prefix("as of ") {
+        parse_end(text, Local::now())
+    } else if let Some(text) = input.strip_prefix("before ") {
+        parse_time(text, Local::now()).map(|time| (time, false))
+    } else {
+        return Ok(None);
+    };
+
+    let Some((time, inclusive)) = end else {
+        return Ok(None);
+    };
+
+    let dates = load_dates(series, collection).await?;
+
+    Ok(select_latest(&dates, time, inclusive))
+}
+
+pub async fn load_dates(
+    series: &Series,
+    collection: &Collection<Snapshot>,
+) -> Result<Vec<VersionDate>, ServiceError> {
+    let options = FindOptions::builder()
+        .projection(doc! {"version": 1, "date": 1})
+        .build();
+
+    let mut cursor = match collection
+        .clone_with_type::<Document>()
+        .find(series.filter(), options)
+        .await
+    {
+        Ok(cursor) => cursor,
+        Err(_) => return Err(ServiceError::FailedToFoundCollection),
+    };
+
+    let mut dates = Vec::new();
+    while cursor
+        .advance()
+        .await
+        .or(Err(ServiceError::FailedToFoundCollection))?
+    {
+        let Ok(current) = cursor.deserialize_current() else {
+            continue;
+        };
+        let version = current.get_i32("version");
+        let date = current.get("date").and_then(snapshot::parse_date);
+
+        if let (Ok(version), Some(date)) = (version, date) {
+            dates.push(VersionDate { version, date });
+        }
+    }
+
+    Ok(dates)
+}
+
+/// Picks the most recent version taken before `time`, or at `time` too when
+/// `inclusive` is set. Ties go to the higher version.
+pub fn select_latest(dates: &[VersionDate], time: DateTime<Utc>, inclusive: bool) -> Option<i32> {
+    dates
+        .iter()
+        .filter(|stored| stored.date < time || inclusive && stored.date == time)
+        .max_by_key(|stored| (stored.date, stored.version))
+        .map(|stored| stored.version)
+}
+
+/// Whether `date` is at or after `from` and before `to`, or at `to` too when
+/// its flag is set, as returned by `parse_end`.
+pub fn is_within(
+    date: DateTime<Utc>,
+    from: Option<DateTime<Utc>>,
+    to: Option<(DateTime<Utc>, bool)>,
+) -> bool {
+    from.is_none_or(|from| date >= from)
+        && to.is_none_or(|(to, inclusive)| date < to || inclusive && date == to)
+}
+
+/// Parses a point in time relative to `now`: `now`, `today`, `yesterday`,
+/// an amount like `3d` or `12h ago` (units s, m, h, d, w), a local date
+/// (`2026-09-01`, meaning its midnight), a local date and time
+/// (`2026-09-01 14:30[:00]`) or an RFC 3339 timestamp.
+pub fn parse_time(text: &str, now: DateTime<Local>) -> Option<DateTime<Utc>> {
+    let text = text.trim();
+
+    if text == "now" {
+        return Some(now.to_utc());
+    }
+
+    if let Some(day) = parse_day(text, now) {
+        return local_midnight(day);
+    }
+
+    if let Some(duration) = parse_duration(text.strip_suffix(" ago").unwrap_or(text)) {
+        return Some((now - duration).to_utc());
+    }
+
+    for format in ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"] {
+        if let Ok(time) = NaiveDateTime::parse_from_str(text, format) {
+            return Some(Local.from_local_datetime(&time).earliest()?.to_utc());
+        }
+    }
+
+    DateTime::parse_from_rfc3339(text)
+        .ok()
+        .map(|time| time.to_utc())
+}
+
+/// Parses the end of a range like `parse_time`, returning whether the end
+/// itself is included. A whole day (a date, `today` or `yesterday`) ends at
+/// the next midnight, which is excluded.
+pub fn parse_end(text: &str, now: DateTime<Local>) -> Option<(DateTime<Utc>, bool)> {
+    match parse_day(text.trim(), now) {
+        Some(day) => Some((local_midnight(day.succ_opt()?)?, false)),
+        None => Some((parse_time(text, now)?, true)),
+    }
+}
+
+fn parse_day(text: &str, now: DateTime<Local>) -> Option<NaiveDate> {
+    match text {
+        "today" => Some(now.date_naive()),
+        "yesterday" => now.date_naive().pred_opt(),
+        _ => NaiveDate::parse_from_str(text, "%Y-%m-%d").ok(),
+    }
+}
+
+fn parse_duration(text: &str) -> Option<Duration> {
+    let unit = text.chars().last()?;
+    let amount: i64 = text[..text.len() - unit.len_utf8()].trim().parse().ok()?;
+
+    match unit {
+        's' => Duration::try_seconds(amount),
+        'm' => Duration::try_minutes(amount),
+        'h' => Duration::try_hours(amount),
+        'd' => Duration::try_days(amount),
+        'w' => Duration::try_weeks(amount),
+        _ => None,
+    }
+}
+
+fn local_midnight(date: NaiveDate) -> Option<DateTime<Utc>> {
+    Some(
+        Local
+            .from_local_datetime(&date.and_hms_opt(0, 0, 0)?)
+            .earliest()?
+            .to_utc(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Duration, Local, TimeZone, Utc};
+
+    use super::{is_within, parse_end, parse_time, select_latest, VersionDate};
+
+    fn create_dates() -> Vec<VersionDate> {
+        [(1, 10), (2, 20), (3, 20), (4, 30)]
+            .into_iter()
+            .map(|(version, seconds)| VersionDate {
+                version,
+                date: Utc.timestamp_opt(seconds, 0).unwrap(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_select_latest() {
+        let dates = create_dates();
+        let time = Utc.timestamp_opt(20, 0).unwrap();
+
+        assert_eq!(select_latest(&dates, time, true), Some(3));
+        assert_eq!(select_latest(&dates, time, false), Some(1));
+        assert_eq!(select_latest(&dates, DateTime::UNIX_EPOCH, true), None);
+    }
+
+    #[test]
+    fn test_is_within() {
+        let date = Utc.timestamp_opt(20, 0).unwrap();
+
+        assert!(is_within(date, None, None));
+        assert!(is_within(date, Some(date), Some((date, true))));
+        assert!(!is_within(date, Some(date), Some((date, false))));
+        assert!(!is_within(date, Some(date + Duration::seconds(1)), None));
+    }
+
+    #[test]
+    fn test_parse_time() {
+        let now = Local.with_ymd_and_hms(2026, 9, 2, 15, 30, 0).unwrap();
+        let midnight = Local
+            .with_ymd_and_hms(2026, 9, 1, 0, 0, 0)
+            .unwrap()
+            .to_utc();
+
+        assert_eq!(parse_time("now", now), Some(now.to_utc()));
+        assert_eq!(parse_time("yesterday", now), Some(midnight));
+        assert_eq!(parse_time("2026-09-01", now), Some(midnight));
+        assert_eq!(
+            parse_time("3d ago", now),
+            Some((now - Duration::days(3)).to_utc())
+        );
+        assert_eq!(
+            parse_time("12h", now),
+            Some((now - Duration::hours(12)).to_utc())
+        );
+        assert_eq!(
+            parse_time("2026-09-01 14:30", now),
+            Some(
+                Local
+                    .with_ymd_and_hms(2026, 9, 1, 14, 30, 0)
+                    .unwrap()
+                    .to_utc()
+            )
+        );
+        assert_eq!(
+            parse_time("2026-09-01T00:00:00Z", now),
+            Some(Utc.with_ymd_and_hms(2026, 9, 1, 0, 0, 0).unwrap())
+        );
+        assert_eq!(parse_time("someday", now), None);
+    }
+
+    #[test]
+    fn test_parse_end() {
+        let now = Local.with_ymd_and_hms(2026, 9, 2, 15, 30, 0).unwrap();
+        let next_midnight = Local
+            .with_ymd_and_hms(2026, 10, 1, 0, 0, 0)
+            .unwrap()
+            .to_utc();
+        let evening = Local
+            .with_ymd_and_hms(2026, 9, 30, 23, 0, 0)
+            .unwrap()
+            .to_utc();
+
+        let end = parse_end("2026-09-30", now);
+        assert_eq!(end, Some((next_midnight, false)));
+        assert!(is_within(evening, None, end));
+        assert!(!is_within(next_midnight, None, end));
+        assert_eq!(
+            parse_end("today", now).map(|(time, _)| time),
+            parse_time("2026-09-03", now)
+        );
+        assert_eq!(parse_end("now", now), Some((now.to_utc(), true)));
+
+        let dates = [VersionDate {
+            version: 1,
+            date: evening,
+        }];
+        let (time, inclusive) = parse_end("2026-09-30", now).unwrap();
+        assert_eq!(select_latest(&dates, time, inclusive), Some(1));
+    }
+}
@@ -0,0 +1,329 @@
+use std::env;
+use std::path::{Component, Path};
+use std::str::FromStr;
+
+use mongodb::{Collection, Database};
+
+use crate::cli::Args;
+use crate::common::{self, format_size};
+use crate::errors::ServiceError;
+use crate::filter::{self, Filter};
+use crate::snapshot::{self, Directory, File, Series, Snapshot};
+
+use super::{get_snapshot, snapshot_labels, snapshot_listing};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum TreeSort {
+    #[default]
+    Name,
+    /// Largest entries first.
+    Size,
+}
+
+impl FromStr for TreeSort {
+    type Err = ServiceError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "name" => Ok(TreeSort::Name),
+            "size" => Ok(TreeSort::Size),
+            _ => Err(ServiceError::InvalidArgument),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct TreeOptions {
+    /// Levels shown below the root; `None` shows the whole tree.
+    pub depth: Option<usize>,
+    pub sort: TreeSort,
+    /// Directory to show instead of the root, relative to the snapshot path.
+    pub subtree: Option<String>,
+}
+
+/// Directory or file of a listing, with its size in bytes.
+struct Entry<'a> {
+    name: &'a str,
+    bytes: u64,
+    dir: Option<&'a Directory>,
+    link: Option<&'a str>,
+}
+
+#[cfg(not(tarpaulin_include))]
+pub async fn tree_menu(
+    series: &Series,
+    collection: &Collection<Snapshot>,
+    version: i32,
+) -> Result<(), ServiceError> {
+    let Some(mut snapshot) = get_snapshot(series, collection, version).await? else {
+        println!("Snapshot isn't found. Check the version");
+        return Ok(());
+    };
+
+    println!("Please input a filter expression (like size > 10MiB) or leave it empty:");
+    if let Some(filter) = filter::read_filter()? {
+        snapshot = filter::filter_snapshot(&snapshot, &filter);
+    }
+
+    println!("Please input the directory to show, relative to the snapshot, or leave it empty for the whole tree:");
+    let subtree = common::get_string_input();
+
+    println!("Please input the depth to show or 0 for the whole tree:");
+    let depth = common::get_input();
+
+    println!("Sort entries by: 1. Name 2. Size");
+    let sort = match common::get_input() {
+        2 => TreeSort::Size,
+        _ => TreeSort::Name,
+    };
+
+    let options = TreeOptions {
+        depth: usize::try_from(depth).ok().filter(|depth| *depth > 0),
+        sort,
+        subtree: Some(subtree).filter(|subtree| !subtree.is_empty()),
+    };
+
+    match render_snapshot(&snapshot, &options) {
+        Some(tree) => println!("{tree}"),
+        None => println!("There is no such directory in the snapshot"),
+    }
+
+    Ok(())
+}
+
+/// `show [path] [--version=V] [--host=H] [--subtree=DIR] [--depth=N]
+/// [--sort=name|size] [--filter=EXPR]`
+#[cfg(not(tarpaulin_include))]
+pub async fn show_command(args: &Args, path: &Path, db: &Database) -> Result<(), ServiceError> {
+    let series = snapshot_listing::get_series(args, path)?;
+    let collection: Collection<Snapshot> =
+        db.collection(&env::var("COLL_NAME").expect("COLL_NAME must be set"));
+
+    let mut snapshot =
+        snapshot_labels::load_version(&series, &collection, args.get("version").unwrap_or("0"))
+            .await?;
+    if let Some(expression) = args.get("filter") {
+        snapshot = filter::filter_snapshot(&snapshot, &Filter::parse(expression)?);
+    }
+
+    let options = TreeOptions {
+        depth: args
+            .get("depth")
+            .map(str::parse)
+            .transpose()
+            .or(Err(ServiceError::InvalidArgument))?,
+        sort: args.get_or("sort", TreeSort::Name)?,
+        subtree: args.get("subtree").map(String::from),
+    };
+
+    println!(
+        "{}",
+        render_snapshot(&snapshot, &options).ok_or(ServiceError::InvalidArgument)?
+    );
+
+    Ok(())
+}
+
+/// Renders the snapshot, or the subtree chosen in `options`, headed by the
+/// version and its date. Returns `None` when the subtree does not exist.
+pub fn render_snapshot(snapshot: &Snapshot, options: &TreeOptions) -> Option<String> {
+    let header = format!(
+        "version: {} {}",
+        snapshot.version,
+        snapshot.local_date().format("%Y-%m-%d %H:%M:%S %:z")
+    );
+
+    let (name, files, dirs) = match options.subtree.as_deref() {
+        Some(subtree) => {
+            let components = split_subtree(subtree);
+            if components.is_empty() {
+                (snapshot.path.clone(), &snapshot.files, &snapshot.dirs)
+            } else {
+                let dir = snapshot::find_dir(&snapshot.dirs, &components)?;
+                (
+                    Path::new(&snapshot.path)
+                        .join(components.join("/"))
+                        .display()
+                        .to_string(),
+                    &dir.files,
+                    &dir.dirs,
+                )
+            }
+        }
+        None => (snapshot.path.clone(), &snapshot.files, &snapshot.dirs),
+    };
+
+    Some(format!(
+        "{header}\n{}",
+        render_tree(&name, files, dirs, options)
+    ))
+}
+
+/// Draws the listing like `tree` does: one entry per line, nested entries
+/// indented below their directory, with sizes in binary units.
+pub fn render_tree(
+    root: &str,
+    files: &[File],
+    dirs: &[Directory],
+    options: &TreeOptions,
+) -> String {
+    let mut lines = Vec::from([format!(
+        "{root} ({})",
+        format_size(snapshot::total_bytes(files, dirs))
+    )]);
+    render_level(files, dirs, options, "", 1, &mut lines);
+
+    let (file_count, dir_count) = snapshot::count_entries(files, dirs);
+    lines.push(String::new());
+    lines.push(format!("{dir_count} directories, {file_count} files"));
+
+    lines.join("\n")
+}
+
+fn render_level(
+    files: &[File],
+    dirs: &[Directory],
+    options: &TreeOptions,
+    prefix: &str,
+    level: usize,
+    lines: &mut Vec<String>,
+) {
+    let entries = sort_entries(files, dirs, options.sort);
+
+    for (index, entry) in entries.iter().enumerate() {
+        let last = index + 1 == entries.len();
+        let branch = if last { "└── " } else { "├── " };
+
+        let mut line = format!("{prefix}{branch}{}", entry.name);
+        if entry.dir.is_some() {
+            line.push('/');
+        }
+        if let Some(link) = entry.link {
+            line.push_str(&format!(" -> {link}"));
+        }
+        line.push_str(&format!(" ({})", format_size(entry.bytes)));
+        lines.push(line);
+
+        if let Some(dir) = entry.dir {
+            if options.depth.is_none_or(|depth| level < depth) {
+                let prefix = format!("{prefix}{}", if last { "    " } else { "│   " });
+                render_level(&dir.files, &dir.dirs, options, &prefix, level + 1, lines);
+            }
+        }
+    }
+}
+
+fn sort_entries<'a>(files: &'a [File], dirs: &'a [Directory], sort: TreeSort) -> Vec<Entry<'a>> {
+    let mut entries: Vec<Entry> = dirs
+        .iter()
+        .map(|dir| Entry {
+            name: &dir.name,
+            bytes: snapshot::total_bytes(&dir.files, &dir.dirs),
+            dir: Some(dir),
+            link: None,
+        })
+        .chain(files.iter().map(|file| Entry {
+            name: &file.name,
+            bytes: file.size_kb,
+            dir: None,
+            link: file.meta.link.as_deref(),
+        }))
+        .collect();
+
+    match sort {
+        TreeSort::Name => entries.sort_by(|a, b| a.name.cmp(b.name)),
+        TreeSort::Size => entries.sort_by(|a, b| b.bytes.cmp(&a.bytes).then(a.name.cmp(b.name))),
+    }
+
+    entries
+}
+
+/// Directory names of a subtree path; `.` and surrounding slashes are
+/// ignored.
+pub fn split_subtree(subtree: &str) -> Vec<String> {
+    Path::new(subtree)
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(name.to_string_lossy().into_owned()),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::snapshot::fixtures::{self, create_dir, create_file};
+    use crate::snapshot::Snapshot;
+
+    use super::{render_snapshot, split_subtree, TreeOptions, TreeSort};
+
+    fn create_snapshot() -> Snapshot {
+        fixtures::create_snapshot(
+            3,
+            "/data",
+            Vec::from([create_file("b.txt", 100), create_file("a.txt", 2048)]),
+            Vec::from([create_dir(
+                "src",
+                Vec::from([create_file("main.rs", 1000)]),
+                Vec::from([create_dir(
+                    "deep",
+                    Vec::from([create_file("x", 1)]),
+                    Vec::new(),
+                )]),
+            )]),
+        )
+    }
+
+    #[test]
+    fn test_render_tree() {
+        let tree = render_snapshot(&create_snapshot(), &TreeOptions::default()).unwrap();
+
+        assert_eq!(
+            tree,
+            [
+                "version: 3 1970-01-01 00:00:00 +00:00",
+                "/data (3.1 KiB)",
+                "├── a.txt (2.0 KiB)",
+                "├── b.txt (100 B)",
+                "└── src/ (1001 B)",
+                "    ├── deep/ (1 B)",
+                "    │   └── x (1 B)",
+                "    └── main.rs (1000 B)",
+                "",
+                "2 directories, 4 files",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn test_render_with_depth_and_size_sort() {
+        let options = TreeOptions {
+            depth: Some(1),
+            sort: TreeSort::Size,
+            subtree: None,
+        };
+        let tree = render_snapshot(&create_snapshot(), &options).unwrap();
+        let lines: Vec<&str> = tree.lines().collect();
+
+        assert_eq!(lines[2], "├── a.txt (2.0 KiB)");
+        assert_eq!(lines[3], "├── src/ (1001 B)");
+        assert_eq!(lines[4], "└── b.txt (100 B)");
+        assert_eq!(lines[5], "");
+    }
+
+    #[test]
+    fn test_render_subtree() {
+        let mut options = TreeOptions {
+            subtree: Some(String::from("./src/deep/")),
+            ..TreeOptions::default()
+        };
+        let tree = render_snapshot(&create_snapshot(), &options).unwrap();
+
+        assert!(tree.contains("/data/src/deep (1 B)\n└── x (1 B)"));
+        assert_eq!(split_subtree("/src//deep"), Vec::from(["src", "deep"]));
+
+        options.subtree = Some(String::from("missing"));
+        assert_eq!(render_snapshot(&create_snapshot(), &options), None);
+    }
+}
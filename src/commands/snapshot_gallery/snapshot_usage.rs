@@ -0,0 +1,330 @@
+use std::env;
+use std::path::Path;
+
+use mongodb::{Collection, Database};
+use serde::Serialize;
+
+use crate::cli::Args;
+use crate::common::{self, format_size};
+use crate::errors::ServiceError;
+use crate::snapshot::{self, Directory, File, Series, Snapshot};
+
+use super::{get_snapshot, snapshot_labels, snapshot_listing};
+
+const DEFAULT_TOP: usize = 10;
+const BAR_WIDTH: usize = 10;
+
+/// Directory or file with its share of the directory holding it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UsageEntry {
+    pub path: String,
+    pub is_dir: bool,
+    pub bytes: u64,
+    pub percent_of_parent: f64,
+    pub depth: usize,
+}
+
+/// Bytes held by the files at one depth; files in the root are at depth 1.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DepthUsage {
+    pub depth: usize,
+    pub bytes: u64,
+    pub files: u64,
+    pub dirs: u64,
+}
+
+/// Where the space of a snapshot goes. Sizes are in bytes.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UsageReport {
+    pub version: i32,
+    pub path: String,
+    pub total_bytes: u64,
+    pub largest_dirs: Vec<UsageEntry>,
+    pub largest_files: Vec<UsageEntry>,
+    pub by_depth: Vec<DepthUsage>,
+    /// Entries of the root directory, largest first.
+    pub top_level: Vec<UsageEntry>,
+}
+
+#[cfg(not(tarpaulin_include))]
+pub async fn usage_menu(
+    series: &Series,
+    collection: &Collection<Snapshot>,
+) -> Result<(), ServiceError> {
+    println!("Please, input the number or label of version or 0 to choose the last one:");
+    let version = snapshot_labels::read_version(series, collection).await?;
+
+    let Some(snapshot) = get_snapshot(series, collection, version).await? else {
+        println!("Snapshot isn't found. Check the version");
+        return Ok(());
+    };
+
+    println!("Please input how many of the largest entries to show or 0 for {DEFAULT_TOP}:");
+    let top = usize::try_from(common::get_input())
+        .ok()
+        .filter(|top| *top > 0)
+        .unwrap_or(DEFAULT_TOP);
+
+    println!("{}", render_report(&usage_report(&snapshot, top)));
+
+    Ok(())
+}
+
+/// `usage [path] [--version=V] [--host=H] [--top=N] [--json]`
+#[cfg(not(tarpaulin_include))]
+pub async fn usage_command(args: &Args, path: &Path, db: &Database) -> Result<(), ServiceError> {
+    let series = snapshot_listing::get_series(args, path)?;
+    let collection: Collection<Snapshot> =
+        db.collection(&env::var("COLL_NAME").expect("COLL_NAME must be set"));
+
+    let snapshot =
+        snapshot_labels::load_version(&series, &collection, args.get("version").unwrap_or("0"))
+            .await?;
+
+    let report = usage_report(&snapshot, args.get_or("top", DEFAULT_TOP)?);
+
+    if args.has_flag("json") {
+        let json = serde_json::to_string_pretty(&report).or(Err(ServiceError::FailedToExport))?;
+        println!("{json}");
+    } else {
+        println!("{}", render_report(&report));
+    }
+
+    Ok(())
+}
+
+/// Computes the report from the stored tree alone, keeping the `top`
+/// largest directories and files.
+pub fn usage_report(snapshot: &Snapshot, top: usize) -> UsageReport {
+    let total_bytes = snapshot::total_bytes(&snapshot.files, &snapshot.dirs);
+
+    let mut dirs = Vec::new();
+    let mut files = Vec::new();
+    let mut by_depth = Vec::new();
+    collect(
+        &snapshot.files,
+        &snapshot.dirs,
+        total_bytes,
+        "",
+        1,
+        &mut dirs,
+        &mut files,
+        &mut by_depth,
+    );
+
+    let mut top_level: Vec<UsageEntry> = dirs
+        .iter()
+        .chain(&files)
+        .filter(|entry| entry.depth == 1)
+        .cloned()
+        .collect();
+    for entries in [&mut dirs, &mut files, &mut top_level] {
+        entries.sort_by(|a, b| b.bytes.cmp(&a.bytes).then(a.path.cmp(&b.path)));
+    }
+    dirs.truncate(top);
+    files.truncate(top);
+
+    UsageReport {
+        version: snapshot.version,
+        path: snapshot.path.clone(),
+        total_bytes,
+        largest_dirs: dirs,
+        largest_files: files,
+        by_depth,
+        top_level,
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn collect(
+    files: &[File],
+    dirs: &[Directory],
+    parent_bytes: u64,
+    prefix: &str,
+    depth: usize,
+    dir_entries: &mut Vec<UsageEntry>,
+    file_entries: &mut Vec<UsageEntry>,
+    by_depth: &mut Vec<DepthUsage>,
+) {
+    if by_depth.len() < depth {
+        by_depth.push(DepthUsage {
+            depth,
+            bytes: 0,
+            files: 0,
+            dirs: 0,
+        });
+    }
+    let level = &mut by_depth[depth - 1];
+    level.files += files.len() as u64;
+    level.dirs += dirs.len() as u64;
+
+    for file in files {
+        by_depth[depth - 1].bytes += file.size_kb;
+        file_entries.push(UsageEntry {
+            path: format!("{prefix}{}", file.name),
+            is_dir: false,
+            bytes: file.size_kb,
+            percent_of_parent: percent(file.size_kb, parent_bytes),
+            depth,
+        });
+    }
+
+    for dir in dirs {
+        let bytes = snapshot::total_bytes(&dir.files, &dir.dirs);
+        let path = format!("{prefix}{}", dir.name);
+        collect(
+            &dir.files,
+            &dir.dirs,
+            bytes,
+            &format!("{path}/"),
+            depth + 1,
+            dir_entries,
+            file_entries,
+            by_depth,
+        );
+        dir_entries.push(UsageEntry {
+            path,
+            is_dir: true,
+            bytes,
+            percent_of_parent: percent(bytes, parent_bytes),
+            depth,
+        });
+    }
+}
+
+pub fn percent(bytes: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        bytes as f64 * 100.0 / total as f64
+    }
+}
+
+/// Bar of `BAR_WIDTH` cells filled in proportion to `percent`.
+pub fn usage_bar(percent: f64) -> String {
+    let filled = ((percent / 100.0 * BAR_WIDTH as f64).round() as usize).min(BAR_WIDTH);
+    format!("[{}{}]", "#".repeat(filled), " ".repeat(BAR_WIDTH - filled))
+}
+
+pub fn render_report(report: &UsageReport) -> String {
+    let mut lines = Vec::from([format!(
+        "Disk usage of version {} of {}: {}",
+        report.version,
+        report.path,
+        format_size(report.total_bytes)
+    )]);
+
+    let sections = [
+        ("Top level", &report.top_level),
+        ("Largest directories", &report.largest_dirs),
+        ("Largest files", &report.largest_files),
+    ];
+    for (title, entries) in sections {
+        lines.push(String::new());
+        lines.push(format!("{title}:"));
+        for entry in entries {
+            let slash = if entry.is_dir { "/" } else { "" };
+            lines.push(format!(
+                "{:>10} {:>6.1}% {} {}{slash}",
+                format_size(entry.bytes),
+                entry.percent_of_parent,
+                usage_bar(entry.percent_of_parent),
+                entry.path
+            ));
+        }
+    }
+
+    lines.push(String::new());
+    lines.push(String::from("Size by depth:"));
+    for level in &report.by_depth {
+        lines.push(format!(
+            "{:>5} {:>10} {:>6.1}% {} files, {} directories",
+            level.depth,
+            format_size(level.bytes),
+            percent(level.bytes, report.total_bytes),
+            level.files,
+            level.dirs
+        ));
+    }
+
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::snapshot::fixtures::{self, create_dir, create_file};
+    use crate::snapshot::Snapshot;
+
+    use super::{render_report, usage_bar, usage_report};
+
+    fn create_snapshot() -> Snapshot {
+        fixtures::create_snapshot(
+            4,
+            "/data",
+            Vec::from([create_file("readme", 200)]),
+            Vec::from([
+                create_dir(
+                    "media",
+                    Vec::from([create_file("a.mp4", 500), create_file("b.mp4", 100)]),
+                    Vec::from([create_dir(
+                        "raw",
+                        Vec::from([create_file("c.raw", 150)]),
+                        Vec::new(),
+                    )]),
+                ),
+                create_dir("docs", Vec::from([create_file("x.txt", 50)]), Vec::new()),
+            ]),
+        )
+    }
+
+    #[test]
+    fn test_usage_report() {
+        let report = usage_report(&create_snapshot(), 2);
+
+        assert_eq!(report.total_bytes, 1000);
+        let dirs: Vec<(&str, u64, f64)> = report
+            .largest_dirs
+            .iter()
+            .map(|entry| (entry.path.as_str(), entry.bytes, entry.percent_of_parent))
+            .collect();
+        assert_eq!(
+            dirs,
+            Vec::from([("media", 750, 75.0), ("media/raw", 150, 20.0)])
+        );
+
+        let files: Vec<&str> = report
+            .largest_files
+            .iter()
+            .map(|entry| entry.path.as_str())
+            .collect();
+        assert_eq!(files, Vec::from(["media/a.mp4", "readme"]));
+
+        let depths: Vec<(usize, u64, u64, u64)> = report
+            .by_depth
+            .iter()
+            .map(|level| (level.depth, level.bytes, level.files, level.dirs))
+            .collect();
+        assert_eq!(
+            depths,
+            Vec::from([(1, 200, 1, 2), (2, 650, 3, 1), (3, 150, 1, 0)])
+        );
+
+        let top_level: Vec<&str> = report
+            .top_level
+            .iter()
+            .map(|entry| entry.path.as_str())
+            .collect();
+        assert_eq!(top_level, Vec::from(["media", "readme", "docs"]));
+    }
+
+    #[test]
+    fn test_render_report() {
+        let report = render_report(&usage_report(&create_snapshot(), 1));
+
+        assert!(report.starts_with("Disk usage of version 4 of /data: 1000 B"));
+        assert!(report.contains("     750 B   75.0% [########  ] media/"));
+        assert!(report.contains("    2      650 B   65.0% 3 files, 1 directories"));
+        assert_eq!(usage_bar(0.0), "[          ]");
+        assert_eq!(usage_bar(100.0), "[##########]");
+    }
+}
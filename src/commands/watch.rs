@@ -0,0 +1,167 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use mongodb::Database;
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+
+use crate::cli::Args;
+use crate::commands::snapshot_creation::{self, SnapshotOptions, UnchangedMode};
+use crate::commands::snapshot_gallery::snapshot_labels;
+use crate::common::log;
+use crate::errors::ServiceError;
+
+const DEFAULT_DEBOUNCE_SECS: u64 = 5;
+const DEFAULT_MIN_INTERVAL_SECS: u64 = 60;
+
+/// Watches `path` (or the first positional argument) and takes a snapshot
+/// once changes have settled for `--debounce` seconds, at most once every
+/// `--min-interval` seconds. Identical trees are skipped unless
+/// `--unchanged=store` or `--unchanged=marker` is given. Every version gets
+/// the comma-separated `--tags`.
+#[cfg(not(tarpaulin_include))]
+pub async fn watch(args: &Args, path: &Path, db: &Database) -> Result<(), ServiceError> {
+    let path = match args.positional.first() {
+        Some(path) => PathBuf::from(path),
+        None => path.to_path_buf(),
+    };
+    let path = fs::canonicalize(path).or(Err(ServiceError::InvalidArgument))?;
+    let debounce = Duration::from_secs(args.get_or("debounce", DEFAULT_DEBOUNCE_SECS)?);
+    let min_interval = Duration::from_secs(args.get_or("min-interval", DEFAULT_MIN_INTERVAL_SECS)?);
+    let options = SnapshotOptions {
+        unchanged: args.get_or("unchanged", UnchangedMode::Skip)?,
+        tags: snapshot_labels::parse_tags(args.get("tags").unwrap_or_default()),
+        ..SnapshotOptions::default()
+    };
+
+    let (tx, rx) = std::sync::mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx).or(Err(ServiceError::FailedToWatch))?;
+    watcher
+        .watch(&path, RecursiveMode::Recursive)
+        .or(Err(ServiceError::FailedToWatch))?;
+
+    log(&format!("Watching {}", path.display()));
+
+    take_snapshot(&path, db, &options).await?;
+    let mut last_snapshot = Instant::now();
+
+    loop {
+        if !wait_for_change(&rx) || !wait_until_quiet(&rx, debounce) {
+            return Err(ServiceError::FailedToWatch);
+        }
+
+        let elapsed = last_snapshot.elapsed();
+        if elapsed < min_interval {
+            thread::sleep(min_interval - elapsed);
+            while rx.try_recv().is_ok() {}
+        }
+
+        take_snapshot(&path, db, &options).await?;
+        last_snapshot = Instant::now();
+    }
+}
+
+#[cfg(not(tarpaulin_include))]
+async fn take_snapshot(
+    path: &Path,
+    db: &Database,
+    options: &SnapshotOptions,
+) -> Result<(), ServiceError> {
+    match snapshot_creation::store_snapshot(path, db, options).await? {
+        Some(version) => log(&format!(
+            "Snapshot version {version} of {} created",
+            path.display()
+        )),
+        None => log(&format!(
+            "No changes in {}, snapshot skipped",
+            path.display()
+        )),
+    }
+
+    Ok(())
+}
+
+/// Blocks until a relevant event arrives. Returns `false` once the watcher
+/// has gone away.
+fn wait_for_change(rx: &Receiver<notify::Result<Event>>) -> bool {
+    loop {
+        match rx.recv() {
+            Ok(Ok(event)) if is_relevant(&event) => return true,
+            Ok(_) => continue,
+            Err(_) => return false,
+        }
+    }
+}
+
+/// Waits until no relevant event has arrived for `debounce`.
+fn wait_until_quiet(rx: &Receiver<notify::Result<Event>>, debounce: Duration) -> bool {
+    let mut deadline = Instant::now() + debounce;
+
+    loop {
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        match rx.recv_timeout(timeout) {
+            Ok(Ok(event)) if is_relevant(&event) => deadline = Instant::now() + debounce,
+            Ok(_) => continue,
+            Err(RecvTimeoutError::Timeout) => return true,
+            Err(RecvTimeoutError::Disconnected) => return false,
+        }
+    }
+}
+
+fn is_relevant(event: &Event) -> bool {
+    !matches!(event.kind, EventKind::Access(_))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    use notify::event::{AccessKind, CreateKind};
+    use notify::{Event, EventKind};
+
+    use super::{is_relevant, wait_for_change, wait_until_quiet};
+
+    #[test]
+    fn test_is_relevant() {
+        assert!(is_relevant(&Event::new(EventKind::Create(
+            CreateKind::File
+        ))));
+        assert!(!is_relevant(&Event::new(EventKind::Access(
+            AccessKind::Any
+        ))));
+    }
+
+    #[test]
+    fn test_wait_for_change_skips_access_events() {
+        let (tx, rx) = mpsc::channel();
+        tx.send(Ok(Event::new(EventKind::Access(AccessKind::Any))))
+            .unwrap();
+        tx.send(Ok(Event::new(EventKind::Create(CreateKind::File))))
+            .unwrap();
+        assert!(wait_for_change(&rx));
+
+        drop(tx);
+        assert!(!wait_for_change(&rx));
+    }
+
+    #[test]
+    fn test_wait_until_quiet() {
+        let (tx, rx) = mpsc::channel();
+        let sender = thread::spawn(move || {
+            for _ in 0..3 {
+                tx.send(Ok(Event::new(EventKind::Create(CreateKind::File))))
+                    .unwrap();
+                thread::sleep(Duration::from_millis(10));
+            }
+            tx
+        });
+
+        assert!(wait_until_quiet(&rx, Duration::from_millis(100)));
+        drop(sender.join().unwrap());
+        assert!(!wait_until_quiet(&rx, Duration::from_millis(100)));
+    }
+}
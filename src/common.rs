@@ -1,6 +1,15 @@
-use std::fs::{File, OpenOptions};
-use std::io::{self, Write};
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, IsTerminal, Write};
+use std::os::unix::fs::MetadataExt;
 use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use chrono::Local;
+use ratatui::crossterm::terminal;
+use sha2::{Digest, Sha256};
+
+use crate::snapshot::Metadata;
 
 pub fn get_input() -> i32 {
     loop {
@@ -18,6 +27,14 @@ pub fn get_input() -> i32 {
     }
 }
 
+pub fn get_string_input() -> String {
+    let mut input = String::new();
+
+    io::stdin().read_line(&mut input).expect("Input Error");
+
+    String::from(input.trim())
+}
+
 pub fn get_initial_path(str: &str) -> PathBuf {
     let mut path = PathBuf::from(str);
     path.pop();
@@ -31,6 +48,199 @@ pub fn get_size(path: &Path) -> u64 {
     size
 }
 
+/// Reads the attributes of `path` without following symlinks. The content
+/// digest is only computed when `SNAPSHOT_DIGESTS` is set to `1`.
+pub fn get_metadata(path: &Path) -> Metadata {
+    let mut meta = Metadata::default();
+
+    let Ok(metadata) = fs::symlink_metadata(path) else {
+        return meta;
+    };
+
+    meta.mode = Some(metadata.mode() & 0o7777);
+    meta.uid = Some(metadata.uid());
+    meta.gid = Some(metadata.gid());
+    meta.mtime = Some(metadata.mtime());
+
+    if metadata.file_type().is_symlink() {
+        meta.link = fs::read_link(path)
+            .ok()
+            .map(|target| target.to_string_lossy().into_owned());
+    } else if metadata.is_file() && digests_enabled() {
+        meta.sha256 = get_sha256(path).ok();
+    }
+
+    meta
+}
+
+pub fn digests_enabled() -> bool {
+    env::var("SNAPSHOT_DIGESTS").is_ok_and(|value| value == "1")
+}
+
+pub fn get_sha256(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+
+    io::copy(&mut file, &mut hasher)?;
+
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// Name of this machine. `SNAPSHOT_HOST` overrides it, e.g. for containers
+/// whose hostname changes on every start.
+pub fn get_hostname() -> String {
+    let overridden = env::var("SNAPSHOT_HOST").ok();
+    let from_files = ["/proc/sys/kernel/hostname", "/etc/hostname"]
+        .into_iter()
+        .filter_map(|file| fs::read_to_string(file).ok());
+
+    overridden
+        .into_iter()
+        .chain(from_files)
+        .chain(env::var("HOSTNAME").ok())
+        .map(|name| String::from(name.trim()))
+        .find(|name| !name.is_empty())
+        .unwrap_or_else(|| String::from("localhost"))
+}
+
+pub fn get_machine_id() -> Option<String> {
+    ["/etc/machine-id", "/var/lib/dbus/machine-id"]
+        .into_iter()
+        .filter_map(|file| fs::read_to_string(file).ok())
+        .map(|id| String::from(id.trim()))
+        .find(|id| !id.is_empty())
+}
+
+pub fn get_username() -> Option<String> {
+    ["USER", "LOGNAME", "USERNAME"]
+        .into_iter()
+        .filter_map(|name| env::var(name).ok())
+        .find(|name| !name.is_empty())
+}
+
+/// Aligns the cells of `rows` in columns separated by two spaces.
+pub fn render_columns(rows: &[Vec<String>]) -> String {
+    let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+    let widths: Vec<usize> = (0..columns)
+        .map(|column| {
+            rows.iter()
+                .filter_map(|row| row.get(column))
+                .map(|cell| cell.chars().count())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+
+    rows.iter()
+        .map(|row| {
+            let cells: Vec<String> = row
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{cell:<width$}"))
+                .collect();
+            String::from(cells.join("  ").trim_end())
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Human-readable size with binary units, e.g. `512 B` or `1.5 MiB`.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["KiB", "MiB", "GiB", "TiB", "PiB"];
+
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    format!("{size:.1} {}", UNITS[unit])
+}
+
+/// Signed size change like `+1.5 KiB` or `-200 B`.
+pub fn format_delta(delta: i64) -> String {
+    let sign = if delta < 0 { "-" } else { "+" };
+    format!("{sign}{}", format_size(delta.unsigned_abs()))
+}
+
+/// Parses a size like `512`, `10K`, `1.5MiB` or `2GB`. Bare letters and
+/// `iB` suffixes are binary units, `B` suffixes after a letter decimal ones.
+pub fn parse_size(text: &str) -> Option<u64> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number: f64 = number.parse().ok()?;
+
+    let multiplier: u64 = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KIB" => 1 << 10,
+        "M" | "MIB" => 1 << 20,
+        "G" | "GIB" => 1 << 30,
+        "T" | "TIB" => 1 << 40,
+        "KB" => 1_000,
+        "MB" => 1_000_000,
+        "GB" => 1_000_000_000,
+        "TB" => 1_000_000_000_000,
+        _ => return None,
+    };
+
+    Some((number * multiplier as f64).round() as u64)
+}
+
+/// Whether output may use ANSI colors: only on a terminal, unless
+/// `NO_COLOR` is set or the user opted out.
+pub fn use_color(no_color: bool) -> bool {
+    !no_color && env::var_os("NO_COLOR").is_none() && io::stdout().is_terminal()
+}
+
+/// Prints `text`, through `$PAGER` (`less -R` by default) when it does not
+/// fit on the terminal.
+#[cfg(not(tarpaulin_include))]
+pub fn print_paged(text: &str) {
+    let fits = match terminal::size() {
+        Ok((_, rows)) => text.lines().count() < usize::from(rows),
+        Err(_) => true,
+    };
+    if fits || !io::stdout().is_terminal() {
+        println!("{text}");
+        return;
+    }
+
+    let pager = env::var("PAGER").unwrap_or(String::from("less -R"));
+    let mut words = pager.split_whitespace();
+    let Some(program) = words.next() else {
+        println!("{text}");
+        return;
+    };
+
+    match Command::new(program)
+        .args(words)
+        .stdin(Stdio::piped())
+        .spawn()
+    {
+        Ok(mut child) => {
+            if let Some(mut stdin) = child.stdin.take() {
+                // The pager closing early is not an error worth reporting.
+                let _ = writeln!(stdin, "{text}");
+            }
+            let _ = child.wait();
+        }
+        Err(_) => println!("{text}"),
+    }
+}
+
+#[cfg(not(tarpaulin_include))]
+pub fn log(message: &str) {
+    println!("[{}] {message}", Local::now().format("%Y-%m-%d %H:%M:%S"));
+}
+
 pub fn append_to_file(file_path: &str, data: &str) -> io::Result<()> {
     let mut file = OpenOptions::new()
         .create(true)
@@ -49,7 +259,7 @@ mod tests {
 
     use crate::common::get_size;
 
-    use super::get_initial_path;
+    use super::{format_delta, format_size, get_initial_path, parse_size, render_columns};
 
     #[test]
     fn test_get_initial_path() {
@@ -70,4 +280,37 @@ mod tests {
         let result = get_size(&path);
         assert_eq!(result, 55);
     }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(1536), "1.5 KiB");
+        assert_eq!(format_size(5 * 1024 * 1024 * 1024), "5.0 GiB");
+        assert_eq!(format_delta(1536), "+1.5 KiB");
+        assert_eq!(format_delta(-200), "-200 B");
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("512"), Some(512));
+        assert_eq!(parse_size("10MiB"), Some(10 * 1024 * 1024));
+        assert_eq!(parse_size("1.5k"), Some(1536));
+        assert_eq!(parse_size("2 GB"), Some(2_000_000_000));
+        assert_eq!(parse_size("lots"), None);
+    }
+
+    #[test]
+    fn test_render_columns() {
+        let rows = [
+            ["NAME", "SIZE", ""],
+            ["a.txt", "10", "new"],
+            ["logs", "2000", ""],
+        ]
+        .map(|row| row.map(String::from).to_vec());
+
+        assert_eq!(
+            render_columns(&rows),
+            "NAME   SIZE\na.txt  10    new\nlogs   2000"
+        );
+    }
 }
@@ -7,6 +7,16 @@ pub enum ServiceError {
     FailedToParseCommand,
     WrongNumberOfArgs,
     InvalidArgument,
+    FailedToExport,
+    FailedToImport,
+    FailedToReadArchive,
+    FailedToWatch,
+    FailedToReadConfig,
+    FailedToStoreSnapshot,
+    LabelAlreadyUsed,
+    FailedToUseTerminal,
+    InvalidFilter,
+    DirectoryTooLarge,
 }
 
 #[cfg(not(tarpaulin_include))]
@@ -18,6 +28,19 @@ impl fmt::Display for ServiceError {
             ServiceError::FailedToParseCommand => write!(f, "Failed to parse command"),
             ServiceError::WrongNumberOfArgs => write!(f, "Wrong number of arguments"),
             ServiceError::InvalidArgument => write!(f, "Invalid argument"),
+            ServiceError::FailedToExport => write!(f, "Failed to export snapshot"),
+            ServiceError::FailedToImport => write!(f, "Failed to import snapshot"),
+            ServiceError::FailedToReadArchive => write!(f, "Failed to read archive"),
+            ServiceError::FailedToWatch => write!(f, "Failed to watch for changes"),
+            ServiceError::FailedToReadConfig => write!(f, "Failed to read configuration"),
+            ServiceError::FailedToStoreSnapshot => write!(f, "Failed to store snapshot"),
+            ServiceError::LabelAlreadyUsed => write!(f, "Label is already used by another version"),
+            ServiceError::FailedToUseTerminal => write!(f, "Failed to use the terminal"),
+            ServiceError::InvalidFilter => write!(f, "Invalid filter expression"),
+            ServiceError::DirectoryTooLarge => write!(
+                f,
+                "Directory has too many subdirectories to be stored as one node"
+            ),
         }
     }
 }
@@ -0,0 +1,598 @@
+use std::cmp::Ordering;
+use std::path::Path;
+
+use chrono::{DateTime, Local, NaiveDate, TimeZone, Utc};
+use glob::Pattern;
+use regex::Regex;
+
+use crate::common;
+use crate::errors::ServiceError;
+use crate::snapshot::{self, Directory, File, Metadata, Snapshot};
+
+/// Entry of a snapshot tree as seen by a filter. `path` is relative to the
+/// snapshot root and entries of the root are at depth 1.
+#[derive(Debug, Clone, Copy)]
+pub struct Entry<'a> {
+    pub name: &'a str,
+    pub path: &'a str,
+    pub is_dir: bool,
+    pub bytes: u64,
+    pub depth: usize,
+    pub meta: &'a Metadata,
+}
+
+/// Compiled filter expression such as
+/// `ext = "log" and size > 10MiB and depth <= 3` or `mtime < 30d`.
+///
+/// Comparisons are `FIELD OP VALUE` joined with `and`, `or`, `not` and
+/// parentheses. Fields:
+/// - `name`, `path`, `ext`, `kind` (`file`, `dir` or `link`): compared with
+///   `=`, `!=`, `~` (glob) or `=~` (regular expression);
+/// - `size` (with units like `10MiB`), `depth`, `uid`, `gid`, `mode`
+///   (octal): compared with `=`, `!=`, `<`, `<=`, `>`, `>=`;
+/// - `mtime`: against an age like `30d` (`mtime < 30d` means modified less
+///   than 30 days ago) or a date like `2026-01-01`.
+///
+/// Values may be quoted with double quotes.
+#[derive(Debug, Clone)]
+pub struct Filter {
+    expr: Expr,
+    /// Reference time of ages, in seconds since the epoch.
+    now: i64,
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Text(TextField, TextTest),
+    Number(NumberField, Op, u64),
+    Age(Op, i64),
+    Time(Op, i64),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum TextField {
+    Name,
+    Path,
+    Ext,
+    Kind,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum NumberField {
+    Size,
+    Depth,
+    Uid,
+    Gid,
+    Mode,
+}
+
+#[derive(Debug, Clone)]
+enum TextTest {
+    Equal(String),
+    NotEqual(String),
+    Glob(Pattern),
+    Regex(Regex),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    Glob,
+    Regex,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Quoted(String),
+    Op(Op),
+    Open,
+    Close,
+}
+
+impl Filter {
+    pub fn parse(text: &str) -> Result<Filter, ServiceError> {
+        Filter::parse_at(text, Utc::now().timestamp())
+    }
+
+    /// Same as `parse` with ages counted back from `now`.
+    pub fn parse_at(text: &str, now: i64) -> Result<Filter, ServiceError> {
+        let tokens = tokenize(text)?;
+        let mut parser = Parser {
+            tokens: &tokens,
+            position: 0,
+        };
+
+        let expr = parser.parse_or()?;
+        if parser.position != tokens.len() {
+            return Err(ServiceError::InvalidFilter);
+        }
+
+        Ok(Filter { expr, now })
+    }
+
+    pub fn matches(&self, entry: &Entry) -> bool {
+        self.expr.matches(entry, self.now)
+    }
+}
+
+impl Expr {
+    fn matches(&self, entry: &Entry, now: i64) -> bool {
+        match self {
+            Expr::And(left, right) => left.matches(entry, now) && right.matches(entry, now),
+            Expr::Or(left, right) => left.matches(entry, now) || right.matches(entry, now),
+            Expr::Not(inner) => !inner.matches(entry, now),
+            Expr::Text(field, test) => {
+                let value = match field {
+                    TextField::Name => entry.name,
+                    TextField::Path => entry.path,
+                    TextField::Ext => Path::new(entry.name)
+                        .extension()
+                        .and_then(|ext| ext.to_str())
+                        .unwrap_or_default(),
+                    TextField::Kind if entry.is_dir => "dir",
+                    TextField::Kind if entry.meta.link.is_some() => "link",
+                    TextField::Kind => "file",
+                };
+                match test {
+                    TextTest::Equal(expected) => value == expected,
+                    TextTest::NotEqual(expected) => value != expected,
+                    TextTest::Glob(pattern) => pattern.matches(value),
+                    TextTest::Regex(regex) => regex.is_match(value),
+                }
+            }
+            Expr::Number(field, op, expected) => {
+                let value = match field {
+                    NumberField::Size => Some(entry.bytes),
+                    NumberField::Depth => Some(entry.depth as u64),
+                    NumberField::Uid => entry.meta.uid.map(u64::from),
+                    NumberField::Gid => entry.meta.gid.map(u64::from),
+                    NumberField::Mode => entry.meta.mode.map(|mode| u64::from(mode & 0o7777)),
+                };
+                value.is_some_and(|value| op.holds(value.cmp(expected)))
+            }
+            Expr::Age(op, seconds) => entry
+                .meta
+                .mtime
+                .is_some_and(|mtime| op.holds((now - mtime).cmp(seconds))),
+            Expr::Time(op, time) => entry
+                .meta
+                .mtime
+                .is_some_and(|mtime| op.holds(mtime.cmp(time))),
+        }
+    }
+}
+
+impl Op {
+    fn holds(self, ordering: Ordering) -> bool {
+        match self {
+            Op::Equal => ordering == Ordering::Equal,
+            Op::NotEqual => ordering != Ordering::Equal,
+            Op::Less => ordering == Ordering::Less,
+            Op::LessOrEqual => ordering != Ordering::Greater,
+            Op::Greater => ordering == Ordering::Greater,
+            Op::GreaterOrEqual => ordering != Ordering::Less,
+            Op::Glob | Op::Regex => false,
+        }
+    }
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, ServiceError> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token::Open);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::Close);
+            }
+            '"' => {
+                chars.next();
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => value.push(chars.next().ok_or(ServiceError::InvalidFilter)?),
+                        Some(c) => value.push(c),
+                        None => return Err(ServiceError::InvalidFilter),
+                    }
+                }
+                tokens.push(Token::Quoted(value));
+            }
+            '=' | '!' | '<' | '>' | '~' => {
+                let mut op = String::new();
+                while let Some(&c) = chars.peek() {
+                    if !"=!<>~".contains(c) {
+                        break;
+                    }
+                    op.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Op(match op.as_str() {
+                    "=" | "==" => Op::Equal,
+                    "!=" => Op::NotEqual,
+                    "<" => Op::Less,
+                    "<=" => Op::LessOrEqual,
+                    ">" => Op::Greater,
+                    ">=" => Op::GreaterOrEqual,
+                    "~" => Op::Glob,
+                    "=~" => Op::Regex,
+                    _ => return Err(ServiceError::InvalidFilter),
+                }));
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || "()\"=!<>~".contains(c) {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Word(word));
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn next(&mut self) -> Option<&Token> {
+        let token = self.tokens.get(self.position);
+        self.position += 1;
+        token
+    }
+
+    fn keyword(&mut self, keyword: &str) -> bool {
+        match self.tokens.get(self.position) {
+            Some(Token::Word(word)) if word.eq_ignore_ascii_case(keyword) => {
+                self.position += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, ServiceError> {
+        let mut expr = self.parse_and()?;
+        while self.keyword("or") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.parse_and()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, ServiceError> {
+        let mut expr = self.parse_unary()?;
+        while self.keyword("and") {
+            expr = Expr::And(Box::new(expr), Box::new(self.parse_unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, ServiceError> {
+        if self.keyword("not") {
+            return Ok(Expr::Not(Box::new(self.parse_unary()?)));
+        }
+
+        if self.tokens.get(self.position) == Some(&Token::Open) {
+            self.position += 1;
+            let expr = self.parse_or()?;
+            return match self.next() {
+                Some(Token::Close) => Ok(expr),
+                _ => Err(ServiceError::InvalidFilter),
+            };
+        }
+
+        self.parse_comparison()
+    }
+
+    fn parse_comparison(&mut self) -> Result<Expr, ServiceError> {
+        let field = match self.next() {
+            Some(Token::Word(field)) => field.to_ascii_lowercase(),
+            _ => return Err(ServiceError::InvalidFilter),
+        };
+        let op = match self.next() {
+            Some(Token::Op(op)) => *op,
+            _ => return Err(ServiceError::InvalidFilter),
+        };
+        let value = match self.next() {
+            Some(Token::Word(value) | Token::Quoted(value)) => value.clone(),
+            _ => return Err(ServiceError::InvalidFilter),
+        };
+
+        let text_field = match field.as_str() {
+            "name" => Some(TextField::Name),
+            "path" => Some(TextField::Path),
+            "ext" => Some(TextField::Ext),
+            "kind" | "type" => Some(TextField::Kind),
+            _ => None,
+        };
+        if let Some(text_field) = text_field {
+            let test = match op {
+                Op::Equal => TextTest::Equal(value),
+                Op::NotEqual => TextTest::NotEqual(value),
+                Op::Glob => {
+                    TextTest::Glob(Pattern::new(&value).or(Err(ServiceError::InvalidFilter))?)
+                }
+                Op::Regex => {
+                    TextTest::Regex(Regex::new(&value).or(Err(ServiceError::InvalidFilter))?)
+                }
+                _ => return Err(ServiceError::InvalidFilter),
+            };
+            return Ok(Expr::Text(text_field, test));
+        }
+
+        if matches!(op, Op::Glob | Op::Regex) {
+            return Err(ServiceError::InvalidFilter);
+        }
+
+        let number = |field: NumberField, value: Option<u64>| {
+            value
+                .map(|value| Expr::Number(field, op, value))
+                .ok_or(ServiceError::InvalidFilter)
+        };
+        match field.as_str() {
+            "size" => number(NumberField::Size, common::parse_size(&value)),
+            "depth" => number(NumberField::Depth, value.parse().ok()),
+            "uid" => number(NumberField::Uid, value.parse().ok()),
+            "gid" => number(NumberField::Gid, value.parse().ok()),
+            "mode" => number(NumberField::Mode, u64::from_str_radix(&value, 8).ok()),
+            "mtime" => match parse_age(&value) {
+                Some(seconds) => Ok(Expr::Age(op, seconds)),
+                None => parse_date(&value)
+                    .map(|time| Expr::Time(op, time))
+                    .ok_or(ServiceError::InvalidFilter),
+            },
+            _ => Err(ServiceError::InvalidFilter),
+        }
+    }
+}
+
+/// Filter expression typed in, if any.
+#[cfg(not(tarpaulin_include))]
+pub fn read_filter() -> Result<Option<Filter>, ServiceError> {
+    let input = common::get_string_input();
+
+    if input.is_empty() {
+        return Ok(None);
+    }
+
+    Filter::parse(&input).map(Some)
+}
+
+/// Seconds in an age like `90s`, `15m`, `12h`, `30d` or `2w`.
+fn parse_age(text: &str) -> Option<i64> {
+    let unit = text.chars().last()?;
+    let amount: i64 = text[..text.len() - unit.len_utf8()].parse().ok()?;
+
+    let seconds = match unit {
+        's' => 1,
+        'm' => 60,
+        'h' => 60 * 60,
+        'd' => 24 * 60 * 60,
+        'w' => 7 * 24 * 60 * 60,
+        _ => return None,
+    };
+
+    amount.checked_mul(seconds)
+}
+
+/// Seconds since the epoch of a local date (`2026-01-01`, its midnight) or
+/// an RFC 3339 timestamp.
+fn parse_date(text: &str) -> Option<i64> {
+    if let Ok(date) = NaiveDate::parse_from_str(text, "%Y-%m-%d") {
+        let midnight = Local
+            .from_local_datetime(&date.and_hms_opt(0, 0, 0)?)
+            .earliest()?;
+        return Some(midnight.timestamp());
+    }
+
+    DateTime::parse_from_rfc3339(text)
+        .ok()
+        .map(|time| time.timestamp())
+}
+
+/// Keeps the entries accepted by the filter together with the directories
+/// leading to them.
+pub fn filter_tree(
+    files: &[File],
+    dirs: &[Directory],
+    filter: &Filter,
+) -> (Vec<File>, Vec<Directory>) {
+    prune(files, dirs, filter, "", 1)
+}
+
+fn prune(
+    files: &[File],
+    dirs: &[Directory],
+    filter: &Filter,
+    prefix: &str,
+    depth: usize,
+) -> (Vec<File>, Vec<Directory>) {
+    let kept_files = files
+        .iter()
+        .filter(|file| {
+            let path = format!("{prefix}{}", file.name);
+            filter.matches(&Entry {
+                name: &file.name,
+                path: &path,
+                is_dir: false,
+                bytes: file.size_kb,
+                depth,
+                meta: &file.meta,
+            })
+        })
+        .cloned()
+        .collect();
+
+    let mut kept_dirs = Vec::new();
+    for dir in dirs {
+        let path = format!("{prefix}{}", dir.name);
+        let (files, dirs) = prune(
+            &dir.files,
+            &dir.dirs,
+            filter,
+            &format!("{path}/"),
+            depth + 1,
+        );
+        let matches = filter.matches(&Entry {
+            name: &dir.name,
+            path: &path,
+            is_dir: true,
+            bytes: snapshot::total_bytes(&dir.files, &dir.dirs),
+            depth,
+            meta: &dir.meta,
+        });
+
+        if matches || !files.is_empty() || !dirs.is_empty() {
+            kept_dirs.push(Directory {
+                name: dir.name.clone(),
+                size_kb: dir.size_kb,
+                files,
+                dirs,
+                meta: dir.meta.clone(),
+                hash: None,
+            });
+        }
+    }
+
+    (kept_files, kept_dirs)
+}
+
+/// Copy of the snapshot narrowed to the entries accepted by the filter,
+/// with its sizes, counts and digest recomputed.
+pub fn filter_snapshot(snapshot: &Snapshot, filter: &Filter) -> Snapshot {
+    let (mut files, mut dirs) = filter_tree(&snapshot.files, &snapshot.dirs, filter);
+    let size = snapshot::fill_sizes(&mut files, &mut dirs);
+
+    let mut filtered = Snapshot {
+        files,
+        dirs,
+        ..snapshot.clone()
+    };
+    filtered.set_size(size);
+    filtered.set_counts();
+    if filtered.root_digest.is_some() {
+        filtered.root_digest = Some(filtered.compute_digest());
+    }
+
+    filtered
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::snapshot::{Directory, File, Metadata};
+
+    use super::{filter_tree, Entry, Filter};
+
+    const NOW: i64 = 1_800_000_000;
+    const DAY: i64 = 24 * 60 * 60;
+
+    fn check(text: &str, entry: &Entry) -> bool {
+        Filter::parse_at(text, NOW).unwrap().matches(entry)
+    }
+
+    #[test]
+    fn test_parse_and_match() {
+        let meta = Metadata {
+            mode: Some(0o100644),
+            mtime: Some(NOW - 10 * DAY),
+            ..Metadata::default()
+        };
+        let entry = Entry {
+            name: "app.log",
+            path: "var/log/app.log",
+            is_dir: false,
+            bytes: 20 * 1024 * 1024,
+            depth: 3,
+            meta: &meta,
+        };
+
+        assert!(check(
+            "ext = \"log\" and size > 10MiB and depth <= 3",
+            &entry
+        ));
+        assert!(check("mtime < 30d", &entry));
+        assert!(!check("mtime > 30d", &entry));
+        assert!(check(
+            "not kind = dir and (name ~ \"*.txt\" or path =~ ^var/)",
+            &entry
+        ));
+        assert!(!check("mode = 644 AND uid != 0 or size < 1", &entry));
+        assert!(check("mtime >= 2020-01-01", &entry));
+    }
+
+    #[test]
+    fn test_invalid_filters() {
+        for text in [
+            "",
+            "size >",
+            "size > lots",
+            "colour = red",
+            "name < a",
+            "(size > 1",
+            "size > 1 extra",
+            "name = \"open",
+            "depth ~ 3",
+        ] {
+            assert!(Filter::parse_at(text, NOW).is_err(), "{text}");
+        }
+    }
+
+    #[test]
+    fn test_filter_tree() {
+        let file = |name: &str| File {
+            name: String::from(name),
+            size_kb: 10,
+            meta: Metadata::default(),
+        };
+        let dir = |name: &str, files: Vec<File>, dirs: Vec<Directory>| Directory {
+            name: String::from(name),
+            size_kb: 0,
+            files,
+            dirs,
+            meta: Metadata::default(),
+            hash: Some(String::from("digest")),
+        };
+        let files = Vec::from([file("a.log"), file("b.txt")]);
+        let dirs = Vec::from([
+            dir("logs", Vec::from([file("c.log")]), Vec::new()),
+            dir(
+                "docs",
+                Vec::from([file("d.txt")]),
+                Vec::from([dir("old", Vec::new(), Vec::new())]),
+            ),
+        ]);
+
+        let filter = Filter::parse_at("ext = log", NOW).unwrap();
+        let (files, dirs) = filter_tree(&files, &dirs, &filter);
+
+        assert_eq!(files, Vec::from([file("a.log")]));
+        assert_eq!(dirs.len(), 1);
+        assert_eq!(dirs[0].name, "logs");
+        assert_eq!(dirs[0].files, Vec::from([file("c.log")]));
+        assert_eq!(dirs[0].hash, None);
+    }
+}
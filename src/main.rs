@@ -2,11 +2,16 @@ use dotenv::dotenv;
 use errors::ServiceError;
 use std::env;
 
+mod archive;
+mod cli;
 mod commands;
 mod common;
 mod db;
 mod errors;
+mod filter;
+mod mtree;
 mod snapshot;
+mod storage;
 mod user_interface;
 
 #[tokio::main]
@@ -22,6 +27,11 @@ async fn main() -> Result<(), ServiceError> {
         Err(_) => return Err(ServiceError::FailedToCreateDB),
     };
 
+    if let Some(command) = args.get(1) {
+        let cli_args = cli::Args::parse(&args[2..]);
+        return commands::run_cli_command(command, &cli_args, &path, &db).await;
+    }
+
     user_interface::greeting();
 
     loop {
@@ -0,0 +1,367 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, FixedOffset};
+
+use crate::errors::ServiceError;
+use crate::snapshot::{self, Directory, File, Metadata, Snapshot};
+
+/// Renders a snapshot as an mtree specification with one full-path entry
+/// per line, the format produced by `bsdtar --format=mtree`.
+pub fn render(snapshot: &Snapshot) -> String {
+    let mut lines = Vec::from([String::from("#mtree"), String::from(". type=dir")]);
+    render_entries(&snapshot.files, &snapshot.dirs, ".", &mut lines);
+
+    let mut text = lines.join("\n");
+    text.push('\n');
+    text
+}
+
+fn render_entries(files: &[File], dirs: &[Directory], prefix: &str, lines: &mut Vec<String>) {
+    for file in files {
+        let path = format!("{prefix}/{}", encode_name(&file.name));
+        let mut keywords = match &file.meta.link {
+            Some(link) => Vec::from([
+                String::from("type=link"),
+                format!("link={}", encode_name(link)),
+            ]),
+            None => Vec::from([String::from("type=file"), format!("size={}", file.size_kb)]),
+        };
+        push_metadata(&file.meta, &mut keywords);
+        lines.push(format!("{path} {}", keywords.join(" ")));
+    }
+
+    for dir in dirs {
+        let path = format!("{prefix}/{}", encode_name(&dir.name));
+        let mut keywords = Vec::from([String::from("type=dir")]);
+        push_metadata(&dir.meta, &mut keywords);
+        lines.push(format!("{path} {}", keywords.join(" ")));
+        render_entries(&dir.files, &dir.dirs, &path, lines);
+    }
+}
+
+fn push_metadata(meta: &Metadata, keywords: &mut Vec<String>) {
+    if let Some(mode) = meta.mode {
+        keywords.push(format!("mode={mode:04o}"));
+    }
+    if let Some(uid) = meta.uid {
+        keywords.push(format!("uid={uid}"));
+    }
+    if let Some(gid) = meta.gid {
+        keywords.push(format!("gid={gid}"));
+    }
+    if let Some(mtime) = meta.mtime {
+        keywords.push(format!("time={mtime}.000000000"));
+    }
+    if let Some(sha256) = &meta.sha256 {
+        keywords.push(format!("sha256digest={sha256}"));
+    }
+}
+
+/// Builds a snapshot from an mtree specification. Both the full-path format
+/// and the classic relative format (with `..` entries) are understood.
+pub fn parse(
+    text: &str,
+    version: i32,
+    date: DateTime<FixedOffset>,
+    path: String,
+) -> Result<Snapshot, ServiceError> {
+    let mut snapshot = Snapshot::create(version, date, path);
+    let mut defaults: HashMap<String, String> = HashMap::new();
+    let mut cwd: Vec<String> = Vec::new();
+
+    for line in join_continuations(text) {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut tokens = line.split_whitespace();
+        let name = tokens.next().unwrap_or_default();
+
+        match name {
+            "/set" => {
+                for (key, value) in parse_keywords(tokens)? {
+                    defaults.insert(key, value);
+                }
+                continue;
+            }
+            "/unset" => {
+                for key in tokens {
+                    if key == "all" {
+                        defaults.clear();
+                    } else {
+                        defaults.remove(key);
+                    }
+                }
+                continue;
+            }
+            ".." => {
+                cwd.pop();
+                continue;
+            }
+            _ => {}
+        }
+
+        let mut keywords = defaults.clone();
+        keywords.extend(parse_keywords(tokens)?);
+
+        let kind = keywords.get("type").map_or("file", |kind| kind.as_str());
+        let name = decode_name(name);
+
+        let mut components: Vec<String> = if name.contains('/') {
+            name.split('/')
+                .filter(|c| !c.is_empty() && *c != ".")
+                .map(String::from)
+                .collect()
+        } else {
+            let mut components = cwd.clone();
+            if name != "." {
+                components.push(name.clone());
+                if kind == "dir" {
+                    cwd.push(name);
+                }
+            }
+            components
+        };
+
+        let Some(entry_name) = components.pop() else {
+            continue;
+        };
+
+        let meta = parse_metadata(&keywords)?;
+        let (files, dirs) =
+            snapshot::get_parent(&mut snapshot.files, &mut snapshot.dirs, &components);
+
+        if kind == "dir" {
+            snapshot::upsert_dir(dirs, entry_name, meta);
+        } else {
+            let size_kb = match keywords.get("size") {
+                Some(size) => size.parse().or(Err(ServiceError::FailedToImport))?,
+                None => 0,
+            };
+            files.push(File {
+                name: entry_name,
+                size_kb,
+                meta,
+            });
+        }
+    }
+
+    let size = snapshot::fill_sizes(&mut snapshot.files, &mut snapshot.dirs);
+    snapshot.set_size(size);
+
+    Ok(snapshot)
+}
+
+fn join_continuations(text: &str) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut current = String::new();
+
+    for line in text.lines() {
+        match line.strip_suffix('\\') {
+            Some(line) => {
+                current.push_str(line);
+                current.push(' ');
+            }
+            None => {
+                current.push_str(line);
+                lines.push(current);
+                current = String::new();
+            }
+        }
+    }
+    if !current.is_empty() {
+        lines.push(current);
+    }
+
+    lines
+}
+
+fn parse_keywords<'a>(
+    tokens: impl Iterator<Item = &'a str>,
+) -> Result<Vec<(String, String)>, ServiceError> {
+    tokens
+        .map(|token| match token.split_once('=') {
+            Some((key, value)) => Ok((String::from(key), String::from(value))),
+            None => Err(ServiceError::FailedToImport),
+        })
+        .collect()
+}
+
+fn parse_metadata(keywords: &HashMap<String, String>) -> Result<Metadata, ServiceError> {
+    let number = |key: &str, radix: u32| -> Result<Option<u32>, ServiceError> {
+        match keywords.get(key) {
+            Some(value) => u32::from_str_radix(value, radix)
+                .map(Some)
+                .or(Err(ServiceError::FailedToImport)),
+            None => Ok(None),
+        }
+    };
+
+    let mtime = match keywords.get("time") {
+        Some(time) => {
+            let seconds = time.split('.').next().unwrap_or_default();
+            Some(seconds.parse().or(Err(ServiceError::FailedToImport))?)
+        }
+        None => None,
+    };
+
+    Ok(Metadata {
+        mode: number("mode", 8)?,
+        uid: number("uid", 10)?,
+        gid: number("gid", 10)?,
+        mtime,
+        sha256: keywords
+            .get("sha256digest")
+            .or(keywords.get("sha256"))
+            .cloned(),
+        link: keywords.get("link").map(|link| decode_name(link)),
+    })
+}
+
+/// Escapes `#`, `\`, `=` and every byte outside printable ASCII (space,
+/// control characters, bytes of non-ASCII characters) as `\ooo`.
+pub fn encode_name(name: &str) -> String {
+    let mut encoded = String::new();
+
+    for byte in name.bytes() {
+        if byte.is_ascii_graphic() && byte != b'#' && byte != b'\\' && byte != b'=' {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("\\{byte:03o}"));
+        }
+    }
+
+    encoded
+}
+
+pub fn decode_name(name: &str) -> String {
+    let bytes = name.as_bytes();
+    let mut decoded = Vec::new();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'\\' {
+            let octal = name
+                .get(i + 1..i + 4)
+                .and_then(|o| u8::from_str_radix(o, 8).ok());
+            if let Some(byte) = octal {
+                decoded.push(byte);
+                i += 4;
+                continue;
+            }
+            if bytes.get(i + 1) == Some(&b'\\') {
+                decoded.push(b'\\');
+                i += 2;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::DateTime;
+
+    use crate::snapshot::fixtures::{self, create_dir, create_file};
+    use crate::snapshot::{Directory, File, Metadata, Snapshot};
+
+    use super::{decode_name, encode_name, parse, render};
+
+    fn create_snapshot() -> Snapshot {
+        let file = File {
+            meta: Metadata {
+                mode: Some(0o644),
+                uid: Some(1000),
+                gid: Some(1000),
+                mtime: Some(1690000000),
+                sha256: Some(String::from("ab12")),
+                link: None,
+            },
+            ..create_file("my file.txt", 2048)
+        };
+        let link = File {
+            meta: Metadata {
+                link: Some(String::from("../target")),
+                ..Metadata::default()
+            },
+            ..create_file("link", 0)
+        };
+        let dir = Directory {
+            size_kb: 2,
+            meta: Metadata {
+                mode: Some(0o755),
+                ..Metadata::default()
+            },
+            ..create_dir("dir", Vec::from([file.clone(), link]), Vec::new())
+        };
+
+        let mut snapshot =
+            fixtures::create_snapshot(1, "test", Vec::from([file]), Vec::from([dir]));
+        snapshot.set_size(2);
+        snapshot
+    }
+
+    #[test]
+    fn test_render() {
+        let text = render(&create_snapshot());
+        assert!(text.starts_with("#mtree\n. type=dir\n"));
+        assert!(text.contains("./my\\040file.txt type=file size=2048 mode=0644 uid=1000 gid=1000 time=1690000000.000000000 sha256digest=ab12\n"));
+        assert!(text.contains("./dir type=dir mode=0755\n"));
+        assert!(text.contains("./dir/link type=link link=../target\n"));
+    }
+
+    #[test]
+    fn test_render_and_parse_round_trip() {
+        let snapshot = create_snapshot();
+        let text = render(&snapshot);
+        let result = parse(
+            &text,
+            1,
+            DateTime::UNIX_EPOCH.fixed_offset(),
+            String::from("test"),
+        )
+        .unwrap();
+        assert_eq!(result, snapshot);
+    }
+
+    #[test]
+    fn test_parse_relative_format() {
+        let text = "#mtree\n/set type=file uid=0 gid=0 mode=0644\n. type=dir\nsub type=dir mode=0755\n    a size=10\n..\nb size=20 \\\n    uid=5\n";
+        let result = parse(
+            text,
+            2,
+            DateTime::UNIX_EPOCH.fixed_offset(),
+            String::from("test"),
+        )
+        .unwrap();
+
+        assert_eq!(result.files.len(), 1);
+        assert_eq!(result.files[0].name, "b");
+        assert_eq!(result.files[0].meta.uid, Some(5));
+        assert_eq!(result.dirs[0].name, "sub");
+        assert_eq!(result.dirs[0].meta.mode, Some(0o755));
+        assert_eq!(result.dirs[0].files[0].size_kb, 10);
+    }
+
+    #[test]
+    fn test_parse_invalid_keyword() {
+        let result = parse(
+            "./a type=file size",
+            1,
+            DateTime::UNIX_EPOCH.fixed_offset(),
+            String::new(),
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_encode_and_decode_name() {
+        assert_eq!(encode_name("a b#c"), "a\\040b\\043c");
+        assert_eq!(decode_name("a\\040b\\043c"), "a b#c");
+    }
+}
@@ -1,35 +1,160 @@
 use std::path::{Path, PathBuf};
 
-use mongodb::bson::doc;
+use chrono::{DateTime, FixedOffset, TimeZone, Utc};
+use glob::Pattern;
+use mongodb::bson::{self, doc, Bson, Document};
+use mongodb::options::FindOneOptions;
 use mongodb::Collection;
-use serde::{Deserialize, Serialize};
-
-use crate::{common::get_size, errors::ServiceError};
-
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    common::{get_hostname, get_machine_id, get_metadata, get_size, get_username, log},
+    errors::ServiceError,
+};
+
+/// Serialized through `remote = "Self"` so that the `Deserialize` impl below
+/// can fill in the offset of legacy documents before the derived one runs.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(remote = "Self")]
 pub struct Snapshot {
     pub version: i32,
-    pub date: String,
+    /// Creation time, stored as a BSON date. Dates written as strings by
+    /// older versions are still read.
+    #[serde(
+        serialize_with = "serialize_date",
+        deserialize_with = "deserialize_date"
+    )]
+    pub date: DateTime<Utc>,
+    /// Offset of the local time zone at creation, in seconds east of UTC.
+    /// Taken from the date string of legacy documents.
+    #[serde(default)]
+    pub utc_offset: i32,
     pub path: String,
     pub size_kb: u64,
     pub files: Vec<File>,
     pub dirs: Vec<Directory>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub root_digest: Option<String>,
+    /// Root node in the deduplicated tree storage. When set, `files` and
+    /// `dirs` are empty in the stored document.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tree: Option<String>,
+    /// Set on marker versions that were recorded instead of an identical
+    /// tree. The tree itself is read from the referenced version.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unchanged_since: Option<i32>,
+    /// User-supplied name of the version, unique per path.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Machine the snapshot was taken on; part of the series key.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub machine_id: Option<String>,
+    /// OS user that took the snapshot.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_version: Option<String>,
+    /// Number of files and directories in the whole tree, so that listings
+    /// do not need to load it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_count: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dir_count: Option<u64>,
+}
+
+impl Serialize for Snapshot {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Snapshot::serialize(self, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Snapshot {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut document = Document::deserialize(deserializer)?;
+        if !document.contains_key("utc_offset") {
+            document.insert("utc_offset", stored_offset(&document));
+        }
+
+        Snapshot::deserialize(bson::Deserializer::new(Bson::Document(document)))
+            .map_err(de::Error::custom)
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+/// Key of a version series. The same path on different hosts has its own
+/// versions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Series {
+    pub host: String,
+    pub path: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct Directory {
     pub name: String,
     pub size_kb: u64,
     pub files: Vec<File>,
     pub dirs: Vec<Directory>,
+    #[serde(default)]
+    pub meta: Metadata,
+    /// Digest of the directory, known without hashing when it was loaded
+    /// from the deduplicated tree storage.
+    #[serde(skip)]
+    pub hash: Option<String>,
 }
 
-impl Directory {}
+impl Directory {
+    /// Hash of the directory name, attributes and whole content.
+    pub fn digest(&self) -> String {
+        if let Some(hash) = &self.hash {
+            return hash.clone();
+        }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+        self.digest_with(&tree_digest(&self.files, &self.dirs))
+    }
+    /// Same as `digest` for an already known `listing_digest` of the content.
+    pub fn digest_with(&self, content: &str) -> String {
+        let meta = serde_json::to_string(&self.meta).expect("Serialization Error");
+
+        let mut hasher = Sha256::new();
+        hasher.update(format!(
+            "{}\n{}\n{meta}\n{content}",
+            self.name, self.size_kb
+        ));
+        hex::encode(hasher.finalize())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct File {
     pub name: String,
     pub size_kb: u64,
+    #[serde(default)]
+    pub meta: Metadata,
+}
+
+/// Optional attributes of an entry. Older snapshots and sources that do not
+/// know an attribute simply leave it empty.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct Metadata {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uid: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gid: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mtime: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub link: Option<String>,
 }
 
 pub trait Comparison {
@@ -79,22 +204,109 @@ impl Info for File {
 }
 
 impl Snapshot {
-    pub fn create(version: i32, date: String, path: String) -> Snapshot {
+    pub fn create(version: i32, date: DateTime<FixedOffset>, path: String) -> Snapshot {
         Snapshot {
             version: version,
-            date: date,
+            date: date.with_timezone(&Utc),
+            utc_offset: date.offset().local_minus_utc(),
             path: path,
             size_kb: 0,
             files: Vec::new(),
             dirs: Vec::new(),
+            root_digest: None,
+            tree: None,
+            unchanged_since: None,
+            label: None,
+            note: None,
+            tags: Vec::new(),
+            host: None,
+            machine_id: None,
+            user: None,
+            tool_version: None,
+            file_count: None,
+            dir_count: None,
         }
     }
+    /// Creation time in the time zone it was taken in.
+    pub fn local_date(&self) -> DateTime<FixedOffset> {
+        let offset =
+            FixedOffset::east_opt(self.utc_offset).unwrap_or(FixedOffset::east_opt(0).unwrap());
+        self.date.with_timezone(&offset)
+    }
+    /// Records where, by whom and with which tool version the snapshot is
+    /// taken.
+    pub fn set_origin(&mut self, host: &str) {
+        self.host = Some(String::from(host));
+        self.machine_id = get_machine_id();
+        self.user = get_username();
+        self.tool_version = Some(String::from(env!("CARGO_PKG_VERSION")));
+    }
+    pub fn set_counts(&mut self) {
+        let (files, dirs) = count_entries(&self.files, &self.dirs);
+        self.file_count = Some(files);
+        self.dir_count = Some(dirs);
+    }
     pub fn set_size(&mut self, size: u64) {
         self.size_kb = size;
     }
+    pub fn compute_digest(&self) -> String {
+        tree_digest(&self.files, &self.dirs)
+    }
+    /// Compares the stored root digests, computing the missing ones from the
+    /// trees, so that snapshots taken before digests existed still match.
+    pub fn same_content(&self, other: &Snapshot) -> bool {
+        let digest = |snapshot: &Snapshot| {
+            snapshot
+                .root_digest
+                .clone()
+                .unwrap_or_else(|| snapshot.compute_digest())
+        };
+
+        self.size_kb == other.size_kb && digest(self) == digest(other)
+    }
+}
+
+/// Glob patterns of entries left out of a snapshot. A pattern containing `/`
+/// is matched against the path relative to `root`, any other pattern against
+/// the entry name.
+#[derive(Debug, Default)]
+pub struct Exclusions {
+    root: PathBuf,
+    patterns: Vec<Pattern>,
+}
+
+impl Exclusions {
+    pub fn create(root: &Path, patterns: &[String]) -> Result<Exclusions, ServiceError> {
+        let patterns = patterns
+            .iter()
+            .map(|pattern| Pattern::new(pattern).or(Err(ServiceError::InvalidArgument)))
+            .collect::<Result<Vec<Pattern>, ServiceError>>()?;
+
+        Ok(Exclusions {
+            root: root.to_path_buf(),
+            patterns,
+        })
+    }
+    pub fn is_excluded(&self, path: &Path) -> bool {
+        let relative = path.strip_prefix(&self.root).unwrap_or(path);
+        let name = relative.file_name().and_then(|name| name.to_str());
+
+        self.patterns.iter().any(|pattern| {
+            if pattern.as_str().contains('/') {
+                pattern.matches_path(relative)
+            } else {
+                name.is_some_and(|name| pattern.matches(name))
+            }
+        })
+    }
 }
 
-pub fn fill_and_return_size(path: &Path, files: &mut Vec<File>, dirs: &mut Vec<Directory>) -> u64 {
+pub fn fill_and_return_size(
+    path: &Path,
+    files: &mut Vec<File>,
+    dirs: &mut Vec<Directory>,
+    exclusions: &Exclusions,
+) -> u64 {
     for entry in path.read_dir().expect("Reading dir was failed") {
         if let Ok(entry) = entry {
             let item_name = String::from(
@@ -106,10 +318,21 @@ pub fn fill_and_return_size(path: &Path, files: &mut Vec<File>, dirs: &mut Vec<D
                     .expect("File name Error"),
             );
 
-            if entry.path().is_file() {
+            if exclusions.is_excluded(&entry.path()) {
+                continue;
+            }
+
+            let meta = get_metadata(&entry.path());
+
+            if meta.link.is_some() || entry.path().is_file() {
+                let size_kb = match meta.link {
+                    Some(_) => 0,
+                    None => get_size(&entry.path()),
+                };
                 let file = File {
                     name: item_name.clone(),
-                    size_kb: get_size(&entry.path()),
+                    size_kb,
+                    meta,
                 };
                 files.push(file);
             } else {
@@ -118,32 +341,259 @@ pub fn fill_and_return_size(path: &Path, files: &mut Vec<File>, dirs: &mut Vec<D
                     size_kb: 0,
                     files: Vec::new(),
                     dirs: Vec::new(),
+                    meta,
+                    hash: None,
                 };
                 let mut new_path = PathBuf::from(path);
                 new_path.push(&item_name);
-                dir.size_kb = fill_and_return_size(&new_path, &mut dir.files, &mut dir.dirs);
+                dir.size_kb =
+                    fill_and_return_size(&new_path, &mut dir.files, &mut dir.dirs, exclusions);
                 dirs.push(dir);
             }
         }
     }
 
+    sum_size(files, dirs)
+}
+
+/// Order-independent hash of a directory listing.
+pub fn tree_digest(files: &[File], dirs: &[Directory]) -> String {
+    let dir_digests: Vec<String> = dirs.iter().map(|dir| dir.digest()).collect();
+    listing_digest(files, &dir_digests)
+}
+
+/// Same as `tree_digest` with the digests of the directories already known.
+pub fn listing_digest(files: &[File], dir_digests: &[String]) -> String {
+    let mut entries: Vec<String> = files
+        .iter()
+        .map(|file| {
+            let file = serde_json::to_string(file).expect("Serialization Error");
+            format!("f {file}")
+        })
+        .collect();
+    entries.extend(dir_digests.iter().map(|digest| format!("d {digest}")));
+    entries.sort();
+
+    let mut hasher = Sha256::new();
+    hasher.update(entries.join("\n"));
+    hex::encode(hasher.finalize())
+}
+
+/// Number of files and directories below a listing, at any depth.
+pub fn count_entries(files: &[File], dirs: &[Directory]) -> (u64, u64) {
+    dirs.iter().fold(
+        (files.len() as u64, dirs.len() as u64),
+        |(file_count, dir_count), dir| {
+            let (files, dirs) = count_entries(&dir.files, &dir.dirs);
+            (file_count + files, dir_count + dirs)
+        },
+    )
+}
+
+/// Size in bytes of everything below a listing. Unlike `size_kb` of a
+/// directory it is exact, as file sizes are kept in bytes.
+pub fn total_bytes(files: &[File], dirs: &[Directory]) -> u64 {
+    files.iter().map(|file| file.size_kb).sum::<u64>()
+        + dirs
+            .iter()
+            .map(|dir| total_bytes(&dir.files, &dir.dirs))
+            .sum::<u64>()
+}
+
+/// Returns the directory reached by following `components` from the root.
+pub fn find_dir<'a>(dirs: &'a [Directory], components: &[String]) -> Option<&'a Directory> {
+    let (first, rest) = components.split_first()?;
+    let dir = dirs.iter().find(|dir| &dir.name == first)?;
+
+    if rest.is_empty() {
+        Some(dir)
+    } else {
+        find_dir(&dir.dirs, rest)
+    }
+}
+
+pub fn sum_size(files: &[File], dirs: &[Directory]) -> u64 {
     (files.iter().fold(0, |s, e| s + e.size_kb) + dirs.iter().fold(0, |s, e| s + e.size_kb)) / 1024
 }
 
+/// Returns the content of the directory reached by following `components`
+/// from the root, creating the missing directories on the way.
+pub fn get_parent<'a>(
+    files: &'a mut Vec<File>,
+    dirs: &'a mut Vec<Directory>,
+    components: &[String],
+) -> (&'a mut Vec<File>, &'a mut Vec<Directory>) {
+    let Some((first, rest)) = components.split_first() else {
+        return (files, dirs);
+    };
+
+    let index = match dirs.iter().position(|dir| &dir.name == first) {
+        Some(index) => index,
+        None => {
+            upsert_dir(dirs, first.clone(), Metadata::default());
+            dirs.len() - 1
+        }
+    };
+
+    let dir = &mut dirs[index];
+    get_parent(&mut dir.files, &mut dir.dirs, rest)
+}
+
+pub fn upsert_dir(dirs: &mut Vec<Directory>, name: String, meta: Metadata) {
+    match dirs.iter_mut().find(|dir| dir.name == name) {
+        Some(dir) => dir.meta = meta,
+        None => dirs.push(Directory {
+            name,
+            size_kb: 0,
+            files: Vec::new(),
+            dirs: Vec::new(),
+            meta,
+            hash: None,
+        }),
+    }
+}
+
+/// Recomputes the directory sizes of a tree built from a listing and returns
+/// the size of its root.
+pub fn fill_sizes(files: &mut [File], dirs: &mut [Directory]) -> u64 {
+    for dir in dirs.iter_mut() {
+        dir.size_kb = fill_sizes(&mut dir.files, &mut dir.dirs);
+    }
+
+    sum_size(files, dirs)
+}
+
+impl Series {
+    pub fn create(host: &str, path: &str) -> Series {
+        Series {
+            host: String::from(host),
+            path: String::from(path),
+        }
+    }
+    /// Series of `path` on this machine.
+    pub fn local(path: &str) -> Series {
+        Series::create(&get_hostname(), path)
+    }
+    /// Matches the snapshots of the series. Snapshots taken before hosts were
+    /// recorded have none and belong to every series of their path.
+    pub fn filter(&self) -> Document {
+        doc! {"path": &self.path, "host": {"$in": [&self.host, Bson::Null]}}
+    }
+    pub fn version_filter(&self, version: i32) -> Document {
+        let mut filter = self.filter();
+        filter.insert("version", version);
+        filter
+    }
+}
+
+/// Format of the dates stored as strings before they became BSON dates.
+const LEGACY_DATE_FORMAT: &str = "%Y-%m-%d %H:%M:%S%.f %:z";
+
+/// Reads a stored date, either a BSON date or a legacy date string.
+pub fn parse_date(value: &Bson) -> Option<DateTime<Utc>> {
+    match value {
+        Bson::DateTime(date) => Utc.timestamp_millis_opt(date.timestamp_millis()).single(),
+        Bson::String(text) => DateTime::parse_from_str(text, LEGACY_DATE_FORMAT)
+            .ok()
+            .map(|date| date.with_timezone(&Utc)),
+        _ => None,
+    }
+}
+
+fn serialize_date<S: Serializer>(date: &DateTime<Utc>, serializer: S) -> Result<S::Ok, S::Error> {
+    bson::DateTime::from_millis(date.timestamp_millis()).serialize(serializer)
+}
+
+/// Offset of a stored snapshot in seconds east of UTC: the `utc_offset`
+/// field, or the offset written in a legacy date string.
+pub fn stored_offset(document: &Document) -> i32 {
+    if let Ok(offset) = document.get_i32("utc_offset") {
+        return offset;
+    }
+
+    document
+        .get_str("date")
+        .ok()
+        .and_then(|text| DateTime::parse_from_str(text, LEGACY_DATE_FORMAT).ok())
+        .map_or(0, |date| date.offset().local_minus_utc())
+}
+
+/// An unreadable date doesn't make the whole snapshot unreadable; it is
+/// replaced by the Unix epoch.
+fn deserialize_date<'de, D: Deserializer<'de>>(deserializer: D) -> Result<DateTime<Utc>, D::Error> {
+    let value = Bson::deserialize(deserializer)?;
+
+    Ok(parse_date(&value).unwrap_or_else(|| {
+        log(&format!(
+            "Warning: invalid date {value}, the Unix epoch is used"
+        ));
+        DateTime::UNIX_EPOCH
+    }))
+}
+
 pub async fn get_version(
     collection: &Collection<Snapshot>,
-    path: &str,
+    series: &Series,
 ) -> Result<i32, ServiceError> {
-    let mut cursor = match collection.find(doc! {"path": path}, None).await {
-        Ok(cursor) => cursor,
+    let options = FindOneOptions::builder()
+        .sort(doc! {"version": -1})
+        .projection(doc! {"version": 1})
+        .build();
+
+    let latest = match collection
+        .clone_with_type::<Document>()
+        .find_one(series.filter(), options)
+        .await
+    {
+        Ok(latest) => latest,
         Err(_) => return Err(ServiceError::FailedToFoundCollection),
     };
-    let mut version = 0;
 
-    while cursor.advance().await.unwrap() {
-        version += 1
+    Ok(latest.map_or(0, |latest| latest.get_i32("version").unwrap_or_default()))
+}
+
+/// Builders of small trees for the tests of the modules reading snapshots.
+#[cfg(test)]
+pub mod fixtures {
+    use chrono::DateTime;
+
+    use super::{Directory, File, Metadata, Snapshot};
+
+    pub fn create_file(name: &str, size: u64) -> File {
+        File {
+            name: String::from(name),
+            size_kb: size,
+            meta: Metadata::default(),
+        }
+    }
+
+    pub fn create_dir(name: &str, files: Vec<File>, dirs: Vec<Directory>) -> Directory {
+        Directory {
+            name: String::from(name),
+            size_kb: 0,
+            files,
+            dirs,
+            meta: Metadata::default(),
+            hash: None,
+        }
+    }
+
+    /// Version of `path` taken at the Unix epoch.
+    pub fn create_snapshot(
+        version: i32,
+        path: &str,
+        files: Vec<File>,
+        dirs: Vec<Directory>,
+    ) -> Snapshot {
+        let mut snapshot = Snapshot::create(
+            version,
+            DateTime::UNIX_EPOCH.fixed_offset(),
+            String::from(path),
+        );
+        snapshot.files = files;
+        snapshot.dirs = dirs;
+        snapshot
     }
-    Ok(version)
 }
 
 #[cfg(test)]
@@ -151,11 +601,16 @@ mod tests {
 
     use std::{env, fs, path::PathBuf};
 
+    use chrono::{DateTime, FixedOffset, TimeZone};
+    use mongodb::bson::{doc, Bson};
     use mongodb::Collection;
 
     use crate::{
         db,
-        snapshot::{fill_and_return_size, get_version, Comparison, File, Info, Snapshot},
+        snapshot::{
+            fill_and_return_size, find_dir, get_version, parse_date, total_bytes, Comparison,
+            Exclusions, File, Info, Metadata, Series, Snapshot,
+        },
     };
 
     use super::Directory;
@@ -164,6 +619,7 @@ mod tests {
         File {
             name: String::from("test"),
             size_kb: 10,
+            meta: Metadata::default(),
         }
     }
 
@@ -173,6 +629,8 @@ mod tests {
             size_kb: 20,
             files: Vec::from([create_file()]),
             dirs: Vec::new(),
+            meta: Metadata::default(),
+            hash: None,
         }
     }
 
@@ -184,11 +642,24 @@ mod tests {
 
         Snapshot {
             version: 1,
-            date: String::from("date"),
+            date: DateTime::UNIX_EPOCH,
+            utc_offset: 0,
             path: String::from(path.to_str().unwrap()),
             size_kb: 10,
             files: Vec::from([create_file(), create_file(), create_file()]),
             dirs: Vec::from([create_dir(), create_dir(), create_dir(), create_dir()]),
+            root_digest: None,
+            tree: None,
+            unchanged_since: None,
+            label: None,
+            note: None,
+            tags: Vec::new(),
+            host: None,
+            machine_id: None,
+            user: None,
+            tool_version: None,
+            file_count: None,
+            dir_count: None,
         }
     }
 
@@ -210,7 +681,9 @@ mod tests {
         let collection: Collection<Snapshot> =
             db.collection(&env::var("COLL_NAME").expect("COLL_NAME must be set"));
 
-        let result = get_version(&collection, path).await.unwrap();
+        let result = get_version(&collection, &Series::local(path))
+            .await
+            .unwrap();
         assert_eq!(result, 0);
     }
     #[tokio::test]
@@ -222,7 +695,9 @@ mod tests {
         let collection: Collection<Snapshot> =
             db.collection(&env::var("COLL_NAME").expect("COLL_NAME must be set"));
 
-        let result = get_version(&collection, path).await.unwrap();
+        let result = get_version(&collection, &Series::local(path))
+            .await
+            .unwrap();
         assert_eq!(result, 1);
     }
     #[tokio::test]
@@ -234,20 +709,38 @@ mod tests {
         let collection: Collection<Snapshot> =
             db.collection(&env::var("COLL_NAME").expect("COLL_NAME must be set"));
 
-        let result = get_version(&collection, path).await.unwrap();
+        let result = get_version(&collection, &Series::local(path))
+            .await
+            .unwrap();
         assert_eq!(result, 2);
     }
     #[test]
     fn test_create_snapshot() {
         let snapshot = Snapshot {
             version: 1,
-            date: String::new(),
+            date: DateTime::UNIX_EPOCH,
+            utc_offset: 0,
             path: String::new(),
             size_kb: 0,
             files: Vec::new(),
             dirs: Vec::new(),
+            root_digest: None,
+            tree: None,
+            unchanged_since: None,
+            label: None,
+            note: None,
+            tags: Vec::new(),
+            host: None,
+            machine_id: None,
+            user: None,
+            tool_version: None,
+            file_count: None,
+            dir_count: None,
         };
-        assert_eq!(Snapshot::create(1, String::new(), String::new()), snapshot);
+        assert_eq!(
+            Snapshot::create(1, DateTime::UNIX_EPOCH.fixed_offset(), String::new()),
+            snapshot
+        );
     }
 
     #[test]
@@ -327,9 +820,134 @@ mod tests {
             &PathBuf::from(&snapshot.path),
             &mut snapshot.files,
             &mut snapshot.dirs,
+            &Exclusions::default(),
         );
 
         fs::remove_dir_all(&snapshot.path).unwrap();
         assert_eq!(size, 4);
     }
+
+    #[test]
+    fn test_exclusions() {
+        let root = PathBuf::from("/root");
+        let patterns = [String::from("*.log"), String::from("cache/*")];
+        let exclusions = Exclusions::create(&root, &patterns).unwrap();
+
+        assert!(exclusions.is_excluded(&PathBuf::from("/root/dir/app.log")));
+        assert!(exclusions.is_excluded(&PathBuf::from("/root/cache/data")));
+        assert!(!exclusions.is_excluded(&PathBuf::from("/root/dir/cache/data")));
+        assert!(!exclusions.is_excluded(&PathBuf::from("/root/dir/app.txt")));
+    }
+
+    #[test]
+    fn test_same_content_ignores_order() {
+        let snapshot = create_snapshot();
+        let mut other = create_snapshot();
+        other.version = 2;
+        other.dirs[0].name = String::from("other");
+        other.dirs.reverse();
+
+        let mut changed = create_snapshot();
+        changed.dirs[0].name = String::from("other");
+
+        assert!(other.same_content(&changed));
+        assert!(!snapshot.same_content(&changed));
+    }
+
+    #[test]
+    fn test_same_content_uses_stored_digest() {
+        let snapshot = create_snapshot();
+        let mut marker = create_snapshot();
+        marker.root_digest = Some(snapshot.compute_digest());
+        marker.files.clear();
+        marker.dirs.clear();
+
+        assert!(marker.same_content(&snapshot));
+    }
+
+    #[test]
+    fn test_date_round_trip_and_legacy_dates() {
+        let offset = FixedOffset::east_opt(3 * 3600).unwrap();
+        let date = offset.with_ymd_and_hms(2023, 7, 20, 12, 0, 0).unwrap();
+        let snapshot = Snapshot::create(1, date, String::from("test"));
+
+        let document = mongodb::bson::to_document(&snapshot).unwrap();
+        assert!(matches!(document.get("date"), Some(Bson::DateTime(_))));
+        let result: Snapshot = mongodb::bson::from_document(document).unwrap();
+        assert_eq!(result.local_date(), date);
+
+        let json = serde_json::to_string(&snapshot).unwrap();
+        assert_eq!(serde_json::from_str::<Snapshot>(&json).unwrap(), snapshot);
+
+        let legacy = Bson::String(String::from("2023-07-20 12:00:00.123456 +03:00"));
+        assert_eq!(
+            parse_date(&legacy),
+            DateTime::parse_from_rfc3339("2023-07-20T09:00:00.123456Z")
+                .ok()
+                .map(|date| date.to_utc())
+        );
+        assert_eq!(parse_date(&Bson::String(String::from("date"))), None);
+    }
+
+    #[test]
+    fn test_legacy_document_dates() {
+        let mut document = mongodb::bson::to_document(&create_snapshot()).unwrap();
+        document.remove("utc_offset");
+        document.insert("date", "2023-07-20 12:00:00.123456 +03:00");
+
+        let result: Snapshot = mongodb::bson::from_document(document.clone()).unwrap();
+        assert_eq!(result.utc_offset, 3 * 3600);
+        assert_eq!(
+            result.local_date().to_rfc3339(),
+            "2023-07-20T12:00:00.123456+03:00"
+        );
+
+        document.insert("date", "date");
+        let result: Snapshot = mongodb::bson::from_document(document).unwrap();
+        assert_eq!(result.date, DateTime::UNIX_EPOCH);
+        assert_eq!(result.utc_offset, 0);
+    }
+
+    #[test]
+    fn test_series_filter() {
+        let series = Series::create("web1", "/var/www");
+
+        assert_eq!(
+            series.version_filter(3),
+            doc! {"path": "/var/www", "host": {"$in": ["web1", Bson::Null]}, "version": 3}
+        );
+    }
+
+    #[test]
+    fn test_set_origin() {
+        let mut snapshot = Snapshot::create(1, DateTime::UNIX_EPOCH.fixed_offset(), String::new());
+        snapshot.set_origin("web1");
+
+        assert_eq!(snapshot.host, Some(String::from("web1")));
+        assert_eq!(
+            snapshot.tool_version,
+            Some(String::from(env!("CARGO_PKG_VERSION")))
+        );
+    }
+
+    #[test]
+    fn test_set_counts() {
+        let mut snapshot = create_snapshot();
+        snapshot.dirs[0].dirs.push(create_dir());
+        snapshot.set_counts();
+
+        assert_eq!(snapshot.file_count, Some(8));
+        assert_eq!(snapshot.dir_count, Some(5));
+    }
+
+    #[test]
+    fn test_total_bytes_and_find_dir() {
+        let mut snapshot = create_snapshot();
+        snapshot.dirs[0].dirs.push(create_dir());
+        let components = [String::from("test"), String::from("test")];
+
+        assert_eq!(total_bytes(&snapshot.files, &snapshot.dirs), 80);
+        assert_eq!(find_dir(&snapshot.dirs, &components), Some(&create_dir()));
+        assert_eq!(find_dir(&snapshot.dirs, &[String::from("missing")]), None);
+    }
 }
@@ -0,0 +1,638 @@
+use std::collections::{HashMap, HashSet};
+use std::env;
+
+use chrono::{Duration, Utc};
+use mongodb::bson::spec::BinarySubtype;
+use mongodb::bson::{self, doc, Binary, Document};
+use mongodb::error::{Error, ErrorKind};
+use mongodb::options::{FindOptions, InsertManyOptions};
+use mongodb::Collection;
+use serde::{Deserialize, Serialize};
+
+use crate::errors::ServiceError;
+use crate::snapshot::{self, Directory, File, Metadata, Snapshot};
+
+const QUERY_CHUNK: usize = 1000;
+/// MongoDB rejects documents over 16 MiB; anything larger than this is split.
+const MAX_DOCUMENT_BYTES: usize = 15 * 1024 * 1024;
+const CHUNK_BYTES: usize = 8 * 1024 * 1024;
+const DUPLICATE_KEY: i32 = 11000;
+/// Time a snapshot may take between writing its nodes and its document.
+const GC_GRACE: Duration = Duration::hours(1);
+
+/// How the tree of a new snapshot is stored, selected with `STORAGE_LAYOUT`.
+#[derive(Debug, PartialEq)]
+pub enum Layout {
+    /// The whole tree is nested inside the snapshot document.
+    Document,
+    /// Every directory is stored once as a `TreeNode` keyed by its digest and
+    /// the snapshot only refers to its root node.
+    Tree,
+}
+
+/// A directory of the deduplicated layout. Nodes live in the snapshot
+/// collection next to the snapshots; unlike those they have no `path`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TreeNode {
+    #[serde(rename = "_id")]
+    pub id: String,
+    pub name: String,
+    pub size_kb: u64,
+    #[serde(default)]
+    pub meta: Metadata,
+    pub files: Vec<File>,
+    pub dirs: Vec<String>,
+    /// Number of chunks holding `files` when the node was too large to be
+    /// stored as one document.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chunks: Option<i32>,
+    /// Last time a snapshot using the node was stored. Unreferenced nodes
+    /// are only removed once this is older than `GC_GRACE`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub touched: Option<bson::DateTime>,
+}
+
+/// Part of the encoded file list of a chunked tree node.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Chunk {
+    #[serde(rename = "_id")]
+    pub id: String,
+    pub node: String,
+    pub n: i32,
+    pub data: Binary,
+}
+
+#[derive(Serialize, Deserialize)]
+struct FileList {
+    files: Vec<File>,
+}
+
+pub fn get_layout() -> Layout {
+    match env::var("STORAGE_LAYOUT") {
+        Ok(layout) if layout == "document" => Layout::Document,
+        _ => Layout::Tree,
+    }
+}
+
+/// Stores a snapshot with the configured layout. Tree nodes that already
+/// exist, because an earlier version shares the subtree, are not written
+/// again.
+pub async fn insert_snapshot(
+    collection: &Collection<Snapshot>,
+    mut snapshot: Snapshot,
+) -> Result<(), ServiceError> {
+    snapshot.tree = None;
+    if snapshot.file_count.is_none() && snapshot.unchanged_since.is_none() {
+        snapshot.set_counts();
+    }
+
+    // A tree too large for one document is split into nodes even when the
+    // document layout is configured.
+    let split = snapshot.unchanged_since.is_none()
+        && (get_layout() == Layout::Tree || encoded_size(&snapshot) > MAX_DOCUMENT_BYTES);
+
+    if split {
+        let (root, nodes) = split_tree(&snapshot);
+        insert_nodes(&collection.clone_with_type(), nodes).await?;
+
+        snapshot.tree = Some(root);
+        snapshot.files.clear();
+        snapshot.dirs.clear();
+    }
+
+    match collection.insert_one(snapshot, None).await {
+        Ok(_) => Ok(()),
+        Err(_) => Err(ServiceError::FailedToStoreSnapshot),
+    }
+}
+
+async fn insert_nodes(
+    nodes_collection: &Collection<TreeNode>,
+    nodes: Vec<TreeNode>,
+) -> Result<(), ServiceError> {
+    let mut nodes: HashMap<String, TreeNode> = nodes
+        .into_iter()
+        .map(|node| (node.id.clone(), node))
+        .collect();
+
+    // Existing nodes are touched before they are skipped, so that a
+    // concurrent sweep doesn't remove them before the snapshot refers to them.
+    let now = bson::DateTime::now();
+    let ids: Vec<String> = nodes.keys().cloned().collect();
+    for chunk in ids.chunks(QUERY_CHUNK) {
+        if nodes_collection
+            .update_many(
+                doc! {"_id": {"$in": chunk}},
+                doc! {"$set": {"touched": now}},
+                None,
+            )
+            .await
+            .is_err()
+        {
+            return Err(ServiceError::FailedToStoreSnapshot);
+        }
+
+        let options = FindOptions::builder().projection(doc! {"_id": 1}).build();
+        let mut cursor = match nodes_collection
+            .clone_with_type::<Document>()
+            .find(doc! {"_id": {"$in": chunk}}, options)
+            .await
+        {
+            Ok(cursor) => cursor,
+            Err(_) => return Err(ServiceError::FailedToFoundCollection),
+        };
+
+        while cursor
+            .advance()
+            .await
+            .or(Err(ServiceError::FailedToFoundCollection))?
+        {
+            if let Ok(id) = cursor.current().get_str("_id") {
+                nodes.remove(id);
+            }
+        }
+    }
+
+    let mut documents = Vec::new();
+    let mut chunks = Vec::new();
+    for mut node in nodes.into_values() {
+        node.touched = Some(now);
+        if encoded_size(&node) > MAX_DOCUMENT_BYTES {
+            let (node, node_chunks) = split_node(node, CHUNK_BYTES)?;
+            chunks.extend(node_chunks);
+            documents.push(node);
+        } else {
+            documents.push(node);
+        }
+    }
+
+    // Chunks go first, so a stored node is always complete.
+    insert_all(&nodes_collection.clone_with_type(), chunks).await?;
+    insert_all(nodes_collection, documents).await
+}
+
+/// Inserts `documents` in any order. A concurrent writer may have inserted
+/// the same node or chunk in the meantime, so duplicate keys are not errors.
+async fn insert_all<T: Serialize>(
+    collection: &Collection<T>,
+    documents: Vec<T>,
+) -> Result<(), ServiceError> {
+    if documents.is_empty() {
+        return Ok(());
+    }
+
+    let options = InsertManyOptions::builder().ordered(false).build();
+    match collection.insert_many(documents, options).await {
+        Ok(_) => Ok(()),
+        Err(error) if is_duplicate_key(&error) => Ok(()),
+        Err(_) => Err(ServiceError::FailedToStoreSnapshot),
+    }
+}
+
+fn is_duplicate_key(error: &Error) -> bool {
+    match error.kind.as_ref() {
+        ErrorKind::BulkWrite(failure) => {
+            failure.write_concern_error.is_none()
+                && failure
+                    .write_errors
+                    .as_ref()
+                    .is_some_and(|errors| errors.iter().all(|error| error.code == DUPLICATE_KEY))
+        }
+        _ => false,
+    }
+}
+
+fn encoded_size<T: Serialize>(value: &T) -> usize {
+    bson::to_vec(value).map_or(usize::MAX, |bytes| bytes.len())
+}
+
+/// Fills the tree of a snapshot stored with the `Tree` layout. Nodes already
+/// in `cache` are not read again, which makes loading many versions of the
+/// same path cheap.
+pub async fn load_snapshot(
+    collection: &Collection<Snapshot>,
+    mut snapshot: Snapshot,
+    cache: &mut HashMap<String, TreeNode>,
+) -> Result<Snapshot, ServiceError> {
+    let Some(root) = snapshot.tree.clone() else {
+        return Ok(snapshot);
+    };
+
+    let nodes_collection: Collection<TreeNode> = collection.clone_with_type();
+    let mut pending: Vec<String> = Vec::from([root.clone()]);
+    pending.retain(|id| !cache.contains_key(id));
+    let mut chunked = Vec::new();
+
+    while !pending.is_empty() {
+        let mut next = Vec::new();
+
+        for chunk in pending.chunks(QUERY_CHUNK) {
+            let mut cursor = match nodes_collection
+                .find(doc! {"_id": {"$in": chunk}}, None)
+                .await
+            {
+                Ok(cursor) => cursor,
+                Err(_) => return Err(ServiceError::FailedToFoundCollection),
+            };
+
+            while cursor
+                .advance()
+                .await
+                .or(Err(ServiceError::FailedToFoundCollection))?
+            {
+                let node: TreeNode = match cursor.deserialize_current() {
+                    Ok(node) => node,
+                    Err(_) => return Err(ServiceError::FailedToFoundCollection),
+                };
+                next.extend(
+                    node.dirs
+                        .iter()
+                        .filter(|id| !cache.contains_key(*id))
+                        .cloned(),
+                );
+                if node.chunks.is_some() {
+                    chunked.push(node.id.clone());
+                }
+                cache.insert(node.id.clone(), node);
+            }
+        }
+
+        pending = next;
+    }
+
+    load_chunks(collection, &chunked, cache).await?;
+
+    let (files, dirs) = build_tree(&root, cache).ok_or(ServiceError::FailedToFoundCollection)?;
+    snapshot.files = files;
+    snapshot.dirs = dirs;
+
+    Ok(snapshot)
+}
+
+async fn load_chunks(
+    collection: &Collection<Snapshot>,
+    ids: &[String],
+    cache: &mut HashMap<String, TreeNode>,
+) -> Result<(), ServiceError> {
+    let chunks_collection: Collection<Chunk> = collection.clone_with_type();
+
+    for batch in ids.chunks(QUERY_CHUNK) {
+        let mut cursor = match chunks_collection
+            .find(doc! {"node": {"$in": batch}}, None)
+            .await
+        {
+            Ok(cursor) => cursor,
+            Err(_) => return Err(ServiceError::FailedToFoundCollection),
+        };
+
+        let mut parts: HashMap<String, Vec<Chunk>> = HashMap::new();
+        while cursor
+            .advance()
+            .await
+            .or(Err(ServiceError::FailedToFoundCollection))?
+        {
+            let chunk: Chunk = match cursor.deserialize_current() {
+                Ok(chunk) => chunk,
+                Err(_) => return Err(ServiceError::FailedToFoundCollection),
+            };
+            parts.entry(chunk.node.clone()).or_default().push(chunk);
+        }
+
+        for id in batch {
+            let Some(node) = cache.get_mut(id) else {
+                continue;
+            };
+            let chunks = parts.remove(id).unwrap_or_default();
+            node.files = join_chunks(chunks, node.chunks.unwrap_or_default())
+                .ok_or(ServiceError::FailedToFoundCollection)?;
+            node.chunks = None;
+        }
+    }
+
+    Ok(())
+}
+
+/// Deletes the tree nodes no stored snapshot refers to any more, e.g. after
+/// versions were removed by retention. Nodes touched within `GC_GRACE` of
+/// the start of the sweep are kept, as a snapshot being stored may be about
+/// to refer to them.
+pub async fn remove_unreferenced_nodes(
+    collection: &Collection<Document>,
+) -> Result<usize, ServiceError> {
+    let cutoff = bson::DateTime::from_millis((Utc::now() - GC_GRACE).timestamp_millis());
+    let find = |filter: Document, projection: Document| async move {
+        let options = FindOptions::builder().projection(projection).build();
+        match collection.find(filter, options).await {
+            Ok(cursor) => Ok(cursor),
+            Err(_) => Err(ServiceError::FailedToFoundCollection),
+        }
+    };
+
+    let mut roots = Vec::new();
+    let mut cursor = find(doc! {"tree": {"$exists": true}}, doc! {"tree": 1}).await?;
+    while cursor
+        .advance()
+        .await
+        .or(Err(ServiceError::FailedToFoundCollection))?
+    {
+        if let Ok(root) = cursor.current().get_str("tree") {
+            roots.push(String::from(root));
+        }
+    }
+
+    let mut children: HashMap<String, Vec<String>> = HashMap::new();
+    let mut cursor = find(
+        doc! {"path": {"$exists": false}, "dirs": {"$exists": true}},
+        doc! {"_id": 1, "dirs": 1},
+    )
+    .await?;
+    while cursor
+        .advance()
+        .await
+        .or(Err(ServiceError::FailedToFoundCollection))?
+    {
+        let current = cursor.current();
+        let (Ok(id), Ok(dirs)) = (current.get_str("_id"), current.get_array("dirs")) else {
+            continue;
+        };
+        let dirs = dirs
+            .into_iter()
+            .filter_map(|dir| dir.ok()?.as_str().map(String::from))
+            .collect();
+        children.insert(String::from(id), dirs);
+    }
+
+    let mut reachable: HashSet<String> = HashSet::new();
+    while let Some(id) = roots.pop() {
+        if let Some(dirs) = children.get(&id) {
+            roots.extend(dirs.iter().filter(|dir| !reachable.contains(*dir)).cloned());
+        }
+        reachable.insert(id);
+    }
+
+    let unreachable: Vec<String> = children
+        .into_keys()
+        .filter(|id| !reachable.contains(id))
+        .collect();
+
+    let mut removed = 0;
+    for chunk in unreachable.chunks(QUERY_CHUNK) {
+        // Checked again by the delete itself, as nodes may be touched since.
+        let expired = doc! {"_id": {"$in": chunk}, "touched": {"$not": {"$gte": cutoff}}};
+        match collection.delete_many(expired, None).await {
+            Ok(result) => removed += result.deleted_count as usize,
+            Err(_) => return Err(ServiceError::FailedToFoundCollection),
+        }
+
+        let mut kept = HashSet::new();
+        let mut cursor = find(doc! {"_id": {"$in": chunk}}, doc! {"_id": 1}).await?;
+        while cursor
+            .advance()
+            .await
+            .or(Err(ServiceError::FailedToFoundCollection))?
+        {
+            if let Ok(id) = cursor.current().get_str("_id") {
+                kept.insert(String::from(id));
+            }
+        }
+        let deleted: Vec<&String> = chunk.iter().filter(|id| !kept.contains(*id)).collect();
+        if collection
+            .delete_many(doc! {"node": {"$in": deleted}}, None)
+            .await
+            .is_err()
+        {
+            return Err(ServiceError::FailedToFoundCollection);
+        }
+    }
+
+    Ok(removed)
+}
+
+/// Splits the tree of a snapshot into one node per directory plus a root
+/// node. Identical directories collapse into a single node. Returns the id
+/// of the root node, which equals the root digest of the snapshot.
+pub fn split_tree(snapshot: &Snapshot) -> (String, Vec<TreeNode>) {
+    let mut nodes = Vec::new();
+    let children: Vec<String> = snapshot
+        .dirs
+        .iter()
+        .map(|dir| split_directory(dir, &mut nodes))
+        .collect();
+
+    let root = snapshot::listing_digest(&snapshot.files, &children);
+    nodes.push(TreeNode {
+        id: root.clone(),
+        name: String::new(),
+        size_kb: snapshot.size_kb,
+        meta: Metadata::default(),
+        files: snapshot.files.clone(),
+        dirs: children,
+        chunks: None,
+        touched: None,
+    });
+
+    let mut seen = HashSet::new();
+    nodes.retain(|node| seen.insert(node.id.clone()));
+
+    (root, nodes)
+}
+
+fn split_directory(dir: &Directory, nodes: &mut Vec<TreeNode>) -> String {
+    let children: Vec<String> = dir
+        .dirs
+        .iter()
+        .map(|child| split_directory(child, nodes))
+        .collect();
+
+    let id = dir.digest_with(&snapshot::listing_digest(&dir.files, &children));
+    nodes.push(TreeNode {
+        id: id.clone(),
+        name: dir.name.clone(),
+        size_kb: dir.size_kb,
+        meta: dir.meta.clone(),
+        files: dir.files.clone(),
+        dirs: children,
+        chunks: None,
+        touched: None,
+    });
+
+    id
+}
+
+/// Moves the file list of `node` into chunks of at most `chunk_bytes`. The
+/// subdirectories stay in the node, as loading and the sweep walk them, so a
+/// node still too large without its files is rejected.
+pub fn split_node(
+    mut node: TreeNode,
+    chunk_bytes: usize,
+) -> Result<(TreeNode, Vec<Chunk>), ServiceError> {
+    let files = FileList {
+        files: std::mem::take(&mut node.files),
+    };
+    if encoded_size(&node) > MAX_DOCUMENT_BYTES {
+        return Err(ServiceError::DirectoryTooLarge);
+    }
+    let bytes = bson::to_vec(&files).or(Err(ServiceError::FailedToStoreSnapshot))?;
+
+    let chunks: Vec<Chunk> = bytes
+        .chunks(chunk_bytes)
+        .enumerate()
+        .map(|(n, data)| Chunk {
+            id: format!("{}.{n}", node.id),
+            node: node.id.clone(),
+            n: n as i32,
+            data: Binary {
+                subtype: BinarySubtype::Generic,
+                bytes: data.to_vec(),
+            },
+        })
+        .collect();
+    node.chunks = Some(chunks.len() as i32);
+
+    Ok((node, chunks))
+}
+
+/// Restores the file list from the chunks of a node. Returns `None` when
+/// chunks are missing or do not decode.
+pub fn join_chunks(mut chunks: Vec<Chunk>, count: i32) -> Option<Vec<File>> {
+    chunks.sort_by_key(|chunk| chunk.n);
+    let complete = chunks.len() == count as usize
+        && chunks
+            .iter()
+            .enumerate()
+            .all(|(n, chunk)| chunk.n == n as i32);
+    if !complete {
+        return None;
+    }
+
+    let bytes: Vec<u8> = chunks
+        .into_iter()
+        .flat_map(|chunk| chunk.data.bytes)
+        .collect();
+    let files: FileList = bson::from_slice(&bytes).ok()?;
+
+    Some(files.files)
+}
+
+/// Rebuilds the content of the root node `root` from loaded nodes. Returns
+/// `None` when a node is missing.
+pub fn build_tree(
+    root: &str,
+    nodes: &HashMap<String, TreeNode>,
+) -> Option<(Vec<File>, Vec<Directory>)> {
+    let node = nodes.get(root)?;
+    let dirs = node
+        .dirs
+        .iter()
+        .map(|id| build_directory(id, nodes))
+        .collect::<Option<Vec<Directory>>>()?;
+
+    Some((node.files.clone(), dirs))
+}
+
+fn build_directory(id: &str, nodes: &HashMap<String, TreeNode>) -> Option<Directory> {
+    let node = nodes.get(id)?;
+    let (files, dirs) = build_tree(id, nodes)?;
+
+    Some(Directory {
+        name: node.name.clone(),
+        size_kb: node.size_kb,
+        files,
+        dirs,
+        meta: node.meta.clone(),
+        hash: Some(node.id.clone()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::snapshot::fixtures::{self, create_file};
+    use crate::snapshot::{Directory, Snapshot};
+
+    use super::{build_tree, join_chunks, split_node, split_tree};
+
+    fn create_dir(name: &str, dirs: Vec<Directory>) -> Directory {
+        fixtures::create_dir(name, Vec::from([create_file("file", 1024)]), dirs)
+    }
+
+    fn create_snapshot() -> Snapshot {
+        fixtures::create_snapshot(
+            1,
+            "test",
+            Vec::new(),
+            Vec::from([
+                create_dir("a", Vec::from([create_dir("same", Vec::new())])),
+                create_dir("b", Vec::from([create_dir("same", Vec::new())])),
+            ]),
+        )
+    }
+
+    #[test]
+    fn test_split_tree_shares_identical_directories() {
+        let snapshot = create_snapshot();
+        let (root, nodes) = split_tree(&snapshot);
+
+        assert_eq!(root, snapshot.compute_digest());
+        assert_eq!(nodes.len(), 4);
+    }
+
+    #[test]
+    fn test_split_and_build_round_trip() {
+        let snapshot = create_snapshot();
+        let (root, nodes) = split_tree(&snapshot);
+        let nodes = nodes
+            .into_iter()
+            .map(|node| (node.id.clone(), node))
+            .collect();
+
+        let (files, dirs) = build_tree(&root, &nodes).unwrap();
+
+        assert_eq!(files, snapshot.files);
+        assert_eq!(dirs[0].name, "a");
+        assert_eq!(dirs[0].hash, Some(snapshot.dirs[0].digest()));
+        assert_eq!(dirs[1].dirs[0].hash, dirs[0].dirs[0].hash);
+        assert!(build_tree("missing", &HashMap::new()).is_none());
+    }
+
+    #[test]
+    fn test_split_and_join_chunks() {
+        let (root, nodes) = split_tree(&create_snapshot());
+        let node = nodes.into_iter().find(|node| node.id == root).unwrap();
+        let node = super::TreeNode {
+            files: (0..100)
+                .map(|n| create_file(&format!("file-{n}"), n))
+                .collect(),
+            ..node
+        };
+        let files = node.files.clone();
+
+        let (node, mut chunks) = split_node(node, 512).unwrap();
+
+        assert!(node.files.is_empty());
+        assert_eq!(node.chunks, Some(chunks.len() as i32));
+        assert!(chunks.len() > 1);
+
+        chunks.reverse();
+        assert_eq!(
+            join_chunks(chunks.clone(), node.chunks.unwrap()),
+            Some(files)
+        );
+
+        chunks.pop();
+        assert_eq!(join_chunks(chunks, node.chunks.unwrap()), None);
+    }
+
+    #[test]
+    fn test_split_node_rejects_too_many_directories() {
+        let (root, nodes) = split_tree(&create_snapshot());
+        let node = nodes.into_iter().find(|node| node.id == root).unwrap();
+        let node = super::TreeNode {
+            dirs: (0..300_000).map(|n| format!("{n:064}")).collect(),
+            ..node
+        };
+
+        assert!(split_node(node, 512).is_err());
+    }
+}